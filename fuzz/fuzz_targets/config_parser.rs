@@ -0,0 +1,8 @@
+#![no_main]
+
+use duckwind::fuzzing::{ConfigFragment, fuzz_config_parser};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|config: ConfigFragment| {
+    fuzz_config_parser(&config.0);
+});
@@ -0,0 +1,12 @@
+#![no_main]
+
+use std::sync::LazyLock;
+
+use duckwind::{EmitEnv, fuzzing::fuzz_emit};
+use libfuzzer_sys::fuzz_target;
+
+static ENV: LazyLock<EmitEnv> = LazyLock::new(EmitEnv::new_with_default_config);
+
+fuzz_target!(|src: &str| {
+    fuzz_emit(&ENV, src);
+});
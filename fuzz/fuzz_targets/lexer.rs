@@ -0,0 +1,8 @@
+#![no_main]
+
+use duckwind::fuzzing::{Candidate, fuzz_lexer};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|candidate: Candidate| {
+    fuzz_lexer(&candidate.0);
+});
@@ -0,0 +1,8 @@
+#![no_main]
+
+use duckwind::fuzzing::{Candidate, fuzz_parser};
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|candidate: Candidate| {
+    fuzz_parser(&candidate.0);
+});
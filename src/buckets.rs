@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+
+use crate::{CompileOptions, CssDef, EmitEnv, ResolvedCandidate, shadow_stylesheet};
+
+/// parse calls through `EmitEnv::in_bucket`, the rules they reference are remembered under the
+/// bucket's name and end up in its stylesheet, see `EmitEnv::to_bucket_stylesheet`
+pub struct Bucket<'a> {
+    env: &'a mut EmitEnv,
+    name: String,
+}
+
+impl Bucket<'_> {
+    pub fn parse_full_string(&mut self, options: &CompileOptions, txt: &str) {
+        let (_, keys) = self.env.scan_references(options, txt, None);
+        self.env
+            .buckets
+            .entry(self.name.clone())
+            .or_default()
+            .extend(keys);
+    }
+
+    /// like `EmitEnv::compile_classes`, returns the newly generated rules
+    pub fn compile_classes<'c>(
+        &mut self,
+        options: &CompileOptions,
+        classes: impl IntoIterator<Item = &'c str>,
+    ) -> Vec<CssDef> {
+        let mut generated = Vec::new();
+        for class in classes {
+            let Some((resolved, _)) = self.env.resolve_candidate(options, class) else {
+                continue;
+            };
+            let class_name = match resolved {
+                ResolvedCandidate::Generated(css_defs) => {
+                    let class_name = css_defs[0].class_name.clone();
+                    generated.extend(css_defs);
+                    class_name
+                }
+                ResolvedCandidate::AlreadyGenerated(class_name) => class_name,
+            };
+            self.env
+                .buckets
+                .entry(self.name.clone())
+                .or_default()
+                .insert(EmitEnv::generated_key(options, &class_name));
+        }
+        generated
+    }
+}
+
+impl EmitEnv {
+    /// tags the rules parsed through the returned bucket with `name`, so per page or per route
+    /// stylesheets can be emitted from one env
+    pub fn in_bucket(&mut self, name: impl Into<String>) -> Bucket<'_> {
+        Bucket {
+            env: self,
+            name: name.into(),
+        }
+    }
+
+    pub fn bucket_names(&self) -> impl Iterator<Item = &str> {
+        self.buckets.keys().map(String::as_str)
+    }
+
+    /// preflight, theme vars, keyframes and `@property` rules without any utilities, shared by
+    /// the stylesheets of every bucket and source
+    pub fn to_shared_stylesheet(&self, with_preflight: bool) -> String {
+        let rules = self.rules_to_css(self.defs.iter(), true);
+        let mut result = self.stylesheet_head(with_preflight, &rules);
+        result.push_str(&self.rules_to_css(std::iter::empty(), true));
+
+        if self.options.shadow_dom {
+            result = shadow_stylesheet(&result);
+        }
+        self.finish_stylesheet(result)
+    }
+
+    /// only the rules referenced through `in_bucket(name)`, meant to be loaded after
+    /// `to_shared_stylesheet`. rules are in every bucket that references them
+    pub fn to_bucket_stylesheet(&self, name: &str) -> String {
+        self.buckets
+            .get(name)
+            .map_or_else(String::new, |keys| self.keys_to_css(keys))
+    }
+
+    /// like `to_bucket_stylesheet` for a document scanned with `parse_source` (or
+    /// `Compiler::update`), only the rules it currently references
+    pub fn to_source_stylesheet(&self, file_id: &str) -> String {
+        self.source_refs
+            .keys(file_id)
+            .map_or_else(String::new, |keys| self.keys_to_css(keys))
+    }
+
+    fn keys_to_css(&self, keys: &HashSet<String>) -> String {
+        self.finish_stylesheet(
+            self.rules_to_css(
+                self.defs
+                    .iter()
+                    .filter(|css_def| keys.contains(&Self::def_key(css_def))),
+                false,
+            ),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv};
+
+    #[test]
+    fn test_buckets() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions::default();
+        env.in_bucket("admin")
+            .parse_full_string(&options, "<div class=\"p-4 flex\">");
+        env.in_bucket("shop")
+            .parse_full_string(&options, "<div class=\"p-4 grid\">");
+        // already generated for admin, but shop references it too
+        assert!(
+            env.in_bucket("shop")
+                .compile_classes(&options, ["flex"])
+                .is_empty()
+        );
+        assert_eq!(env.bucket_names().collect::<Vec<_>>(), ["admin", "shop"]);
+
+        let admin = env.to_bucket_stylesheet("admin");
+        let shop = env.to_bucket_stylesheet("shop");
+        assert!(admin.contains(".p-4{") && admin.contains(".flex{") && !admin.contains(".grid{"));
+        assert!(shop.contains(".p-4{") && shop.contains(".grid{") && shop.contains(".flex{"));
+        assert!(!admin.contains(":root"));
+
+        let shared = env.to_shared_stylesheet(false);
+        assert!(shared.contains(":root {\n") && !shared.contains(".p-4{"));
+        assert_eq!(env.to_bucket_stylesheet("missing"), "");
+    }
+
+    #[test]
+    fn test_source_stylesheets() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions::default();
+        env.parse_source(&options, "header", "<div class=\"p-4 flex\">");
+        env.parse_source(&options, "footer", "<div class=\"p-4 grid\">");
+
+        let header = env.to_source_stylesheet("header");
+        assert!(
+            header.contains(".p-4{") && header.contains(".flex{") && !header.contains(".grid{")
+        );
+        assert!(!header.contains(":root"));
+
+        // rerendered without flex
+        env.parse_source(&options, "header", "<div class=\"p-4\">");
+        assert!(!env.to_source_stylesheet("header").contains(".flex{"));
+        assert!(env.to_source_stylesheet("footer").contains(".p-4{"));
+        env.retract_source("footer");
+        assert_eq!(env.to_source_stylesheet("footer"), "");
+    }
+}
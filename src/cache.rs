@@ -0,0 +1,74 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::Hash,
+};
+
+/// set of keys that forgets the least recently used one once it's full
+#[derive(Debug, Clone)]
+pub struct LruSet<K> {
+    capacity: usize,
+    tick: u64,
+    last_used: HashMap<K, u64>,
+    by_tick: BTreeMap<u64, K>,
+}
+
+impl<K: Hash + Eq + Clone> LruSet<K> {
+    pub fn new(capacity: usize) -> Self {
+        LruSet {
+            capacity,
+            tick: 0,
+            last_used: HashMap::new(),
+            by_tick: BTreeMap::new(),
+        }
+    }
+
+    fn touch(&mut self, key: K) {
+        self.tick += 1;
+        if let Some(old_tick) = self.last_used.insert(key.clone(), self.tick) {
+            self.by_tick.remove(&old_tick);
+        }
+        self.by_tick.insert(self.tick, key);
+    }
+
+    /// also marks the key as recently used
+    pub fn contains(&mut self, key: &K) -> bool {
+        if self.last_used.contains_key(key) {
+            self.touch(key.clone());
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn insert(&mut self, key: K) {
+        self.touch(key);
+        while self.last_used.len() > self.capacity {
+            let Some((_, oldest)) = self.by_tick.pop_first() else {
+                break;
+            };
+            self.last_used.remove(&oldest);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.last_used.clear();
+        self.by_tick.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::cache::LruSet;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut set = LruSet::new(2);
+        set.insert("a");
+        set.insert("b");
+        assert!(set.contains(&"a"));
+        set.insert("c");
+        assert!(set.contains(&"a"));
+        assert!(!set.contains(&"b"));
+        assert!(set.contains(&"c"));
+    }
+}
@@ -0,0 +1,268 @@
+use crate::{config_css::Theme, is_valid_css_char};
+
+/// a number and its unit, `%` counts as a unit and unitless numbers have an empty one
+#[derive(Debug, Clone, PartialEq)]
+struct Quantity {
+    value: f64,
+    unit: String,
+}
+
+/// recursive descent over a calc expression, `None` as soon as something isn't a constant
+struct Evaluator<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl Evaluator<'_> {
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while let Some(c) = self.src[self.pos..].chars().next()
+            && c.is_whitespace()
+        {
+            self.pos += c.len_utf8();
+        }
+        self.pos > start
+    }
+
+    fn eat(&mut self, s: &str) -> bool {
+        let matches = self.src[self.pos..].starts_with(s);
+        if matches {
+            self.pos += s.len();
+        }
+        matches
+    }
+
+    /// `a + b`, `-` and `+` need whitespace around them like in css
+    fn sum(&mut self) -> Option<Quantity> {
+        let mut res = self.product()?;
+        loop {
+            let before = self.pos;
+            if !self.skip_whitespace() {
+                return Some(res);
+            }
+            let sign = if self.eat("+ ") {
+                1.0
+            } else if self.eat("- ") {
+                -1.0
+            } else {
+                self.pos = before;
+                return Some(res);
+            };
+            let rhs = self.product()?;
+            if rhs.unit != res.unit {
+                return None;
+            }
+            res.value += sign * rhs.value;
+        }
+    }
+
+    fn product(&mut self) -> Option<Quantity> {
+        let mut res = self.factor()?;
+        loop {
+            let before = self.pos;
+            self.skip_whitespace();
+            if self.eat("*") {
+                let rhs = self.factor()?;
+                res = match (res.unit.is_empty(), rhs.unit.is_empty()) {
+                    (_, true) => Quantity {
+                        value: res.value * rhs.value,
+                        unit: res.unit,
+                    },
+                    (true, false) => Quantity {
+                        value: res.value * rhs.value,
+                        unit: rhs.unit,
+                    },
+                    (false, false) => return None,
+                };
+            } else if self.eat("/") {
+                let rhs = self.factor()?;
+                if !rhs.unit.is_empty() || rhs.value == 0.0 {
+                    return None;
+                }
+                res.value /= rhs.value;
+            } else {
+                self.pos = before;
+                return Some(res);
+            }
+        }
+    }
+
+    fn factor(&mut self) -> Option<Quantity> {
+        self.skip_whitespace();
+        if self.eat("calc(") || self.eat("(") {
+            let res = self.sum()?;
+            self.skip_whitespace();
+            return self.eat(")").then_some(res);
+        }
+
+        let start = self.pos;
+        self.eat("-");
+        let number_len = self.src[self.pos..]
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(self.src.len() - self.pos);
+        self.pos += number_len;
+        let value = self.src[start..self.pos].parse::<f64>().ok()?;
+
+        let unit_len = self.src[self.pos..]
+            .find(|c: char| !(c.is_ascii_alphabetic() || c == '%'))
+            .unwrap_or(self.src.len() - self.pos);
+        let unit = self.src[self.pos..self.pos + unit_len].to_string();
+        self.pos += unit_len;
+        Some(Quantity { value, unit })
+    }
+}
+
+fn evaluate(expression: &str) -> Option<Quantity> {
+    let mut evaluator = Evaluator {
+        src: expression,
+        pos: 0,
+    };
+    let res = evaluator.sum()?;
+    evaluator.skip_whitespace();
+    (evaluator.pos == expression.len()).then_some(res)
+}
+
+/// `0.3750` -> `0.375`, at most 4 decimals
+fn format_number(value: f64) -> String {
+    let formatted = format!("{value:.4}");
+    let trimmed = formatted.trim_end_matches('0').trim_end_matches('.');
+    match trimmed {
+        "-0" => "0".to_string(),
+        trimmed => trimmed.to_string(),
+    }
+}
+
+/// the offset of the first `calc(` in `css` that isn't in a quoted string like
+/// `content: 'calc(1 + 1)'`
+fn find_calc(css: &str) -> Option<usize> {
+    let mut quote = None;
+    let mut escaped = false;
+    for (i, c) in css.char_indices() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match (quote, c) {
+            (_, '\\') => escaped = true,
+            (Some(open), _) if c == open => quote = None,
+            (Some(_), _) => {}
+            (None, '\'' | '"') => quote = Some(c),
+            (None, _) if css[i..].starts_with("calc(") => return Some(i),
+            (None, _) => {}
+        }
+    }
+    None
+}
+
+/// replaces every `calc()` that only contains constants with its value, theme vars with a
+/// literal value count as constants: `calc(var(--spacing) * 4)` -> `1rem`. calcs that mix
+/// units, reference vars that aren't known or that a named or variant theme overrides (see
+/// `Theme::dynamic_vars`) and calcs in strings stay as they are
+pub fn fold_calc(css: &str, theme: &Theme) -> String {
+    let mut res = String::with_capacity(css.len());
+    let mut rest = css;
+    while let Some(idx) = find_calc(rest) {
+        let is_function_start = rest[..idx]
+            .chars()
+            .next_back()
+            .is_none_or(|c| !is_valid_css_char(c));
+        let args_start = idx + "calc(".len();
+        let mut depth = 1;
+        let args_end = rest[args_start..].char_indices().find_map(|(i, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth -= 1,
+                _ => {}
+            }
+            (depth == 0).then_some(args_start + i)
+        });
+        let Some(args_end) = args_end.filter(|_| is_function_start) else {
+            res.push_str(&rest[..args_start]);
+            rest = &rest[args_start..];
+            continue;
+        };
+
+        res.push_str(&rest[..idx]);
+        match evaluate(&theme.inline_vars(&rest[args_start..args_end])) {
+            Some(quantity) => {
+                res.push_str(&format_number(quantity.value));
+                res.push_str(&quantity.unit);
+            }
+            None => res.push_str(&rest[idx..=args_end]),
+        }
+        rest = &rest[args_end + 1..];
+    }
+    res.push_str(rest);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv, calc::fold_calc, config_css::Theme};
+
+    #[test]
+    fn test_fold_calc() {
+        let mut theme = Theme::default();
+        theme
+            .vars
+            .insert("spacing".to_string(), "0.25rem".to_string());
+        theme.vars.insert("accent".to_string(), "red".to_string());
+
+        assert_eq!(
+            fold_calc("padding: calc(var(--spacing) * 4);", &theme),
+            "padding: 1rem;"
+        );
+        assert_eq!(
+            fold_calc(
+                "margin: calc(var(--spacing) * -1.5) calc(2 * (1px + 3px));",
+                &theme
+            ),
+            "margin: -0.375rem 8px;"
+        );
+        assert_eq!(
+            fold_calc("top: calc(100% / 3 - 0%);", &theme),
+            "top: 33.3333%;"
+        );
+        // mixed units, unknown vars and divisions by zero can't be folded
+        for kept in [
+            "width: calc(100% - 1rem);",
+            "width: calc(var(--tw-x) * 2);",
+            "width: calc(var(--accent) * 2);",
+            "width: calc(1px / 0);",
+            "width: -webkit-calc(1px * 2);",
+            "content: 'calc(1px * 2)';",
+            "content: \"a\\\" calc(1px * 2)\";",
+        ] {
+            assert_eq!(fold_calc(kept, &theme), kept);
+        }
+        assert_eq!(
+            fold_calc(".a\\'{\ntop: calc(1px * 2);", &theme),
+            ".a\\'{\ntop: 2px;"
+        );
+    }
+
+    #[test]
+    fn test_fold_calc_stylesheet() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.options.fold_calc = true;
+        env.compile_classes(&CompileOptions::default(), ["m-2", "content-['calc(1+1)']"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("margin: 0.5rem;"), "{css}");
+        assert!(css.contains("content: 'calc(1 + 1)';"), "{css}");
+
+        // a var a theme overrides is referenced, not folded
+        for config in [
+            "@theme dark {\n--spacing: 0.5rem;\n}",
+            "@theme name(roomy) {\n--spacing: 0.5rem;\n}",
+        ] {
+            let mut env = EmitEnv::new_with_default_config();
+            env.options.no_theme_vars = true;
+            env.options.fold_calc = true;
+            env.try_load_config(config).unwrap();
+            env.compile_classes(&CompileOptions::default(), ["m-2"]);
+            let css = env.to_css_stylesheet(false);
+            assert!(css.contains("margin: calc(var(--spacing) * 2);"), "{css}");
+        }
+    }
+}
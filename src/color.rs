@@ -0,0 +1,244 @@
+use crate::{declaration_ranges, is_valid_css_char};
+
+/// the color functions there are sRGB fallbacks for
+const MODERN_COLOR_FUNCTIONS: [&str; 4] = ["oklch", "oklab", "lch", "lab"];
+
+/// a channel like `63.7%`, `0.2` or `none`, percentages are of `percent_of`
+fn channel(s: &str, percent_of: f64) -> Option<f64> {
+    if s == "none" {
+        return Some(0.0);
+    }
+    match s.strip_suffix('%') {
+        Some(percent) => Some(percent.parse::<f64>().ok()? / 100.0 * percent_of),
+        None => s.parse().ok(),
+    }
+}
+
+/// a hue in degrees, `25.3`, `25.3deg` or `0.1turn`
+fn hue(s: &str) -> Option<f64> {
+    if let Some(turns) = s.strip_suffix("turn") {
+        return Some(turns.parse::<f64>().ok()? * 360.0);
+    }
+    channel(s.strip_suffix("deg").unwrap_or(s), 1.0)
+}
+
+/// the three channels and the alpha of `l c h / a`, `None` for anything with `var()`, `calc()`
+/// or relative colors in it
+fn channels(args: &str) -> Option<([&str; 3], f64)> {
+    let (channels, alpha) = match args.split_once('/') {
+        Some((channels, alpha)) => (channels, channel(alpha.trim(), 1.0)?),
+        None => (args, 1.0),
+    };
+    let channels = channels.split_whitespace().collect::<Vec<_>>();
+    Some((channels.try_into().ok()?, alpha))
+}
+
+/// `(l, a, b)` of oklab -> linear srgb
+fn oklab_to_linear_srgb(l: f64, a: f64, b: f64) -> [f64; 3] {
+    let l_ = (l + 0.3963377774 * a + 0.2158037573 * b).powi(3);
+    let m_ = (l - 0.1055613458 * a - 0.0638541728 * b).powi(3);
+    let s_ = (l - 0.0894841775 * a - 1.2914855480 * b).powi(3);
+    [
+        4.0767416621 * l_ - 3.3077115913 * m_ + 0.2309699292 * s_,
+        -1.2684380046 * l_ + 2.6097574011 * m_ - 0.3413193965 * s_,
+        -0.0041960863 * l_ - 0.7034186147 * m_ + 1.7076147010 * s_,
+    ]
+}
+
+/// `(l, a, b)` of cie lab with a d50 white point -> linear srgb
+fn lab_to_linear_srgb(l: f64, a: f64, b: f64) -> [f64; 3] {
+    const EPSILON: f64 = 216.0 / 24389.0;
+    const KAPPA: f64 = 24389.0 / 27.0;
+    const WHITE: [f64; 3] = [0.3457 / 0.3585, 1.0, (1.0 - 0.3457 - 0.3585) / 0.3585];
+    // d50 xyz -> d65 xyz with bradford and d65 xyz -> linear srgb in one
+    const TO_SRGB: [[f64; 3]; 3] = [
+        [3.1341359569958707, -1.6173863321612538, -0.4906619460083532],
+        [-0.978795502912089, 1.916254567259524, 0.03344273116131949],
+        [0.07195537988411677, -0.2289768264158322, 1.405386058324125],
+    ];
+
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let f_inverse = |f: f64| {
+        if f.powi(3) > EPSILON {
+            f.powi(3)
+        } else {
+            (116.0 * f - 16.0) / KAPPA
+        }
+    };
+    let y = if l > KAPPA * EPSILON {
+        fy.powi(3)
+    } else {
+        l / KAPPA
+    };
+    let xyz = [f_inverse(fx) * WHITE[0], y, f_inverse(fz) * WHITE[2]];
+    TO_SRGB.map(|row| row.iter().zip(xyz).map(|(m, c)| m * c).sum())
+}
+
+/// the srgb hex like `#fb2c36` of the arguments of a `oklch()`, `oklab()`, `lch()` or `lab()`.
+/// colors outside of srgb are clipped
+fn to_srgb_hex(function: &str, args: &str) -> Option<String> {
+    let ([first, second, third], alpha) = channels(args)?;
+    let polar = |lightness: f64, chroma: f64, hue: f64| {
+        let (sin, cos) = hue.to_radians().sin_cos();
+        (lightness, chroma * cos, chroma * sin)
+    };
+    let linear = match function {
+        "oklch" => {
+            let (l, a, b) = polar(channel(first, 1.0)?, channel(second, 0.4)?, hue(third)?);
+            oklab_to_linear_srgb(l, a, b)
+        }
+        "oklab" => oklab_to_linear_srgb(
+            channel(first, 1.0)?,
+            channel(second, 0.4)?,
+            channel(third, 0.4)?,
+        ),
+        "lch" => {
+            let (l, a, b) = polar(channel(first, 100.0)?, channel(second, 150.0)?, hue(third)?);
+            lab_to_linear_srgb(l, a, b)
+        }
+        "lab" => lab_to_linear_srgb(
+            channel(first, 100.0)?,
+            channel(second, 125.0)?,
+            channel(third, 125.0)?,
+        ),
+        _ => return None,
+    };
+
+    let byte = |c: f64| (c.clamp(0.0, 1.0) * 255.0).round() as u8;
+    let mut res = String::from("#");
+    for c in linear {
+        let gamma = if c.abs() <= 0.0031308 {
+            12.92 * c
+        } else {
+            c.signum() * (1.055 * c.abs().powf(1.0 / 2.4) - 0.055)
+        };
+        res.push_str(&format!("{:02x}", byte(gamma)));
+    }
+    if alpha < 1.0 {
+        res.push_str(&format!("{:02x}", byte(alpha)));
+    }
+    Some(res)
+}
+
+/// `value` with its modern color functions in srgb, `None` if it has none or one of them can't
+/// be converted
+fn srgb_value(value: &str) -> Option<String> {
+    let mut res = String::with_capacity(value.len());
+    let mut converted = false;
+    let mut rest = value;
+    'outer: while !rest.is_empty() {
+        let after_ident = res
+            .chars()
+            .next_back()
+            .is_none_or(|c| !is_valid_css_char(c));
+        for function in MODERN_COLOR_FUNCTIONS {
+            let Some(args) = rest
+                .strip_prefix(function)
+                .and_then(|rest| rest.strip_prefix('('))
+                .filter(|_| after_ident)
+            else {
+                continue;
+            };
+            let end = args.find(')')?;
+            res.push_str(&to_srgb_hex(function, &args[..end])?);
+            rest = &args[end + 1..];
+            converted = true;
+            continue 'outer;
+        }
+        let c = rest.chars().next().expect("not empty");
+        res.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    converted.then_some(res)
+}
+
+/// adds an srgb fallback in front of every declaration of a rule body that uses `oklch()`,
+/// `oklab()`, `lch()` or `lab()`, for browsers that drop the modern one:
+/// `color: oklch(63.7% 0.237 25.331)` -> `color: #fb2c36; color: oklch(...)`. custom
+/// properties are left alone, their value isn't checked until it's used
+pub fn srgb_fallbacks(body: &str) -> String {
+    let mut res = body.to_string();
+    for range in declaration_ranges(body).into_iter().rev() {
+        let declaration = &body[range.clone()];
+        let Some((name, value)) = declaration.split_once(':') else {
+            continue;
+        };
+        if name.trim().starts_with("--") {
+            continue;
+        }
+        let Some(fallback) = srgb_value(value) else {
+            continue;
+        };
+        let line_start = body[..range.start].rfind('\n').map_or(0, |i| i + 1);
+        let indent = &body[line_start..range.start];
+        let separator = if indent.trim().is_empty() {
+            format!("\n{indent}")
+        } else {
+            " ".to_string()
+        };
+        res.insert_str(range.start, &format!("{name}:{fallback};{separator}"));
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CompileOptions, EmitEnv,
+        color::{srgb_fallbacks, to_srgb_hex},
+    };
+
+    #[test]
+    fn test_srgb_fallbacks() {
+        assert_eq!(
+            to_srgb_hex("oklch", "63.7% 0.237 25.331").as_deref(),
+            Some("#fb2c36")
+        );
+        assert_eq!(to_srgb_hex("oklch", "1 0 0").as_deref(), Some("#ffffff"));
+        assert_eq!(
+            to_srgb_hex("oklab", "0 0 0 / 50%").as_deref(),
+            Some("#00000080")
+        );
+        assert_eq!(to_srgb_hex("lab", "100% 0 0").as_deref(), Some("#ffffff"));
+        assert_eq!(to_srgb_hex("lch", "50% 0 0").as_deref(), Some("#777777"));
+        assert_eq!(to_srgb_hex("oklch", "var(--l) 0 0"), None);
+
+        assert_eq!(
+            srgb_fallbacks(
+                "    color: oklch(1 0 0);\n    --x: lab(0 0 0);\n    border: 1px solid lab(0 0 0) !important;\n    top: 0;"
+            ),
+            "    color: #ffffff;\n    color: oklch(1 0 0);\n    --x: lab(0 0 0);\n    border: 1px solid #000000 !important;\n    border: 1px solid lab(0 0 0) !important;\n    top: 0;"
+        );
+        // `color-lab(` isn't `lab(`, and values that can't be worked out don't get a fallback
+        assert_eq!(
+            srgb_fallbacks("a: color-lab(1 0 0); b: oklch(from red l c h);"),
+            "a: color-lab(1 0 0); b: oklch(from red l c h);"
+        );
+    }
+
+    #[test]
+    fn test_color_fallback_stylesheet() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.options.color_fallbacks = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["bg-red-500", "hover:text-white"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(
+            css.contains(
+                "    background-color: #fb2c36;\n    background-color: oklch(63.7% 0.237 25.331);"
+            ),
+            "{css}"
+        );
+
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.options.targets = Some("safari 15".parse().unwrap());
+        env.compile_classes(&CompileOptions::default(), ["bg-red-500"]);
+        assert!(env.to_css_stylesheet(false).contains("#fb2c36"));
+    }
+}
@@ -0,0 +1,230 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{CompileOptions, CssDef, EmitEnv, Location, report::LineIndex};
+
+/// what changed in the stylesheet after `Compiler::update`
+#[derive(Debug, Clone, Default)]
+pub struct Delta {
+    /// rules no file referenced before, in the order they were generated
+    pub added_rules: Vec<CssDef>,
+    /// rules no file references anymore, they were dropped from the env
+    pub removed_rules: Vec<CssDef>,
+}
+
+impl Delta {
+    pub fn is_empty(&self) -> bool {
+        self.added_rules.is_empty() && self.removed_rules.is_empty()
+    }
+}
+
+/// what became of a candidate, see `Compiler::set_observer`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CandidateOutcome {
+    /// a new rule was generated for it
+    Generated,
+    /// its rule was generated before and was skipped
+    AlreadyGenerated,
+    /// a word that looks like a class but doesn't resolve to a rule
+    Unresolved,
+}
+
+/// a candidate found while scanning a file in `Compiler::update`
+#[derive(Debug, Clone, PartialEq)]
+pub struct CandidateEvent<'a> {
+    pub candidate: &'a str,
+    pub location: Location,
+    pub outcome: CandidateOutcome,
+}
+
+type Observer = Box<dyn Fn(&CandidateEvent)>;
+
+/// called with the offset, text and outcome of every candidate, see `EmitEnv::scan_references`
+pub(crate) type OnCandidate<'a> = &'a mut dyn FnMut(usize, &str, CandidateOutcome);
+
+/// reference counts of the rules generated for the sources scanned with
+/// `EmitEnv::parse_source`, keyed on the emitted selector
+#[derive(Debug, Clone, Default)]
+pub(crate) struct SourceRefs {
+    /// file id -> rules the file references
+    sources: HashMap<String, HashSet<String>>,
+    /// rule -> number of files referencing it
+    references: HashMap<String, usize>,
+    /// rules generated while scanning a source, only those are dropped again, rules that were in
+    /// the env before belong to whoever put them there
+    pub(crate) owned: HashSet<String>,
+}
+
+impl SourceRefs {
+    /// sets the rules a file references, returns the owned rules nothing references anymore
+    pub(crate) fn replace(&mut self, file_id: &str, keys: HashSet<String>) -> Vec<String> {
+        for key in &keys {
+            *self.references.entry(key.clone()).or_default() += 1;
+        }
+        let previous = self
+            .sources
+            .insert(file_id.to_string(), keys)
+            .unwrap_or_default();
+        self.release(previous)
+    }
+
+    /// the rules a file references, `None` if it wasn't scanned
+    pub(crate) fn keys(&self, file_id: &str) -> Option<&HashSet<String>> {
+        self.sources.get(file_id)
+    }
+
+    pub(crate) fn remove(&mut self, file_id: &str) -> Vec<String> {
+        let previous = self.sources.remove(file_id).unwrap_or_default();
+        self.release(previous)
+    }
+
+    fn release(&mut self, keys: HashSet<String>) -> Vec<String> {
+        let mut unused = Vec::new();
+        for key in keys {
+            let count = self
+                .references
+                .get_mut(&key)
+                .expect("referenced rules are counted");
+            *count -= 1;
+            if *count == 0 {
+                self.references.remove(&key);
+                if self.owned.remove(&key) {
+                    unused.push(key);
+                }
+            }
+        }
+        unused
+    }
+}
+
+/// an env with fixed compile options for hosts that keep running (dev servers, editors) and only
+/// want to push what changed
+pub struct Compiler {
+    pub env: EmitEnv,
+    pub options: CompileOptions,
+    observer: Option<Observer>,
+}
+
+impl Compiler {
+    pub fn new(env: EmitEnv, options: CompileOptions) -> Self {
+        Compiler {
+            env,
+            options,
+            observer: None,
+        }
+    }
+
+    /// calls `observer` for every candidate `update` finds, for progress reporting and
+    /// telemetry. replaces the previous observer
+    pub fn set_observer(&mut self, observer: impl Fn(&CandidateEvent) + 'static) {
+        self.observer = Some(Box::new(observer));
+    }
+
+    /// rescans a file with its new contents and returns the rules that appeared in or
+    /// disappeared from the stylesheet
+    pub fn update(&mut self, file_id: &str, new_contents: &str) -> Delta {
+        let Some(observer) = &self.observer else {
+            return self.env.parse_source(&self.options, file_id, new_contents);
+        };
+        let line_index = LineIndex::new(new_contents);
+        let mut on_candidate = |offset: usize, candidate: &str, outcome| {
+            observer(&CandidateEvent {
+                candidate,
+                location: line_index.location(file_id, offset),
+                outcome,
+            });
+        };
+        self.env.parse_source_observed(
+            &self.options,
+            file_id,
+            new_contents,
+            Some(&mut on_candidate),
+        )
+    }
+
+    /// the file was deleted, returns the rules that disappeared from the stylesheet
+    pub fn remove(&mut self, file_id: &str) -> Delta {
+        Delta {
+            added_rules: Vec::new(),
+            removed_rules: self.env.retract_source(file_id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::{CandidateOutcome, CompileOptions, Compiler, EmitEnv};
+
+    #[test]
+    fn test_update_deltas() {
+        let mut compiler = Compiler::new(
+            EmitEnv::new_with_default_config(),
+            CompileOptions::default(),
+        );
+
+        let delta = compiler.update("a.html", "<div class=\"p-4 flex\">");
+        let mut added = delta
+            .added_rules
+            .iter()
+            .map(|css_def| css_def.class_name.as_str())
+            .collect::<Vec<_>>();
+        added.sort();
+        assert_eq!(added, ["flex", "p-4"]);
+        assert!(delta.removed_rules.is_empty());
+
+        // p-4 is still used by a.html
+        let delta = compiler.update("b.html", "<div class=\"p-4\">");
+        assert!(delta.is_empty());
+
+        let delta = compiler.update("a.html", "<div class=\"grid\">");
+        assert_eq!(delta.added_rules.len(), 1);
+        assert_eq!(delta.removed_rules.len(), 1);
+        assert_eq!(delta.removed_rules[0].class_name, "flex");
+        assert!(!compiler.env.to_css_stylesheet(false).contains(".flex{"));
+
+        // removed rules are generated again when they come back
+        let delta = compiler.update("b.html", "<div class=\"flex\">");
+        assert_eq!(delta.added_rules[0].class_name, "flex");
+        assert_eq!(delta.removed_rules[0].class_name, "p-4");
+    }
+
+    #[test]
+    fn test_observer() {
+        let mut compiler = Compiler::new(
+            EmitEnv::new_with_default_config(),
+            CompileOptions::default(),
+        );
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let seen = events.clone();
+        compiler.set_observer(move |event| {
+            seen.borrow_mut().push((
+                event.candidate.to_string(),
+                event.location.line,
+                event.location.column,
+                event.outcome,
+            ));
+        });
+
+        compiler.update("a.html", "<p class=\"p-4\">\n<p class=\"p-4 not-a-class\">");
+        let events = events.borrow();
+        let relevant = events
+            .iter()
+            .filter(|event| event.0.contains('-'))
+            .cloned()
+            .collect::<Vec<_>>();
+        assert_eq!(
+            relevant,
+            [
+                ("p-4".to_string(), 1, 11, CandidateOutcome::Generated),
+                ("p-4".to_string(), 2, 11, CandidateOutcome::AlreadyGenerated),
+                (
+                    "not-a-class".to_string(),
+                    2,
+                    15,
+                    CandidateOutcome::Unresolved
+                ),
+            ]
+        );
+    }
+}
@@ -1,25 +1,60 @@
-use std::collections::HashMap;
+use std::{
+    collections::{HashMap, HashSet},
+    ops::Range,
+};
 
 use chumsky::{
     IterParser, Parser,
     error::Rich,
     extra,
     input::Input,
-    prelude::{any, choice, just, recursive},
+    prelude::{any, choice, just, none_of, recursive},
 };
 
 use crate::{
     SpecialParam,
     css_literals::{CssLiteral, data_type_parser},
-    ignore_whitespace, ignore_whitespace2,
+    ignore_whitespace, ignore_whitespace2, is_valid_css_char,
 };
 
-#[derive(Debug, Clone, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct Variant {
     pub name: String,
     pub body: String,
     pub target: usize,
     pub is_short: bool,
+    /// from the `/** ... */` comment in front of the `@custom-variant`
+    pub doc: Option<Doc>,
+    /// index of the config it was loaded with, see `EmitEnv::config_names`
+    pub config: usize,
+    /// where the name is in the source of the config
+    pub span: Range<usize>,
+}
+
+/// documentation of a utility or variant, written as a `/** ... */` comment in front of it. an
+/// `@example` line in the comment is the example, the other lines are the description
+#[derive(Debug, Clone, PartialEq, PartialOrd, Default)]
+pub struct Doc {
+    pub description: String,
+    pub example: Option<String>,
+}
+
+impl Doc {
+    /// the text between `/**` and `*/`, leading `*`s of the lines are dropped
+    pub fn from_comment(comment: &str) -> Self {
+        let mut res = Doc::default();
+        let mut description = Vec::new();
+        for line in comment.lines() {
+            let line = line.trim().trim_start_matches('*').trim();
+            if let Some(example) = line.strip_prefix("@example") {
+                res.example = Some(example.trim().to_string());
+            } else if !line.is_empty() {
+                description.push(line);
+            }
+        }
+        res.description = description.join(" ");
+        res
+    }
 }
 
 impl Variant {
@@ -43,14 +78,14 @@ impl Property {
             format!(
                 "@property {} {{\nsyntax: \"{}\";\ninitial-value: {};\ninherits: false;\n}}\n",
                 self.name,
-                self.syntax.as_ref().map(String::as_str).unwrap_or("*"),
+                self.syntax.as_deref().unwrap_or("*"),
                 default_value
             )
         } else {
             format!(
                 "@property {} {{\nsyntax: \"{}\";\ninherits: false;\n}}\n",
                 self.name,
-                self.syntax.as_ref().map(String::as_str).unwrap_or("*"),
+                self.syntax.as_deref().unwrap_or("*"),
             )
         }
     }
@@ -62,12 +97,309 @@ pub struct Utility {
     pub parts: Vec<ParsedCodePart>,
     pub has_value: bool,
     pub properties: Vec<Property>,
+    /// `@keyframes` in the body, they're only emitted once the utility is used
+    pub keyframes: Vec<(String, String)>,
+    /// set by the `@group name;` in front of the utility, see `EmitOptions::utility_groups`
+    pub group: Option<String>,
+    /// set by the `@layer name;` in front of the utility, `utilities` if there's none. see
+    /// `EmitOptions::layers`
+    pub layer: Option<String>,
+    /// from the `/** ... */` comment in front of the `@utility`
+    pub doc: Option<Doc>,
+    /// index of the config it was loaded with, see `EmitEnv::config_names`
+    pub config: usize,
+    /// where the name is in the source of the config
+    pub span: Range<usize>,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct Theme {
+    /// set for `@theme name(x) { ... }`, whose vars are scoped to `[data-theme="x"]`
+    pub name: Option<String>,
+    /// set for `@theme dark { ... }`, whose vars only apply under the `dark` custom variant
+    pub variant: Option<String>,
     pub vars: HashMap<String, String>,
     pub keyframes: HashMap<String, String>,
+    /// vars that are overridden by a named theme, utilities reference them with `var(--x)`
+    /// instead of inlining their value so switching the theme has an effect
+    pub dynamic_vars: HashSet<String>,
+    /// vars of the `light { ... }` and `dark { ... }` blocks, see `Theme::pair_scheme_vars`
+    pub light_vars: HashMap<String, String>,
+    pub dark_vars: HashMap<String, String>,
+    /// where the declarations of `vars` are in the source of the config
+    pub var_spans: HashMap<String, Range<usize>>,
+}
+
+/// returns the names (without the leading `--`) of all vars referenced with `var(--name)`
+pub fn var_references(value: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut rest = value;
+    while let Some(idx) = rest.find("var(--") {
+        rest = &rest[idx + "var(--".len()..];
+        let end = rest
+            .find(|c: char| !(c.is_alphanumeric() || c == '-' || c == '_'))
+            .unwrap_or(rest.len());
+        if end > 0 {
+            res.push(rest[..end].to_string());
+        }
+        rest = &rest[end..];
+    }
+    res
+}
+
+impl Theme {
+    pub fn has_var(&self, name: &str) -> bool {
+        self.vars.contains_key(name) || self.dynamic_vars.contains(name)
+    }
+
+    /// `vars` sorted by name, so the emitted theme is the same on every run
+    pub fn sorted_vars(&self) -> Vec<(&String, &String)> {
+        let mut vars = self.vars.iter().collect::<Vec<_>>();
+        vars.sort();
+        vars
+    }
+
+    /// `steps` of the spacing scale: an explicit step like `--spacing-4: 1.125rem` if the theme
+    /// has one, otherwise a multiple of `--spacing`
+    pub fn spacing(&self, steps: &str, negative: bool) -> String {
+        let step = format!("spacing-{steps}");
+        let step_value = if self.dynamic_vars.contains(&step) {
+            Some(format!("var(--{step})"))
+        } else {
+            self.vars.get(&step).cloned()
+        };
+        match (step_value, negative) {
+            (Some(step_value), false) => step_value,
+            (Some(step_value), true) => format!("calc({step_value} * -1)"),
+            (None, false) => format!("calc(var(--spacing) * {steps})"),
+            (None, true) => format!("calc(var(--spacing) * -{steps})"),
+        }
+    }
+
+    /// moves the vars of the `light` and `dark` blocks into `vars` as `light-dark(light, dark)`,
+    /// the side that isn't set falls back to the var of this theme or to `fallback`, the value
+    /// it had before the theme was loaded
+    pub fn pair_scheme_vars(&mut self, fallback: impl Fn(&str) -> Option<String>) {
+        let mut dark_vars = std::mem::take(&mut self.dark_vars);
+        let mut pairs = self
+            .light_vars
+            .drain()
+            .map(|(name, light_value)| {
+                let dark_value = dark_vars.remove(&name);
+                (name, Some(light_value), dark_value)
+            })
+            .collect::<Vec<_>>();
+        pairs.extend(
+            dark_vars
+                .into_iter()
+                .map(|(name, dark_value)| (name, None, Some(dark_value))),
+        );
+
+        for (name, light_value, dark_value) in pairs {
+            let current = self.vars.get(&name).cloned().or_else(|| fallback(&name));
+            let value = match (light_value.or(current.clone()), dark_value.or(current)) {
+                (Some(light_value), Some(dark_value)) if light_value != dark_value => {
+                    format!("light-dark({light_value}, {dark_value})")
+                }
+                (Some(value), _) | (None, Some(value)) => value,
+                (None, None) => continue,
+            };
+            self.vars.insert(name, value);
+        }
+    }
+
+    /// finds all reference cycles between theme vars, each cycle is returned as the chain
+    /// of var names starting at its smallest member and ending with it again
+    pub fn var_cycles(&self) -> Vec<Vec<String>> {
+        #[derive(Clone, Copy, PartialEq)]
+        enum State {
+            Visiting,
+            Done,
+        }
+
+        fn visit<'a>(
+            theme: &'a Theme,
+            name: &'a str,
+            states: &mut HashMap<&'a str, State>,
+            stack: &mut Vec<&'a str>,
+            cycles: &mut Vec<Vec<String>>,
+        ) {
+            match states.get(name) {
+                Some(State::Done) => return,
+                Some(State::Visiting) => {
+                    let start = stack.iter().position(|x| *x == name).unwrap_or(0);
+                    let mut cycle = stack[start..]
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect::<Vec<_>>();
+                    let min_idx = cycle
+                        .iter()
+                        .enumerate()
+                        .min_by_key(|(_, x)| x.as_str())
+                        .map(|(i, _)| i)
+                        .unwrap_or(0);
+                    cycle.rotate_left(min_idx);
+                    cycle.push(cycle[0].clone());
+                    if !cycles.contains(&cycle) {
+                        cycles.push(cycle);
+                    }
+                    return;
+                }
+                None => {}
+            }
+
+            let Some((name, value)) = theme.vars.get_key_value(name) else {
+                return;
+            };
+
+            states.insert(name, State::Visiting);
+            stack.push(name);
+            for reference in var_references(value) {
+                if let Some((reference, _)) = theme.vars.get_key_value(reference.as_str()) {
+                    visit(theme, reference, states, stack, cycles);
+                }
+            }
+            stack.pop();
+            states.insert(name, State::Done);
+        }
+
+        let mut names = self.vars.keys().map(String::as_str).collect::<Vec<_>>();
+        names.sort();
+
+        let mut states = HashMap::new();
+        let mut cycles = Vec::new();
+        for name in names {
+            visit(self, name, &mut states, &mut Vec::new(), &mut cycles);
+        }
+        cycles
+    }
+
+    /// replaces every `var(--name)` that refers to a theme var with its (recursively inlined)
+    /// value, vars that aren't part of the theme, are dynamic or are part of a cycle are kept
+    pub fn inline_vars(&self, src: &str) -> String {
+        self.inline_vars_with_stack(src, &mut Vec::new())
+    }
+
+    fn inline_vars_with_stack<'a>(&'a self, src: &str, stack: &mut Vec<&'a str>) -> String {
+        let mut res = String::with_capacity(src.len());
+        let mut rest = src;
+        while let Some(idx) = rest.find("var(") {
+            res.push_str(&rest[..idx]);
+            let args_start = idx + "var(".len();
+
+            let mut depth = 1;
+            let mut args_end = None;
+            let mut comma = None;
+            for (i, c) in rest[args_start..].char_indices() {
+                match c {
+                    '(' => depth += 1,
+                    ')' => {
+                        depth -= 1;
+                        if depth == 0 {
+                            args_end = Some(args_start + i);
+                            break;
+                        }
+                    }
+                    ',' if depth == 1 && comma.is_none() => comma = Some(args_start + i),
+                    _ => {}
+                }
+            }
+
+            let Some(args_end) = args_end else {
+                // unbalanced, leave the rest untouched
+                res.push_str(&rest[idx..]);
+                return res;
+            };
+
+            let name = rest[args_start..comma.unwrap_or(args_end)].trim();
+            let resolved = name
+                .strip_prefix("--")
+                .and_then(|name| self.vars.get_key_value(name))
+                .filter(|(name, _)| {
+                    !stack.contains(&name.as_str()) && !self.dynamic_vars.contains(*name)
+                });
+
+            if let Some((name, value)) = resolved {
+                stack.push(name);
+                res.push_str(&self.inline_vars_with_stack(value, stack));
+                stack.pop();
+            } else if let Some(comma) = comma {
+                res.push_str(&rest[idx..comma + 1]);
+                res.push_str(&self.inline_vars_with_stack(&rest[comma + 1..args_end], stack));
+                res.push(')');
+            } else {
+                res.push_str(&rest[idx..args_end + 1]);
+            }
+
+            rest = &rest[args_end + 1..];
+        }
+        res.push_str(rest);
+        res
+    }
+
+    /// replaces every `theme(--name)` with the value of the theme var, `theme(--name / 50%)`
+    /// applies the alpha like the `/50` modifier does (a bare number is a percentage too).
+    /// dynamic vars stay `var()` references, calls with unknown vars or an alpha for a value that
+    /// isn't a color are kept as they are
+    pub fn resolve_theme_functions(&self, src: &str) -> String {
+        let mut res = String::with_capacity(src.len());
+        let mut rest = src;
+        while let Some(idx) = rest.find("theme(") {
+            let is_function_start = rest[..idx]
+                .chars()
+                .next_back()
+                .is_none_or(|c| !is_valid_css_char(c));
+            let args_start = idx + "theme(".len();
+            let mut depth = 1;
+            let args_end = rest[args_start..].char_indices().find_map(|(i, c)| {
+                match c {
+                    '(' => depth += 1,
+                    ')' => depth -= 1,
+                    _ => {}
+                }
+                (depth == 0).then_some(args_start + i)
+            });
+            let Some(args_end) = args_end.filter(|_| is_function_start) else {
+                res.push_str(&rest[..args_start]);
+                rest = &rest[args_start..];
+                continue;
+            };
+
+            res.push_str(&rest[..idx]);
+            let args = &rest[args_start..args_end];
+            let (name, alpha) = match args.split_once('/') {
+                Some((name, alpha)) => (name.trim(), Some(alpha.trim())),
+                None => (args.trim(), None),
+            };
+            let value = name
+                .strip_prefix("--")
+                .filter(|name| self.has_var(name))
+                .map(|name| match self.vars.get(name) {
+                    Some(value) if !self.dynamic_vars.contains(name) => self.inline_vars(value),
+                    _ => format!("var(--{name})"),
+                });
+            match (value, alpha) {
+                (Some(value), None) => res.push_str(&value),
+                // `theme(--radius / 50%)` has no color to make transparent
+                (Some(value), Some(alpha))
+                    if !alpha.is_empty() && (name.starts_with("--color") || is_color(&value)) =>
+                {
+                    let alpha = if alpha.ends_with('%') {
+                        alpha.to_string()
+                    } else {
+                        format!("{alpha}%")
+                    };
+                    res.push_str(&insert_alpha(&value, &alpha).unwrap_or_else(|| {
+                        format!("color-mix(in oklab, {value} {alpha}, transparent)")
+                    }));
+                }
+                _ => res.push_str(&rest[idx..=args_end]),
+            }
+            rest = &rest[args_end + 1..];
+        }
+        res.push_str(rest);
+        res
+    }
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -75,15 +407,69 @@ pub enum UtilityInstantiationError {
     DontNeedValue,
     NeedValue,
     NothingMatched,
+    /// `-bg-red-500`, only numbers, lengths, angles and arbitrary values can be negated
+    CantBeNegative,
 }
 
 impl Utility {
+    /// the name as written after `@utility`, like `p-*`
+    pub fn display_name(&self) -> String {
+        if self.has_value {
+            format!("{}-*", self.name)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// whether `other` has the same body, the indentation of the config doesn't matter
+    pub fn same_definition(&self, other: &Utility) -> bool {
+        let normalized = |utility: &Utility| {
+            utility
+                .parts
+                .iter()
+                .map(|part| match part {
+                    ParsedCodePart::String(s) => {
+                        ParsedCodePart::String(s.split_whitespace().collect::<Vec<_>>().join(" "))
+                    }
+                    ParsedCodePart::ValueCall(call) => ParsedCodePart::ValueCall(call.clone()),
+                })
+                .collect::<Vec<_>>()
+        };
+        normalized(self) == normalized(other)
+            && self.properties == other.properties
+            && self.keyframes == other.keyframes
+    }
+
+    /// the theme namespaces of every `--value()` call in the body that uses any, as the parts in
+    /// front of and after the `*` without the leading `--`, like `[("icon-", "")]`
+    pub fn value_namespaces(&self) -> Vec<Vec<(&str, &str)>> {
+        self.parts
+            .iter()
+            .filter_map(|part| match part {
+                ParsedCodePart::ValueCall(call) => Some(
+                    call.params
+                        .iter()
+                        .filter_map(|param| match param {
+                            ValueUsage::Var(var, target) => {
+                                Some((&var[..*target], &var[*target..]))
+                            }
+                            _ => None,
+                        })
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            })
+            .filter(|namespaces| !namespaces.is_empty())
+            .collect()
+    }
+
     pub fn instantiate(
         &self,
         theme: &Theme,
         value: Option<&str>,
         special_param: Option<&SpecialParam>,
         is_arb: bool,
+        negative: bool,
     ) -> Result<String, UtilityInstantiationError> {
         if self.has_value {
             if value.is_none() {
@@ -93,6 +479,9 @@ impl Utility {
             if value.is_some() {
                 return Err(UtilityInstantiationError::DontNeedValue);
             }
+            if negative {
+                return Err(UtilityInstantiationError::CantBeNegative);
+            }
 
             return Ok(self
                 .parts
@@ -110,6 +499,29 @@ impl Utility {
             .parse(value)
             .into_output()
             .unwrap_or(CssLiteral::Other(value.to_string()));
+        if negative
+            && !is_arb
+            && !matches!(
+                literal,
+                CssLiteral::Length(..)
+                    | CssLiteral::Number(..)
+                    | CssLiteral::Integer(..)
+                    | CssLiteral::Percentage(..)
+                    | CssLiteral::Angle(..)
+                    | CssLiteral::Fr(..)
+            )
+        {
+            return Err(UtilityInstantiationError::CantBeNegative);
+        }
+        // `-mt-[5px]` of a `mt-*` without a `-mt-*`, see `EmitEnv::utility_body`
+        let negate = |replacement: Option<String>| {
+            let replacement = replacement.unwrap_or_else(|| value.to_string());
+            if negative {
+                format!("calc({replacement} * -1)")
+            } else {
+                replacement
+            }
+        };
 
         let mut i = 0;
         'outer: while i < self.parts.len() {
@@ -117,7 +529,7 @@ impl Utility {
             if let ParsedCodePart::ValueCall(call) = part {
                 for p in call.params.iter() {
                     if let Some(replacement) =
-                        p.literal_matches(theme, value, &literal, special_param.clone(), is_arb)
+                        p.literal_matches(theme, value, &literal, special_param, is_arb)
                     {
                         let mut res_string = String::new();
 
@@ -146,15 +558,12 @@ impl Utility {
                                                     theme,
                                                     value,
                                                     &literal,
-                                                    special_param.clone(),
+                                                    special_param,
                                                     is_arb,
                                                 )
                                             })
                                         {
-                                            res_string.insert_str(
-                                                0,
-                                                &replacement.unwrap_or_else(|| value.to_string()),
-                                            );
+                                            res_string.insert_str(0, &negate(replacement));
                                         } else {
                                             i += 1;
                                             continue 'outer;
@@ -169,7 +578,7 @@ impl Utility {
                             }
                         }
 
-                        res_string.push_str(&replacement.unwrap_or_else(|| value.to_string()));
+                        res_string.push_str(&negate(replacement));
 
                         // Find end of line
                         let mut i_forward = i + 1;
@@ -195,14 +604,12 @@ impl Utility {
                                                 theme,
                                                 value,
                                                 &literal,
-                                                special_param.clone(),
+                                                special_param,
                                                 is_arb,
                                             )
                                         })
                                     {
-                                        res_string.push_str(
-                                            &replacement.unwrap_or_else(|| value.to_string()),
-                                        );
+                                        res_string.push_str(&negate(replacement));
                                     } else {
                                         i += 1;
                                         continue 'outer;
@@ -264,6 +671,25 @@ pub enum ValueUsage {
     ArbType(ValueType),
     Literal(String),
     Var(String, usize),
+    /// `spacing` / `-spacing`, a number of spacing steps, see `Theme::spacing`
+    Spacing(bool),
+    /// `percentage-from-ratio` / `-percentage-from-ratio`, a fraction as a percentage like
+    /// `1/2` -> `50%`
+    RatioPercentage(bool),
+}
+
+/// `1/3` -> `33.333333%`, `None` for a zero denominator
+fn ratio_percentage(numerator: &str, denominator: &str, negative: bool) -> Option<String> {
+    let denominator = denominator.parse::<f64>().ok().filter(|d| *d != 0.0)?;
+    let mut percentage = numerator.parse::<f64>().ok()? / denominator * 100.0;
+    if negative {
+        percentage = -percentage;
+    }
+    let formatted = format!("{percentage:.6}");
+    Some(format!(
+        "{}%",
+        formatted.trim_end_matches('0').trim_end_matches('.')
+    ))
 }
 
 fn expand_3_digit_hex(hex: &str) -> String {
@@ -276,10 +702,23 @@ fn expand_3_digit_hex(hex: &str) -> String {
         })
 }
 
+/// whether an alpha can be applied to `value`, a color like `#fff`, `red` or `oklch(...)` or a
+/// `var()` that could be one
+pub(crate) fn is_color(value: &str) -> bool {
+    let value = value.trim();
+    value.starts_with("var(")
+        || value == "transparent"
+        || value.eq_ignore_ascii_case("currentcolor")
+        || matches!(
+            data_type_parser().parse(value).into_output(),
+            Some(CssLiteral::Color(..))
+        )
+}
+
 /// alpha given like so: 100%, 0%, 20%
-fn insert_alpha(color: &str, alpha: &str) -> Option<String> {
+pub(crate) fn insert_alpha(color: &str, alpha: &str) -> Option<String> {
     let color = color.trim_ascii_start().to_string();
-    if color.starts_with("#") {
+    if let Some(hex) = color.strip_prefix("#") {
         let percent_value = alpha[..alpha.len() - 1].parse::<f32>().ok()?;
         let hex_alpha_channel = format!(
             "{:02X}",
@@ -294,16 +733,34 @@ fn insert_alpha(color: &str, alpha: &str) -> Option<String> {
             }
         );
 
-        if color.len() == 4 {
+        if hex.len() == 3 {
             // is 3 digit notation
-            return Some(format!(
-                "#{}{hex_alpha_channel}",
-                expand_3_digit_hex(&color[1..]),
-            ));
-        } else if color.len() == 7 {
+            return Some(format!("#{}{hex_alpha_channel}", expand_3_digit_hex(hex),));
+        } else if hex.len() == 6 {
             // is 6 digit notation
-            return Some(format!("#{color}{hex_alpha_channel}"));
+            return Some(format!("#{hex}{hex_alpha_channel}"));
         }
+    } else if let Some(pair) = color
+        .strip_prefix("light-dark(")
+        .and_then(|pair| pair.strip_suffix(')'))
+    {
+        let mut depth = 0usize;
+        let split = pair.char_indices().find_map(|(i, c)| {
+            match c {
+                '(' => depth += 1,
+                ')' => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => return Some(i),
+                _ => {}
+            }
+            None
+        })?;
+        return match (
+            insert_alpha(&pair[..split], alpha),
+            insert_alpha(&pair[split + 1..], alpha),
+        ) {
+            (Some(light), Some(dark)) => Some(format!("light-dark({light}, {dark})")),
+            _ => Some(format!("color-mix(in oklab, {color} {alpha}, transparent)")),
+        };
     } else if ["rgb", "hsl", "lab", "lch", "oklab", "oklch", "color"]
         .into_iter()
         .any(|prefix| color.starts_with(prefix))
@@ -322,9 +779,9 @@ fn insert_alpha(color: &str, alpha: &str) -> Option<String> {
 }
 
 impl ValueUsage {
-    pub fn literal_matches<'a>(
+    pub fn literal_matches(
         &self,
-        theme: &'a Theme,
+        theme: &Theme,
         value: &str,
         css_literal_src: &CssLiteral,
         special_param: Option<&SpecialParam>,
@@ -334,19 +791,18 @@ impl ValueUsage {
             ValueUsage::Type(t) if !is_arb && t.css_literal_matches(css_literal_src) => {
                 if let CssLiteral::Color(..) = css_literal_src
                     && let Some(SpecialParam::Transparency(t)) = special_param
-                    && let Some(with_alpha) = {
-                        dbg!(&value, &t);
-                        dbg!(insert_alpha(value, t.as_str()))
-                    }
+                    && let Some(with_alpha) = insert_alpha(value, t.as_str())
                 {
                     return Some(Some(with_alpha));
                 }
 
                 Some(None)
             }
-            ValueUsage::ArbType(t) if is_arb && t.css_literal_matches(css_literal_src) => {
-                Some(None)
-            }
+            // a value with a type in front of it only goes where that type is expected
+            ValueUsage::ArbType(t) if is_arb => match special_param {
+                Some(SpecialParam::DataType(data_type)) => (t == data_type).then_some(None),
+                _ => t.css_literal_matches(css_literal_src).then_some(None),
+            },
             ValueUsage::Literal(s) => {
                 if let CssLiteral::Other(x) = css_literal_src
                     && x == s
@@ -359,7 +815,15 @@ impl ValueUsage {
             ValueUsage::Var(var, to_insert) => {
                 let mut to_check = var.clone();
                 to_check.insert_str(*to_insert, value);
-                if let Some(value) = theme.vars.get(to_check.as_str()) {
+                if theme.dynamic_vars.contains(to_check.as_str()) {
+                    let reference = format!("var(--{to_check})");
+                    if let Some(SpecialParam::Transparency(t)) = special_param {
+                        return Some(Some(format!(
+                            "color-mix(in oklab, {reference} {t}, transparent)"
+                        )));
+                    }
+                    Some(Some(reference))
+                } else if let Some(value) = theme.vars.get(to_check.as_str()) {
                     if let Some(SpecialParam::Transparency(t)) = special_param
                         && let Some(inserted) = insert_alpha(value, t.as_str())
                     {
@@ -370,6 +834,19 @@ impl ValueUsage {
                     None
                 }
             }
+            ValueUsage::Spacing(negative)
+                if !is_arb
+                    && (ValueType::Number.css_literal_matches(css_literal_src)
+                        || theme.has_var(&format!("spacing-{value}"))) =>
+            {
+                Some(Some(theme.spacing(value, *negative)))
+            }
+            ValueUsage::RatioPercentage(negative) if !is_arb => {
+                let CssLiteral::Ratio(numerator, denominator) = css_literal_src else {
+                    return None;
+                };
+                Some(Some(ratio_percentage(numerator, denominator, *negative)?))
+            }
             _ => None,
         }
     }
@@ -444,6 +921,10 @@ pub fn parse_value_param<'a>()
                 ValueUsage::Var(text, target_idx.unwrap())
             }),
         ),
+        just("-spacing").to(ValueUsage::Spacing(true)),
+        just("spacing").to(ValueUsage::Spacing(false)),
+        just("-percentage-from-ratio").to(ValueUsage::RatioPercentage(true)),
+        just("percentage-from-ratio").to(ValueUsage::RatioPercentage(false)),
         parse_css_data_type().map(ValueUsage::Type),
         just("[")
             .ignore_then(parse_css_data_type())
@@ -471,6 +952,7 @@ enum RawParsedCodePart {
     Char(char),
     ValueCall(ValueCall),
     Property(Property),
+    Keyframes(String, String),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -484,6 +966,18 @@ pub enum ConfigUnit {
     Utility(Utility),
     Variant(Variant),
     Theme(Theme),
+    Preset(String),
+    Plugin(String),
+    /// alias name and the variants it stands for, like `dark:hover`
+    VariantAlias(String, String),
+    /// variant name and the pseudo-element it adds to the selector
+    PseudoElement(String, String),
+    /// the group of the utilities after it
+    Group(String),
+    /// the cascade layer of the utilities after it
+    Layer(String),
+    /// documentation of the utility or variant after it
+    Doc(Doc),
 }
 
 // pub fn parse_nested_utility_code<'a>()
@@ -555,7 +1049,7 @@ pub fn variant_parser<'a>() -> impl Parser<'a, &'a str, Variant, extra::Err<Rich
     choice((
         just("@custom-variant")
             .then(ignore_whitespace2())
-            .then(parse_utility_name())
+            .then(parse_utility_name().map_with(|name, e| (name, e.span().into_range())))
             .then(ignore_whitespace2())
             .then_ignore(just("{").rewind())
             .then(variant_rec_text())
@@ -564,7 +1058,7 @@ pub fn variant_parser<'a>() -> impl Parser<'a, &'a str, Variant, extra::Err<Rich
                     .iter()
                     .any(|unit| matches!(unit, VariantParseUnit::Target(..)))
             })
-            .map(|((((a, b), name), d), units)| {
+            .map(|((((a, b), (name, span)), d), units)| {
                 let mut s_buf = String::new();
                 let mut target = None;
 
@@ -583,16 +1077,19 @@ pub fn variant_parser<'a>() -> impl Parser<'a, &'a str, Variant, extra::Err<Rich
                     target: target.expect("need target") // is checked above with filter
                         - (a.len() + b.len() + d.len() + 1 + name_len),
                     is_short: false,
+                    doc: None,
+                    config: 0,
+                    span,
                 }
             }),
         just("@custom-variant")
             .ignore_then(ignore_whitespace2())
-            .ignore_then(parse_utility_name())
+            .ignore_then(parse_utility_name().map_with(|name, e| (name, e.span().into_range())))
             .then_ignore(ignore_whitespace2())
             .then_ignore(just("(").rewind())
             .then(variant_short_rec_text())
             .then_ignore(just(";"))
-            .map(|(name, units)| {
+            .map(|((name, span), units)| {
                 let mut s_buf = String::new();
 
                 for unit in units {
@@ -609,6 +1106,9 @@ pub fn variant_parser<'a>() -> impl Parser<'a, &'a str, Variant, extra::Err<Rich
                     body: s_buf,
                     target: len + 2,
                     is_short: true,
+                    doc: None,
+                    config: 0,
+                    span,
                 }
             }),
     ))
@@ -638,6 +1138,15 @@ pub fn parse_var<'a>()
         .then_ignore(just(";"))
 }
 
+/// `@plugin "./my-plugin.so";`
+pub fn parse_plugin<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> {
+    just("@plugin")
+        .ignore_then(ignore_whitespace2())
+        .ignore_then(parse_literal())
+        .then_ignore(ignore_whitespace2())
+        .then_ignore(just(";"))
+}
+
 pub fn keyframes_text_parser<'a>()
 -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> + Clone {
     recursive(|e| {
@@ -665,22 +1174,49 @@ pub fn parse_keyframes<'a>()
         .then(keyframes_text_parser())
 }
 
+/// `light { ... }` and `dark { ... }` blocks of a theme, `true` for dark
+fn parse_scheme_block<'a>()
+-> impl Parser<'a, &'a str, (bool, Vec<(String, String)>), extra::Err<Rich<'a, char>>> + Clone {
+    choice((just("light").to(false), just("dark").to(true)))
+        .then_ignore(ignore_whitespace())
+        .then_ignore(just("{"))
+        .then(parse_var().padded().repeated().collect::<Vec<_>>())
+        .then_ignore(ignore_whitespace2())
+        .then_ignore(just("}"))
+}
+
 pub fn parse_theme<'a>() -> impl Parser<'a, &'a str, Theme, extra::Err<Rich<'a, char>>> + Clone {
     #[derive(Debug, Clone, PartialEq)]
     enum ParseUnit {
-        Variable(String, String),
+        Variable(String, String, Range<usize>),
         Keyframes(String, String),
+        Scheme(bool, Vec<(String, String)>),
     }
     just("@theme")
         .ignore_then(ignore_whitespace())
-        .ignore_then(just("{"))
-        .ignore_then(ignore_whitespace2())
         .ignore_then(
             choice((
-                parse_var().map(|(var_name, var_value)| ParseUnit::Variable(var_name, var_value)),
+                just("name(")
+                    .ignore_then(parse_utility_name())
+                    .then_ignore(just(")"))
+                    .map(|name| (Some(name), None)),
+                parse_utility_name().map(|variant| (None, Some(variant))),
+            ))
+            .then_ignore(ignore_whitespace())
+            .or_not()
+            .map(Option::unwrap_or_default),
+        )
+        .then_ignore(just("{"))
+        .then_ignore(ignore_whitespace2())
+        .then(
+            choice((
+                parse_var().map_with(|(var_name, var_value), e| {
+                    ParseUnit::Variable(var_name, var_value, e.span().into_range())
+                }),
                 parse_keyframes().map(|(keyframes_name, keyframes_src)| {
                     ParseUnit::Keyframes(keyframes_name, keyframes_src)
                 }),
+                parse_scheme_block().map(|(is_dark, vars)| ParseUnit::Scheme(is_dark, vars)),
             ))
             .padded()
             .repeated()
@@ -689,20 +1225,24 @@ pub fn parse_theme<'a>() -> impl Parser<'a, &'a str, Theme, extra::Err<Rich<'a,
         .then_ignore(ignore_whitespace2())
         .then_ignore(just("}"))
         .then_ignore(ignore_whitespace2())
-        .map(|vars| {
+        .map(|((name, variant), vars)| {
             vars.into_iter().fold(
                 Theme {
-                    vars: HashMap::new(),
-                    keyframes: HashMap::new(),
+                    name,
+                    variant,
+                    ..Default::default()
                 },
                 |mut acc, unit| {
                     match unit {
-                        ParseUnit::Variable(var_name, var_value) => {
+                        ParseUnit::Variable(var_name, var_value, span) => {
+                            acc.var_spans.insert(var_name.clone(), span);
                             acc.vars.insert(var_name, var_value);
                         }
                         ParseUnit::Keyframes(keyframes_name, keyframes_value) => {
                             acc.keyframes.insert(keyframes_name, keyframes_value);
                         }
+                        ParseUnit::Scheme(false, scheme_vars) => acc.light_vars.extend(scheme_vars),
+                        ParseUnit::Scheme(true, scheme_vars) => acc.dark_vars.extend(scheme_vars),
                     }
                     acc
                 },
@@ -730,6 +1270,87 @@ pub struct UserConfig {
     pub utilities: Vec<Utility>,
     pub variants: Vec<Variant>,
     pub themes: Vec<Theme>,
+    pub presets: Vec<String>,
+    pub plugins: Vec<String>,
+    pub variant_aliases: Vec<(String, String)>,
+    pub pseudo_elements: Vec<(String, String)>,
+}
+
+/// `@preset typography;`
+pub fn parse_preset<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> {
+    just("@preset")
+        .ignore_then(ignore_whitespace2())
+        .ignore_then(parse_utility_name())
+        .then_ignore(ignore_whitespace2())
+        .then_ignore(just(";"))
+}
+
+/// `@alias-variant parent-hover = group-hover;`, the target can be a chain like `dark:hover`
+pub fn parse_variant_alias<'a>()
+-> impl Parser<'a, &'a str, (String, String), extra::Err<Rich<'a, char>>> {
+    just("@alias-variant")
+        .ignore_then(ignore_whitespace2())
+        .ignore_then(parse_utility_name())
+        .then_ignore(ignore_whitespace2())
+        .then_ignore(just("="))
+        .then_ignore(ignore_whitespace2())
+        .then(
+            none_of(";")
+                .repeated()
+                .at_least(1)
+                .to_slice()
+                .map(|target: &str| target.trim().to_string()),
+        )
+        .then_ignore(just(";"))
+}
+
+/// `@pseudo-element file = file-selector-button;`, a variant that adds the pseudo-element to the
+/// selector of the rule. `@pseudo-element backdrop;` is short for `backdrop = backdrop`
+pub fn parse_pseudo_element<'a>()
+-> impl Parser<'a, &'a str, (String, String), extra::Err<Rich<'a, char>>> {
+    just("@pseudo-element")
+        .ignore_then(ignore_whitespace2())
+        .ignore_then(parse_utility_name())
+        .then_ignore(ignore_whitespace2())
+        .then(
+            just("=")
+                .ignore_then(ignore_whitespace2())
+                .ignore_then(parse_utility_name())
+                .then_ignore(ignore_whitespace2())
+                .or_not(),
+        )
+        .then_ignore(just(";"))
+        .map(|(name, pseudo_element)| {
+            let pseudo_element = pseudo_element.unwrap_or_else(|| name.clone());
+            (name, pseudo_element)
+        })
+}
+
+/// `@group spacing;`, the utilities after it belong to the group until the next `@group`
+pub fn parse_group<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> {
+    just("@group")
+        .ignore_then(ignore_whitespace2())
+        .ignore_then(parse_utility_name())
+        .then_ignore(ignore_whitespace2())
+        .then_ignore(just(";"))
+}
+
+/// `@layer components;`, the utilities after it are emitted in that layer until the next
+/// `@layer`
+pub fn parse_layer<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> {
+    just("@layer")
+        .ignore_then(ignore_whitespace2())
+        .ignore_then(parse_utility_name())
+        .then_ignore(ignore_whitespace2())
+        .then_ignore(just(";"))
+}
+
+/// `/** ... */` in front of a `@utility` or `@custom-variant`
+pub fn parse_doc_comment<'a>() -> impl Parser<'a, &'a str, Doc, extra::Err<Rich<'a, char>>> {
+    just("/**")
+        .ignore_then(any().and_is(just("*/").not()).repeated().to_slice())
+        .then_ignore(just("*/"))
+        .map(Doc::from_comment)
 }
 
 pub fn config_parser<'a>() -> impl Parser<'a, &'a str, UserConfig, extra::Err<Rich<'a, char>>> {
@@ -737,6 +1358,15 @@ pub fn config_parser<'a>() -> impl Parser<'a, &'a str, UserConfig, extra::Err<Ri
         parse_utility().map_with(|x, e| (ConfigUnit::Utility(x), e.span())),
         variant_parser().map_with(|x, e| (ConfigUnit::Variant(x), e.span())),
         parse_theme().map_with(|x, e| (ConfigUnit::Theme(x), e.span())),
+        parse_preset().map_with(|x, e| (ConfigUnit::Preset(x), e.span())),
+        parse_plugin().map_with(|x, e| (ConfigUnit::Plugin(x), e.span())),
+        parse_variant_alias()
+            .map_with(|(name, target), e| (ConfigUnit::VariantAlias(name, target), e.span())),
+        parse_pseudo_element()
+            .map_with(|(name, pseudo), e| (ConfigUnit::PseudoElement(name, pseudo), e.span())),
+        parse_group().map_with(|x, e| (ConfigUnit::Group(x), e.span())),
+        parse_layer().map_with(|x, e| (ConfigUnit::Layer(x), e.span())),
+        parse_doc_comment().map_with(|x, e| (ConfigUnit::Doc(x), e.span())),
     ))
     .padded()
     .repeated()
@@ -746,18 +1376,42 @@ pub fn config_parser<'a>() -> impl Parser<'a, &'a str, UserConfig, extra::Err<Ri
             utilities: Vec::new(),
             variants: Vec::new(),
             themes: Vec::new(),
+            presets: Vec::new(),
+            plugins: Vec::new(),
+            variant_aliases: Vec::new(),
+            pseudo_elements: Vec::new(),
         };
 
+        let mut group = None;
+        let mut layer = None;
+        let mut pending_doc = None;
         for (v, span) in v {
+            // a doc comment only documents what comes right after it
+            let doc = pending_doc.take();
             match v {
-                ConfigUnit::Utility(u) => res.utilities.push(u),
+                ConfigUnit::Utility(mut u) => {
+                    u.group = group.clone();
+                    u.layer = layer.clone();
+                    u.doc = doc;
+                    res.utilities.push(u);
+                }
                 ConfigUnit::Variant(mut v) => {
                     if !v.is_short {
                         v.target -= span.start;
                     }
+                    v.doc = doc;
                     res.variants.push(v);
                 }
                 ConfigUnit::Theme(v) => res.themes.push(v),
+                ConfigUnit::Preset(name) => res.presets.push(name),
+                ConfigUnit::Plugin(path) => res.plugins.push(path),
+                ConfigUnit::VariantAlias(name, target) => res.variant_aliases.push((name, target)),
+                ConfigUnit::PseudoElement(name, pseudo_element) => {
+                    res.pseudo_elements.push((name, pseudo_element))
+                }
+                ConfigUnit::Group(name) => group = Some(name),
+                ConfigUnit::Layer(name) => layer = Some(name),
+                ConfigUnit::Doc(doc) => pending_doc = Some(doc),
             }
         }
 
@@ -775,7 +1429,7 @@ pub fn parse_braces_into_string<'a>()
                         .rewind()
                         .ignore_then(s.clone())
                         .map(|x: String| format!("({x})")),
-                    any().and_is(just(")").not()).map(|x| String::from(x)),
+                    any().and_is(just(")").not()).map(String::from),
                 ))
                 .repeated()
                 .collect::<Vec<_>>(),
@@ -828,6 +1482,7 @@ fn parse_utility_text<'a>()
                         syntax,
                     })]
                 }),
+            parse_keyframes().map(|(name, body)| vec![RawParsedCodePart::Keyframes(name, body)]),
             just("{")
                 .ignore_then(s.clone())
                 .map(|mut x: Vec<RawParsedCodePart>| {
@@ -849,14 +1504,15 @@ fn parse_utility_text<'a>()
 pub fn parse_utility<'a>() -> impl Parser<'a, &'a str, Utility, extra::Err<Rich<'a, char>>> {
     just("@utility")
         .ignore_then(ignore_whitespace())
-        .ignore_then(parse_utility_name())
+        .ignore_then(parse_utility_name().map_with(|name, e| (name, e.span().into_range())))
         .then_ignore(ignore_whitespace())
         .then_ignore(just("{"))
         .then(parse_utility_text())
-        .map(|(name, content)| {
+        .map(|((name, span), content)| {
             let mut parts = Vec::new();
             let mut buf = String::new();
             let mut properties = Vec::new();
+            let mut keyframes = Vec::new();
 
             for c in content {
                 match c {
@@ -869,6 +1525,7 @@ pub fn parse_utility<'a>() -> impl Parser<'a, &'a str, Utility, extra::Err<Rich<
                         parts.push(ParsedCodePart::ValueCall(e));
                     }
                     RawParsedCodePart::Property(property) => properties.push(property),
+                    RawParsedCodePart::Keyframes(name, body) => keyframes.push((name, body)),
                 }
             }
 
@@ -887,6 +1544,186 @@ pub fn parse_utility<'a>() -> impl Parser<'a, &'a str, Utility, extra::Err<Rich<
                 parts,
                 has_value,
                 properties,
+                keyframes,
+                group: None,
+                layer: None,
+                doc: None,
+                config: 0,
+                span,
             }
         })
 }
+
+#[cfg(test)]
+mod tests {
+    use chumsky::Parser;
+
+    use crate::{
+        CompileOptions, EmitEnv,
+        config_css::{insert_alpha, parse_theme, ratio_percentage},
+    };
+
+    #[test]
+    fn test_var_cycles() {
+        let theme = parse_theme()
+            .parse(
+                "@theme {
+                    --color-primary: var(--color-brand);
+                    --color-brand: var(--color-primary);
+                    --spacing: 0.25rem;
+                    --gap: calc(var(--spacing) * 2);
+                    --a: var(--b, var(--c));
+                    --b: 1px;
+                    --c: var(--a);
+                }",
+            )
+            .into_result()
+            .expect("theme should parse");
+
+        let mut cycles = theme.var_cycles();
+        cycles.sort();
+        assert_eq!(
+            cycles,
+            vec![
+                vec!["a", "c", "a"],
+                vec!["color-brand", "color-primary", "color-brand"],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_inline_vars() {
+        let theme = parse_theme()
+            .parse(
+                "@theme {
+                    --spacing: 0.25rem;
+                    --gap: calc(var(--spacing) * 2);
+                    --x: var(--y);
+                    --y: var(--x);
+                }",
+            )
+            .into_result()
+            .expect("theme should parse");
+
+        assert_eq!(
+            theme.inline_vars("margin: var(--gap);"),
+            "margin: calc(0.25rem * 2);"
+        );
+        assert_eq!(
+            theme.inline_vars("color: var(--tw-color, var(--spacing));"),
+            "color: var(--tw-color, 0.25rem);"
+        );
+        assert_eq!(theme.inline_vars("top: var(--x);"), "top: var(--x);");
+    }
+
+    #[test]
+    fn test_theme_functions() {
+        let mut theme = parse_theme()
+            .parse(
+                "@theme {
+                    --color-red-500: #f00;
+                    --color-brand: var(--color-red-500);
+                    --color-sky: oklch(0.7 0.1 230);
+                    --radius: 4px;
+                }",
+            )
+            .into_result()
+            .expect("theme should parse");
+        theme.dynamic_vars.insert("color-sky".to_string());
+
+        assert_eq!(
+            theme.resolve_theme_functions("color: theme(--color-brand / 50%);"),
+            "color: #ff000080;"
+        );
+        assert_eq!(
+            theme.resolve_theme_functions("border-radius: theme(--radius);"),
+            "border-radius: 4px;"
+        );
+        assert_eq!(
+            theme.resolve_theme_functions("color: theme(--color-sky / 10%);"),
+            "color: color-mix(in oklab, var(--color-sky) 10%, transparent);"
+        );
+        for kept in [
+            "color: theme(--missing / 50%);",
+            "border-radius: theme(--radius/25);",
+            "color: --theme(--radius);",
+        ] {
+            assert_eq!(theme.resolve_theme_functions(kept), kept);
+        }
+    }
+
+    #[test]
+    fn test_light_dark_pairs() {
+        let mut theme = parse_theme()
+            .parse(
+                "@theme {
+                    --color-surface: #fff;
+                    dark {
+                        --color-surface: #111;
+                        --color-red-500: #f00;
+                    }
+                    light {
+                        --color-ink: black;
+                    }
+                }",
+            )
+            .into_result()
+            .expect("theme should parse");
+        theme.pair_scheme_vars(|var| (var == "color-red-500").then(|| "red".to_string()));
+        assert_eq!(theme.vars["color-surface"], "light-dark(#fff, #111)");
+        assert_eq!(theme.vars["color-red-500"], "light-dark(red, #f00)");
+        assert_eq!(theme.vars["color-ink"], "black");
+        assert!(theme.light_vars.is_empty() && theme.dark_vars.is_empty());
+
+        assert_eq!(
+            insert_alpha("light-dark(#fff, oklch(20% 0 0))", "50%").as_deref(),
+            Some("light-dark(#ffffff80, oklch(20% 0 0 / 50%))")
+        );
+
+        let mut env = EmitEnv::new_with_default_config();
+        env.try_load_config("@theme {\ndark {\n--color-red-500: #f00;\n}\n}")
+            .unwrap();
+        env.compile_classes(&CompileOptions::default(), ["bg-red-500"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("color-scheme: light dark;"));
+        assert!(css.contains("background-color: light-dark(oklch(63.7% 0.237 25.331), #f00);"));
+    }
+
+    #[test]
+    fn test_insert_alpha_hex() {
+        // the `#` of a 6 digit color isn't doubled
+        assert_eq!(insert_alpha("#ff0000", "50%").as_deref(), Some("#ff000080"));
+        assert_eq!(insert_alpha(" #f00", "100%").as_deref(), Some("#ff0000FF"));
+        assert_eq!(insert_alpha("#ff00", "50%"), None);
+
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.try_load_config("@theme {\n--color-brand: #336699;\n}")
+            .unwrap();
+        env.compile_classes(&CompileOptions::default(), ["bg-brand/25"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("background-color: #33669940;"), "{css}");
+    }
+
+    #[test]
+    fn test_ratio_percentages() {
+        assert_eq!(ratio_percentage("1", "2", false).as_deref(), Some("50%"));
+        assert_eq!(
+            ratio_percentage("2", "3", false).as_deref(),
+            Some("66.666667%")
+        );
+        assert_eq!(ratio_percentage("1", "4", true).as_deref(), Some("-25%"));
+        assert_eq!(ratio_percentage("1", "0", false), None);
+
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["w-1/2", "-top-1/3", "aspect-3/2"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("width: 50%;"));
+        assert!(css.contains("top: -33.333333%;"));
+        assert!(css.contains("aspect-ratio: 3/2;"));
+    }
+}
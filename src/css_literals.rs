@@ -42,6 +42,7 @@ pub enum Length {
 }
 
 impl Length {
+    #[allow(clippy::wrong_self_convention)]
     pub fn to_css(self) -> String {
         use Length::*;
         match self {
@@ -78,7 +79,7 @@ impl Length {
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
-#[allow(non_camel_case_types)]
+#[allow(non_camel_case_types, clippy::enum_variant_names)]
 pub enum Color {
     Hex(String),
     Named(String),
@@ -306,13 +307,7 @@ pub fn angle_parser<'a>() -> impl Parser<'a, &'a str, Angle, extra::Err<Rich<'a,
             just("rad"),
             just("turn"),
         )))
-        .filter(|(_, unit)| match *unit {
-            "deg" => true,
-            "grad" => true,
-            "rad" => true,
-            "turn" => true,
-            _ => false,
-        })
+        .filter(|(_, unit)| matches!(*unit, "deg" | "grad" | "rad" | "turn"))
         .map(|(s, unit)| {
             use Angle::*;
 
@@ -339,16 +334,18 @@ pub fn absolute_size_parser<'a>()
         just("xx-large"),
         just("xxx-large"),
     ))
-    .filter(|size| match *size {
-        "xx-small" => true,
-        "x-small" => true,
-        "small" => true,
-        "medium" => true,
-        "large" => true,
-        "x-large" => true,
-        "xx-large" => true,
-        "xxx-large" => true,
-        _ => false,
+    .filter(|size| {
+        matches!(
+            *size,
+            "xx-small"
+                | "x-small"
+                | "small"
+                | "medium"
+                | "large"
+                | "x-large"
+                | "xx-large"
+                | "xxx-large"
+        )
     })
     .map(|size| {
         use AbsoluteSize::*;
@@ -0,0 +1,70 @@
+use std::{fmt::Display, ops::Range};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Diagnostic {
+    /// chain of theme vars (without `--`) that reference each other, first == last
+    ThemeVarCycle(Vec<String>),
+    /// `@preset` with a name that isn't bundled
+    UnknownPreset(String),
+    /// `@plugin` path and why it couldn't be loaded
+    PluginLoadFailed(String, String),
+    /// class passed to `compile_classes` in strict mode that didn't resolve to a rule
+    UnknownClass(String),
+    /// `@alias-variant` name and the target that isn't a valid variant chain
+    InvalidVariantAlias(String, String),
+    /// `@theme x { ... }` for a custom variant that isn't defined
+    UnknownThemeVariant(String),
+    /// utility name and the namespaces of a `--value()` call no theme has vars in, like `--icon-*`
+    EmptyValueNamespace(String, String),
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::ThemeVarCycle(chain) => write!(
+                f,
+                "theme vars reference each other in a cycle: {}",
+                chain
+                    .iter()
+                    .map(|var| format!("--{var}"))
+                    .collect::<Vec<_>>()
+                    .join(" -> ")
+            ),
+            Diagnostic::UnknownPreset(name) => write!(f, "unknown preset {name}"),
+            Diagnostic::PluginLoadFailed(path, reason) => {
+                write!(f, "couldn't load plugin {path}: {reason}")
+            }
+            Diagnostic::UnknownClass(class) => write!(f, "unknown class {class}"),
+            Diagnostic::InvalidVariantAlias(name, target) => {
+                write!(
+                    f,
+                    "variant alias {name} points to {target}, which isn't a variant"
+                )
+            }
+            Diagnostic::UnknownThemeVariant(variant) => {
+                write!(f, "theme for the unknown variant {variant}")
+            }
+            Diagnostic::EmptyValueNamespace(utility, namespace) => write!(
+                f,
+                "utility {utility} takes its values from {namespace}, but no theme has vars there"
+            ),
+        }
+    }
+}
+
+/// why a config couldn't be parsed, `span` is a byte range into the config source
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigError {
+    pub message: String,
+    pub span: Range<usize>,
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}..{}",
+            self.message, self.span.start, self.span.end
+        )
+    }
+}
@@ -0,0 +1,133 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Serializer};
+
+use crate::{CssDef, stylesheet::NormalizedStylesheet};
+
+/// `hover\:bg-red-500` -> `hover:bg-red-500`
+pub(crate) fn unescape_class_name(class_name: &str) -> String {
+    let mut res = String::with_capacity(class_name.len());
+    let mut chars = class_name.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => res.extend(chars.next()),
+            c => res.push(c),
+        }
+    }
+    res
+}
+
+/// a rule of `defs_to_json`
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct JsonRule<'a> {
+    selector_suffix: &'a str,
+    at_rules: &'a [String],
+    declarations: &'a BTreeMap<String, String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    nested: Vec<JsonRule<'a>>,
+}
+
+/// class -> rule in the order of the stylesheet, a json object keeps the order it's written in
+struct JsonRules<'a>(Vec<(String, JsonRule<'a>)>);
+
+impl Serialize for JsonRules<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map(self.0.iter().map(|(class, rule)| (class, rule)))
+    }
+}
+
+/// every rule as `"class": {"selectorSuffix": ":hover", "atRules": [...], "declarations": {...}}`
+/// with nesting resolved, utilities that generate more than one rule list the others in
+/// `"nested"`
+pub fn defs_to_json(defs: &[&CssDef]) -> String {
+    let sheets = defs
+        .iter()
+        .filter_map(|css_def| Some((*css_def, NormalizedStylesheet::parse(&css_def.to_css())?)))
+        .collect::<Vec<_>>();
+    let no_declarations = BTreeMap::new();
+    let rules = sheets
+        .iter()
+        .map(|(css_def, sheet)| {
+            let class_selector = format!(".{}", css_def.emitted_class_name());
+            let mut rules = sheet.rules.iter().map(|(key, declarations)| {
+                let selector_suffix = match key.selector.find(&class_selector) {
+                    Some(start) => &key.selector[start + class_selector.len()..],
+                    None => key.selector.as_str(),
+                };
+                JsonRule {
+                    selector_suffix,
+                    at_rules: &key.at_rules,
+                    declarations,
+                    nested: Vec::new(),
+                }
+            });
+
+            let mut rule = rules.next().unwrap_or(JsonRule {
+                selector_suffix: "",
+                at_rules: &[],
+                declarations: &no_declarations,
+                nested: Vec::new(),
+            });
+            rule.nested = rules.collect();
+            (unescape_class_name(&css_def.class_name), rule)
+        })
+        .collect();
+    serde_json::to_string_pretty(&JsonRules(rules)).expect("rules are serializable") + "\n"
+}
+
+/// a stylesheet as rust source, `pub const STYLES: &str = r#"..."#;` with `name` as the name of
+/// the constant, so apps without an asset pipeline can `include!` it
+pub fn rust_const(css: &str, name: &str) -> String {
+    // one `#` more than follows any `"` in the css, so it can't end the raw string
+    let hashes = css
+        .match_indices('"')
+        .map(|(i, _)| css[i + 1..].len() - css[i + 1..].trim_start_matches('#').len())
+        .max()
+        .map_or(1, |longest| longest + 1);
+    let hashes = "#".repeat(hashes);
+    format!("pub const {name}: &str = r{hashes}\"{css}\"{hashes};\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv, rust_const};
+
+    #[test]
+    fn test_defs_to_json() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.compile_classes(&CompileOptions::default(), ["hover:bg-red-500", "md:p-4"]);
+        let json = env.defs_to_json();
+        // in the order of the stylesheet
+        assert!(json.find("\"hover:bg-red-500\"") < json.find("\"md:p-4\""));
+        let json = serde_json::from_str::<serde_json::Value>(&json).unwrap();
+        assert_eq!(
+            json["hover:bg-red-500"],
+            serde_json::json!({
+                "selectorSuffix": ":where(:hover)",
+                "atRules": ["@media (hover: hover)"],
+                "declarations": {"background-color": "oklch(63.7% 0.237 25.331)"}
+            })
+        );
+        assert_eq!(
+            json["md:p-4"],
+            serde_json::json!({
+                "selectorSuffix": "",
+                "atRules": ["@media (width >= 48rem)"],
+                "declarations": {"padding": "calc(var(--spacing) * 4)"}
+            })
+        );
+    }
+
+    #[test]
+    fn test_rust_const() {
+        assert_eq!(
+            rust_const(".a{}\n", "STYLES"),
+            "pub const STYLES: &str = r#\".a{}\n\"#;\n"
+        );
+        assert_eq!(
+            rust_const("a[title=\"#\"]{}", "CSS"),
+            "pub const CSS: &str = r##\"a[title=\"#\"]{}\"##;\n"
+        );
+    }
+}
@@ -0,0 +1,171 @@
+//! pulls the parts of a document that can contain classes out of it, so prose doesn't end up
+//! being scanned for candidates
+
+/// fenced code blocks with one of these info strings are markup and get scanned
+const MARKUP_FENCE_LANGUAGES: &[&str] = &["html", "jsx", "tsx", "vue", "svelte", "astro", "xml"];
+
+fn is_fence(line: &str) -> Option<(&str, usize)> {
+    let trimmed = line.trim_start();
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let fence_len = trimmed.chars().take_while(|c| *c == fence_char).count();
+    (fence_len >= 3).then_some((&trimmed[..fence_len], fence_len))
+}
+
+/// the end of the tag starting at `start` (which has to be a `<`), quotes and `{...}`
+/// expressions in mdx attributes may contain `>`
+fn tag_end(src: &str, start: usize) -> Option<usize> {
+    let mut quote = None;
+    let mut brace_depth = 0usize;
+    for (i, c) in src[start + 1..].char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'' | '`') => quote = Some(c),
+            (None, '{') => brace_depth += 1,
+            (None, '}') => brace_depth = brace_depth.saturating_sub(1),
+            (None, '>') if brace_depth == 0 => return Some(start + 1 + i + 1),
+            (None, '<') if brace_depth == 0 => return None,
+            _ => {}
+        }
+    }
+    None
+}
+
+/// copies the html/jsx tags (with their attributes) from `prose` into `res` and leaves the rest
+/// blank, inline code spans are skipped
+fn extract_tags(prose: &str, res: &mut [u8]) {
+    let bytes = prose.as_bytes();
+    let mut i = 0;
+    while i < prose.len() {
+        match bytes[i] {
+            b'`' => {
+                let ticks = prose[i..].bytes().take_while(|b| *b == b'`').count();
+                let closing = &prose[i..i + ticks];
+                i = match prose[i + ticks..].find(closing) {
+                    Some(end) => i + ticks + end + ticks,
+                    None => i + ticks,
+                };
+            }
+            b'<' if bytes
+                .get(i + 1)
+                .is_some_and(|b| b.is_ascii_alphabetic() || *b == b'/') =>
+            {
+                if let Some(end) = tag_end(prose, i) {
+                    res[i..end].copy_from_slice(&bytes[i..end]);
+                    i = end;
+                } else {
+                    i += 1;
+                }
+            }
+            _ => i += 1,
+        }
+    }
+}
+
+fn blank(bytes: &mut [u8]) {
+    for b in bytes.iter_mut().filter(|b| **b != b'\n') {
+        *b = b' ';
+    }
+}
+
+/// the parts of a markdown or mdx document classes can appear in: inline html, mdx component
+/// tags and fenced code blocks marked as html/jsx. everything else is replaced with spaces, so
+/// byte offsets and line numbers still match the source
+pub fn extract_markdown(src: &str) -> String {
+    let mut res = src.as_bytes().to_vec();
+    blank(&mut res);
+    // the document without code blocks and mdx imports, for finding tags
+    let mut prose = src.as_bytes().to_vec();
+
+    let mut offset = 0;
+    let mut lines = src.split_inclusive('\n');
+    while let Some(line) = lines.next() {
+        let line_start = offset;
+        offset += line.len();
+
+        if let Some((fence, fence_len)) = is_fence(line) {
+            let language = line.trim_start()[fence_len..]
+                .trim()
+                .split(|c: char| c.is_whitespace() || c == '{')
+                .next()
+                .unwrap_or_default()
+                .to_ascii_lowercase();
+            let is_markup = MARKUP_FENCE_LANGUAGES.contains(&language.as_str());
+
+            let code_start = offset;
+            for code_line in lines.by_ref() {
+                let code_line_start = offset;
+                offset += code_line.len();
+                if is_fence(code_line).is_some_and(|(closing, _)| closing.starts_with(fence)) {
+                    if is_markup {
+                        res[code_start..code_line_start]
+                            .copy_from_slice(&src.as_bytes()[code_start..code_line_start]);
+                    }
+                    break;
+                }
+                if is_markup && offset == src.len() {
+                    res[code_start..offset].copy_from_slice(&src.as_bytes()[code_start..offset]);
+                }
+            }
+            blank(&mut prose[line_start..offset]);
+            continue;
+        }
+
+        // mdx imports and exports are javascript, not markup
+        let trimmed = line.trim_start();
+        if trimmed.starts_with("import ") || trimmed.starts_with("export ") {
+            blank(&mut prose[line_start..offset]);
+        }
+    }
+
+    let prose = String::from_utf8(prose).expect("only whole lines are blanked");
+    extract_tags(&prose, &mut res);
+    String::from_utf8(res).expect("only whole lines and tags are copied")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::extract::extract_markdown;
+
+    #[test]
+    fn test_extract_markdown() {
+        let src = r#"import { Card } from "./card";
+
+# Some flex title
+
+This text mentions container and grid but those aren't classes, `<b class="code-span">`.
+
+<div class="p-4
+  bg-red-500">inline html</div>
+
+<Card className="shadow-lg" onClick={() => a > b}>
+  text
+</Card>
+
+```html
+<span class="text-sm">hi</span>
+```
+
+```rust
+let x = "hidden";
+```
+"#;
+        let extracted = extract_markdown(src);
+        assert_eq!(extracted.len(), src.len());
+        assert_eq!(extracted.lines().count(), src.lines().count());
+        assert!(extracted.contains("<div class=\"p-4\n  bg-red-500\">"));
+        assert!(extracted.contains("<Card className=\"shadow-lg\" onClick={() => a > b}>"));
+        assert!(extracted.contains("</Card>"));
+        assert!(extracted.contains("<span class=\"text-sm\">hi</span>"));
+        for noise in [
+            "container",
+            "flex",
+            "code-span",
+            "hidden",
+            "import",
+            "inline html",
+        ] {
+            assert!(!extracted.contains(noise), "{noise} should be skipped");
+        }
+    }
+}
@@ -0,0 +1,270 @@
+use crate::scoping::split_selector_list;
+
+/// a declaration or a block of a rule body
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) enum Item<'a> {
+    Declaration(&'a str),
+    Block {
+        /// a selector list or an at-rule
+        prelude: &'a str,
+        items: Vec<Item<'a>>,
+        /// the whole block with its prelude as written
+        source: &'a str,
+    },
+}
+
+/// the end of the quoted string or comment starting at `start`, `None` if there's none
+fn skip_quoted(css: &str, start: usize) -> Option<usize> {
+    let rest = &css[start..];
+    if let Some(comment) = rest.strip_prefix("/*") {
+        return Some(comment.find("*/").map_or(css.len(), |end| start + end + 4));
+    }
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let mut escaped = false;
+    for (i, c) in rest.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            c if c == quote => return Some(start + i + 1),
+            _ => {}
+        }
+    }
+    Some(css.len())
+}
+
+/// the declarations and blocks of `css`, comments are dropped
+pub(crate) fn parse_items(css: &str) -> Vec<Item<'_>> {
+    let mut res = Vec::new();
+    let mut segment_start = 0;
+    let mut i = 0;
+    while i < css.len() {
+        if let Some(end) = skip_quoted(css, i) {
+            // comments aren't part of the declaration or selector after them
+            if css[i..].starts_with("/*") && css[segment_start..i].trim().is_empty() {
+                segment_start = end;
+            }
+            i = end;
+            continue;
+        }
+        let c = css[i..].chars().next().expect("in bounds");
+        match c {
+            '{' => {
+                let mut depth = 1;
+                let mut j = i + 1;
+                while j < css.len() && depth > 0 {
+                    if let Some(end) = skip_quoted(css, j) {
+                        j = end;
+                        continue;
+                    }
+                    match css.as_bytes()[j] {
+                        b'{' => depth += 1,
+                        b'}' => depth -= 1,
+                        _ => {}
+                    }
+                    j += 1;
+                }
+                let inner_end = if depth == 0 { j - 1 } else { j };
+                let prelude = &css[segment_start..i];
+                let prelude_start = segment_start + prelude.len() - prelude.trim_start().len();
+                res.push(Item::Block {
+                    prelude: prelude.trim(),
+                    items: parse_items(&css[i + 1..inner_end]),
+                    source: &css[prelude_start..j],
+                });
+                i = j;
+                segment_start = j;
+                continue;
+            }
+            ';' => {
+                let declaration = css[segment_start..i].trim();
+                if !declaration.is_empty() {
+                    res.push(Item::Declaration(declaration));
+                }
+                segment_start = i + 1;
+            }
+            _ => {}
+        }
+        i += c.len_utf8();
+    }
+    let declaration = css[segment_start..].trim();
+    if !declaration.is_empty() {
+        res.push(Item::Declaration(declaration));
+    }
+    res
+}
+
+/// `&:hover` in `.a` -> `.a:hover`, selectors without `&` are descendants of the parent.
+/// `&` in quotes is left alone
+fn resolve_selector(parent: &str, nested: &str) -> String {
+    if !nested.contains('&') {
+        return format!("{parent} {nested}");
+    }
+    let mut res = String::with_capacity(nested.len() + parent.len());
+    let mut i = 0;
+    while i < nested.len() {
+        if let Some(end) = skip_quoted(nested, i) {
+            res.push_str(&nested[i..end]);
+            i = end;
+            continue;
+        }
+        let c = nested[i..].chars().next().expect("in bounds");
+        if c == '&' {
+            res.push_str(parent);
+        } else {
+            res.push(c);
+        }
+        i += c.len_utf8();
+    }
+    res
+}
+
+/// appends the rules for `items` nested in `selectors` and `at_rules`, declarations between
+/// nested blocks end up in a rule of their own so the order stays the same
+fn flatten_items(selectors: &[String], at_rules: &[&str], items: &[Item], res: &mut String) {
+    let mut declarations = Vec::new();
+    let flush = |declarations: &mut Vec<&str>, res: &mut String| {
+        if declarations.is_empty() {
+            return;
+        }
+        for at_rule in at_rules {
+            res.push_str(&format!("{at_rule} {{\n"));
+        }
+        res.push_str(&format!("{} {{\n", selectors.join(",\n")));
+        for declaration in declarations.drain(..) {
+            res.push_str(&format!("{declaration};\n"));
+        }
+        res.push_str(&"}\n".repeat(at_rules.len() + 1));
+    };
+    for item in items {
+        match item {
+            Item::Declaration(declaration) => declarations.push(*declaration),
+            Item::Block {
+                prelude,
+                items: children,
+                ..
+            } => {
+                flush(&mut declarations, res);
+                if prelude.starts_with('@') {
+                    let mut at_rules = at_rules.to_vec();
+                    at_rules.push(prelude);
+                    flatten_items(selectors, &at_rules, children, res);
+                } else {
+                    let nested = split_selector_list(prelude)
+                        .into_iter()
+                        .flat_map(|nested| {
+                            selectors
+                                .iter()
+                                .map(move |parent| resolve_selector(parent, nested))
+                        })
+                        .collect::<Vec<_>>();
+                    flatten_items(&nested, at_rules, children, res);
+                }
+            }
+        }
+    }
+    flush(&mut declarations, res);
+}
+
+/// rewrites nested rules into plain rules and hoists the at-rules in them, for browsers without
+/// css nesting: `.a{ &:hover { @media (x) { color: red } } }` ->
+/// `@media (x) { .a:hover { color: red; } }`. at-rules at the top level like `@keyframes` stay
+/// as they are
+pub fn flatten_nesting(css: &str) -> String {
+    let mut res = String::with_capacity(css.len());
+    for item in parse_items(css) {
+        match item {
+            Item::Block {
+                prelude,
+                items,
+                source,
+            } => {
+                if prelude.starts_with('@') {
+                    // the blocks of at-rules don't have to contain rules
+                    res.push_str(source);
+                    res.push('\n');
+                } else {
+                    let selectors = split_selector_list(prelude)
+                        .into_iter()
+                        .map(String::from)
+                        .collect::<Vec<_>>();
+                    flatten_items(&selectors, &[], &items, &mut res);
+                }
+            }
+            Item::Declaration(declaration) => {
+                res.push_str(declaration);
+                res.push_str(";\n");
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv, flatten::flatten_nesting};
+
+    #[test]
+    fn test_flatten_nesting() {
+        assert_eq!(
+            flatten_nesting(
+                ".md\\:hover\\:p-4{
+    @media (hover: hover) {
+        &:hover {
+            @media (width >= 48rem) {
+    padding: 1rem;
+}
+        }
+    }
+}"
+            ),
+            "@media (hover: hover) {
+@media (width >= 48rem) {
+.md\\:hover\\:p-4:hover {
+padding: 1rem;
+}
+}
+}
+"
+        );
+        assert_eq!(
+            flatten_nesting(
+                ".a, .b{ color: red; & > *, &:is([title=\"&{\"]) { margin: 0 } /* c */ top: 0 }"
+            ),
+            ".a,
+.b {
+color: red;
+}
+.a > *,
+.b > *,
+.a:is([title=\"&{\"]),
+.b:is([title=\"&{\"]) {
+margin: 0;
+}
+.a,
+.b {
+top: 0;
+}
+"
+        );
+        assert_eq!(
+            flatten_nesting(".x{ .y { color: red } }"),
+            ".x .y {\ncolor: red;\n}\n"
+        );
+        let keyframes = "@keyframes spin {\n  to { transform: rotate(360deg); }\n}";
+        assert_eq!(flatten_nesting(keyframes), format!("{keyframes}\n"));
+    }
+
+    #[test]
+    fn test_flattened_stylesheet() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.flatten_nesting = true;
+        env.options.no_theme_vars = true;
+        env.compile_classes(&CompileOptions::default(), ["hover:flex", "*:p-4"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(
+            "@media (hover: hover) {\n.hover\\:flex:where(:hover) {\ndisplay: flex;\n}\n}"
+        ));
+        assert!(css.contains(".\\*\\:p-4 > * {\n"));
+        assert!(!css.contains('&'), "{css}");
+    }
+}
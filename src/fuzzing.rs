@@ -0,0 +1,210 @@
+//! `Arbitrary` inputs and panic-free entry points for fuzzing the lexer and the parsers, see the
+//! `fuzz` directory for the cargo-fuzz targets
+
+use arbitrary::{Arbitrary, Unstructured};
+use chumsky::Parser;
+
+use crate::{
+    CompileOptions, EmitEnv,
+    config_css::config_parser,
+    lexer::lexer,
+    parser::{duckwind_parser, make_eoi, make_input},
+};
+
+const VARIANTS: &[&str] = &[
+    "hover",
+    "md",
+    "dark",
+    "*",
+    "**",
+    "before",
+    "@md",
+    "@min-[400px]",
+    "min-[100px]",
+    "max-[100px]",
+    "group-hover",
+    "group-has-checked",
+    "group-has-[.a]/name",
+    "peer-checked",
+    "peer-not-focus",
+    "in-focus",
+    "not-first",
+    "not-supports-grid",
+    "has-[>img]",
+    "aria-checked",
+    "aria-[sort=ascending]",
+    "data-active",
+    "data-[state=open]",
+    "nth-[3]",
+    "supports-[display:grid]",
+    "[&>li]",
+    "[::marker]",
+];
+
+const UTILITIES: &[&str] = &[
+    "flex",
+    "p-4",
+    "-mt-4",
+    "m-[calc(1px+2px)]",
+    "w-[100px]",
+    "w-1/2",
+    "bg-red-500",
+    "bg-red-500/50",
+    "bg-[#ff0000]",
+    "text-lg/7",
+    "text-sm/[1.5]",
+    "text-[red]",
+    "grid-cols-3",
+    "aspect-[16/9]",
+    "[color:red]",
+    "content-[hello_world]",
+    "(p-4,hover:bg-red-500)",
+];
+
+const CANDIDATE_CHARS: &[char] = &[
+    '-', ':', '[', ']', '(', ')', '/', '_', '\\', '*', '#', '@', '.', '%', '!', '&', '>', '~', '+',
+    '=', ' ', '\n', 'a', '1', 'é', ',',
+];
+
+const CONFIG_FRAGMENTS: &[&str] = &[
+    "@utility tab-* {\n    tab-size: --value(--tab-size-*, integer, [integer]);\n}\n",
+    "@utility box {\n    box-sizing: border-box;\n    @tw-property --tw-box 0 <length>;\n}\n",
+    "@utility x-* {\n    &:hover {\n        x: --value(number);\n    }\n}\n",
+    "@custom-variant pointer-fine (@media (pointer: fine));\n",
+    "@custom-variant hocus {\n    &:hover, &:focus {\n        @slot;\n    }\n}\n",
+    "@theme {\n    --tab-size-2: 2;\n    --color-brand: var(--color-red-500);\n}\n",
+    "@theme {\n    @keyframes wiggle {\n        50% { rotate: 3deg; }\n    }\n}\n",
+    "@preset forms;\n",
+    "@alias-variant parent-hover = group-hover;\n",
+    "@pseudo-element file = file-selector-button;\n",
+];
+
+const CONFIG_CHARS: &[char] = &[
+    '@', '{', '}', '(', ')', ';', ':', '-', '*', '"', ',', '[', ']', ' ', '\n',
+];
+
+fn mutate(u: &mut Unstructured<'_>, mut s: String, chars: &[char]) -> arbitrary::Result<String> {
+    for _ in 0..u.int_in_range(0..=3)? {
+        let mut idx = u.choose_index(s.len() + 1)?;
+        while !s.is_char_boundary(idx) {
+            idx -= 1;
+        }
+        match u.int_in_range(0..=2)? {
+            0 => s.insert(idx, *u.choose(chars)?),
+            1 => s.truncate(idx),
+            _ => {
+                if idx < s.len() {
+                    s.remove(idx);
+                }
+            }
+        }
+    }
+    Ok(s)
+}
+
+/// something that looks like a class candidate: a stack of variants followed by a utility,
+/// possibly with a few characters inserted or removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct Candidate(pub String);
+
+impl<'a> Arbitrary<'a> for Candidate {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut parts = Vec::new();
+        for _ in 0..u.int_in_range(0..=3)? {
+            parts.push(*u.choose(VARIANTS)?);
+        }
+        parts.push(u.choose(UTILITIES)?);
+        let candidate = mutate(u, parts.join(":"), CANDIDATE_CHARS)?;
+        Ok(Candidate(candidate))
+    }
+}
+
+/// a config built from known good `@utility`, `@custom-variant` and `@theme` fragments, possibly
+/// with a few characters inserted or removed
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConfigFragment(pub String);
+
+impl<'a> Arbitrary<'a> for ConfigFragment {
+    fn arbitrary(u: &mut Unstructured<'a>) -> arbitrary::Result<Self> {
+        let mut config = String::new();
+        for _ in 0..u.int_in_range(1..=3)? {
+            config.push_str(u.choose(CONFIG_FRAGMENTS)?);
+        }
+        let config = mutate(u, config, CONFIG_CHARS)?;
+        Ok(ConfigFragment(config))
+    }
+}
+
+fn leak(src: &str) -> &'static str {
+    src.to_string().leak()
+}
+
+pub fn fuzz_lexer(src: &str) {
+    let _ = lexer("fuzz", leak(src)).parse(src);
+}
+
+pub fn fuzz_parser(src: &str) {
+    let leaked = leak(src);
+    if let Some((toks, _)) = lexer("fuzz", leaked).parse(leaked).into_output() {
+        let _ = duckwind_parser(make_input)
+            .parse(make_input(make_eoi("fuzz", leaked), toks.as_slice()));
+    }
+}
+
+pub fn fuzz_config_parser(src: &str) {
+    let _ = config_parser().parse(src);
+}
+
+/// runs the candidates through the whole pipeline, from scanning to the stylesheet
+pub fn fuzz_emit(env: &EmitEnv, src: &str) {
+    let mut env = env.clone();
+    env.parse_full_string(&CompileOptions::default(), src);
+    let _ = env.to_css_stylesheet(false);
+}
+
+#[cfg(test)]
+mod tests {
+    use arbitrary::{Arbitrary, Unstructured};
+
+    use crate::{
+        EmitEnv,
+        fuzzing::{
+            Candidate, ConfigFragment, fuzz_config_parser, fuzz_emit, fuzz_lexer, fuzz_parser,
+        },
+    };
+
+    /// xorshift, good enough to get reproducible bytes for `Unstructured`
+    fn pseudo_random_bytes(seed: u64, len: usize) -> Vec<u8> {
+        let mut state = seed.wrapping_mul(0x9E3779B97F4A7C15) | 1;
+        (0..len)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 7;
+                state ^= state << 17;
+                state as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_arbitrary_candidates_dont_panic() {
+        let env = EmitEnv::new_with_default_config();
+        for seed in 0..500 {
+            let bytes = pseudo_random_bytes(seed, 64);
+            let candidate = Candidate::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            fuzz_lexer(&candidate.0);
+            fuzz_parser(&candidate.0);
+            fuzz_emit(&env, &candidate.0);
+        }
+    }
+
+    #[test]
+    fn test_arbitrary_configs_dont_panic() {
+        for seed in 0..500 {
+            let bytes = pseudo_random_bytes(seed, 64);
+            let config = ConfigFragment::arbitrary(&mut Unstructured::new(&bytes)).unwrap();
+            fuzz_config_parser(&config.0);
+            EmitEnv::new().load_config(&config.0);
+        }
+    }
+}
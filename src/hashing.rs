@@ -0,0 +1,112 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde::Serialize;
+
+use crate::{EmitEnv, escape_string_for_css, export::unescape_class_name};
+
+/// generated class names start with this in hashed mode
+pub const HASHED_CLASS_PREFIX: &str = "dw-";
+
+/// characters after the prefix, more are used when two classes collide
+const HASH_LEN: usize = 5;
+
+fn fnv1a(s: &str) -> u64 {
+    s.bytes().fold(0xcbf29ce484222325, |hash, b| {
+        (hash ^ u64::from(b)).wrapping_mul(0x100000001b3)
+    })
+}
+
+fn to_base36(mut n: u64) -> String {
+    const DIGITS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+    let mut res = Vec::new();
+    while n > 0 || res.is_empty() {
+        res.push(DIGITS[(n % 36) as usize]);
+        n /= 36;
+    }
+    res.reverse();
+    String::from_utf8(res).expect("digits are ascii")
+}
+
+/// class as written -> the short name it was renamed to, see `EmitEnv::hash_class_names`
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+#[serde(transparent)]
+pub struct HashedClassNames {
+    pub names: BTreeMap<String, String>,
+}
+
+impl HashedClassNames {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("class names are serializable") + "\n"
+    }
+}
+
+impl EmitEnv {
+    /// renames every generated class to a short hash of its name (`dw-x7f3a`), so the
+    /// stylesheet can't collide with the classes of a host page. the names only depend on the
+    /// class unless two hashes collide. the rules keep their `class_name`, so the env can go on
+    /// compiling and be hashed again, rules that were hashed before keep their names
+    pub fn hash_class_names(&mut self) -> HashedClassNames {
+        let mut res = HashedClassNames::default();
+        let mut taken = self
+            .defs
+            .iter()
+            .filter_map(|css_def| Some(unescape_class_name(css_def.hashed_name.as_ref()?)))
+            .collect::<HashSet<_>>();
+        for css_def in self.defs.iter_mut() {
+            let class = unescape_class_name(&css_def.class_name);
+            if let Some(hashed) = &css_def.hashed_name {
+                res.names.insert(class, unescape_class_name(hashed));
+                continue;
+            }
+            let hash = to_base36(fnv1a(&class));
+            let hashed = (HASH_LEN..=hash.len())
+                .map(|len| format!("{HASHED_CLASS_PREFIX}{}", &hash[..len]))
+                .find(|hashed| !taken.contains(hashed))
+                .unwrap_or_else(|| format!("{HASHED_CLASS_PREFIX}{hash}{}", taken.len()));
+            taken.insert(hashed.clone());
+            css_def.hashed_name = Some(escape_string_for_css(&hashed));
+            res.names.insert(class, hashed);
+        }
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv};
+
+    #[test]
+    fn test_hash_class_names() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.compile_classes(&CompileOptions::default(), ["hover:bg-red-500", "p-4"]);
+        let hashed = env.hash_class_names();
+
+        let name = &hashed.names["hover:bg-red-500"];
+        assert!(name.starts_with("dw-") && name.len() == 8);
+        assert_ne!(name, &hashed.names["p-4"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(&format!(".{name}{{")));
+        assert!(!css.contains("hover\\:bg-red-500"));
+
+        // same names on every run
+        let mut other = EmitEnv::new_with_default_config();
+        other.compile_classes(&CompileOptions::default(), ["p-4"]);
+        assert_eq!(other.hash_class_names().names["p-4"], hashed.names["p-4"]);
+        assert!(
+            hashed
+                .to_json()
+                .contains(&format!("\"p-4\": \"{}\"", hashed.names["p-4"]))
+        );
+
+        // classes compiled later are hashed on the next call, the others keep their names
+        assert!(
+            env.compile_classes(&CompileOptions::default(), ["p-4"])
+                .is_empty()
+        );
+        env.compile_classes(&CompileOptions::default(), ["flex"]);
+        let again = env.hash_class_names();
+        assert_eq!(again.names["p-4"], hashed.names["p-4"]);
+        assert!(again.names["flex"].starts_with("dw-"));
+        assert_eq!(again.names.len(), 3);
+    }
+}
@@ -0,0 +1,65 @@
+/// byte offset of the first `needle` in `haystack`, ignoring ascii case
+fn find_ignore_case(haystack: &str, needle: &str) -> Option<usize> {
+    haystack
+        .as_bytes()
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+}
+
+/// the end of the opening tag `<name ...>`, `<header>` doesn't count as `<head>`
+fn opening_tag_end(html: &str, name: &str) -> Option<usize> {
+    let mut offset = 0;
+    while let Some(start) = find_ignore_case(&html[offset..], &format!("<{name}")) {
+        let after_name = offset + start + name.len() + 1;
+        if html[after_name..].starts_with(|c: char| c == '>' || c.is_ascii_whitespace()) {
+            return Some(after_name + html[after_name..].find('>')? + 1);
+        }
+        offset = after_name;
+    }
+    None
+}
+
+/// adds a `<style>` element with `css` at the end of the document's `<head>`, a `<head>` is
+/// created if there is none
+pub fn inject_stylesheet(html: &str, css: &str) -> String {
+    let style = format!("<style>\n{}\n</style>\n", css.trim_end());
+    let (at, insert) = if let Some(head_end) = find_ignore_case(html, "</head>") {
+        (head_end, style)
+    } else if let Some(head_start) = opening_tag_end(html, "head") {
+        (head_start, style)
+    } else if let Some(html_start) = opening_tag_end(html, "html") {
+        (html_start, format!("<head>\n{style}</head>\n"))
+    } else {
+        (0, format!("<head>\n{style}</head>\n"))
+    };
+
+    let mut res = String::with_capacity(html.len() + insert.len());
+    res.push_str(&html[..at]);
+    res.push_str(&insert);
+    res.push_str(&html[at..]);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inline::inject_stylesheet;
+
+    #[test]
+    fn test_inject_stylesheet() {
+        assert_eq!(
+            inject_stylesheet(
+                "<html><HEAD><title>x</title></HEAD><body></body></html>",
+                ".p-4{}\n"
+            ),
+            "<html><HEAD><title>x</title><style>\n.p-4{}\n</style>\n</HEAD><body></body></html>"
+        );
+        assert_eq!(
+            inject_stylesheet("<html lang=\"en\"><header></header></html>", ".p-4{}"),
+            "<html lang=\"en\"><head>\n<style>\n.p-4{}\n</style>\n</head>\n<header></header></html>"
+        );
+        assert_eq!(
+            inject_stylesheet("<p>hi</p>", ".p-4{}"),
+            "<head>\n<style>\n.p-4{}\n</style>\n</head>\n<p>hi</p>"
+        );
+    }
+}
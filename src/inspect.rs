@@ -0,0 +1,116 @@
+//! breaks a class up into its parts without looking at any config, for tools that sort or lint
+//! classes
+
+use chumsky::Parser;
+
+use crate::{
+    lexer::{Spanned, lexer},
+    parser::{ParsedUnit, duckwind_parser, make_eoi, make_input},
+};
+
+/// `hover`, `data-[open]`, `group-hover/card`: the segments of a variant joined like they're
+/// written, arbitrary ones in brackets
+pub(crate) fn variant_name(variant: &[Spanned<ParsedUnit>]) -> String {
+    variant
+        .iter()
+        .map(|(unit, _)| match unit {
+            ParsedUnit::String(s) => s.clone(),
+            ParsedUnit::Raw(raw) => format!("[{raw}]"),
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// the parts of a class as written, see `parse_candidate`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct ParsedCandidate {
+    /// outermost first, `md:hover:p-4` -> `["md", "hover"]`
+    pub variants: Vec<String>,
+    /// the named segments of the utility, `-mt-4` -> `["-mt", "4"]`. empty for variant groups
+    /// and arbitrary properties
+    pub utility: Vec<String>,
+    /// `bg-[#fff]` -> `#fff`, also the whole declaration of arbitrary properties like
+    /// `[color:red]`. underscores are spaces already
+    pub arbitrary_value: Option<String>,
+    /// what comes after the `/` of the last segment, `bg-red-500/50` -> `50`
+    pub modifier: Option<String>,
+    /// written with `!` in front of the utility or at the end
+    pub important: bool,
+    /// the members of a variant group like `hover:(bg-red-500,text-white)`
+    pub group: Option<Vec<String>>,
+}
+
+/// parses a single class into its parts without resolving it against a config, `None` if it
+/// isn't a class at all. the class has to be the whole string, prefixes aren't stripped
+pub fn parse_candidate(candidate: &str) -> Option<ParsedCandidate> {
+    // the spans are never shown, so there's no need for the source to outlive the call
+    let (toks, end) = lexer("candidate", "").parse(candidate).into_output()?;
+    if end != candidate.len() {
+        return None;
+    }
+    let (parsed, _) = duckwind_parser(make_input)
+        .parse(make_input(make_eoi("candidate", ""), toks.as_slice()))
+        .into_output()?;
+
+    let mut res = ParsedCandidate {
+        variants: parsed.variants.iter().map(|v| variant_name(v)).collect(),
+        important: parsed.important,
+        group: parsed.group,
+        ..Default::default()
+    };
+    for (i, (unit, _)) in parsed.utility.iter().enumerate() {
+        match unit {
+            ParsedUnit::String(s) if i + 1 == parsed.utility.len() => match s.split_once('/') {
+                Some((segment, modifier)) => {
+                    res.utility.push(segment.to_string());
+                    res.modifier = Some(modifier.to_string());
+                }
+                None => res.utility.push(s.clone()),
+            },
+            ParsedUnit::String(s) => res.utility.push(s.clone()),
+            ParsedUnit::Raw(raw) => res.arbitrary_value = Some(raw.clone()),
+        }
+    }
+    Some(res)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::inspect::{ParsedCandidate, parse_candidate};
+
+    #[test]
+    fn test_parse_candidate() {
+        assert_eq!(
+            parse_candidate("md:hover:bg-red-500/50!"),
+            Some(ParsedCandidate {
+                variants: vec!["md".to_string(), "hover".to_string()],
+                utility: vec!["bg".to_string(), "red".to_string(), "500".to_string()],
+                modifier: Some("50".to_string()),
+                important: true,
+                ..Default::default()
+            })
+        );
+
+        assert!(parse_candidate("hover:!-mt-4").unwrap().important);
+
+        let arbitrary = parse_candidate("data-[state=open]:grid-cols-[1fr_2fr]").unwrap();
+        assert_eq!(arbitrary.variants, ["data-[state=open]"]);
+        assert_eq!(arbitrary.utility, ["grid", "cols"]);
+        assert_eq!(arbitrary.arbitrary_value.as_deref(), Some("1fr 2fr"));
+
+        let property = parse_candidate("[color:red]").unwrap();
+        assert!(property.utility.is_empty());
+        assert_eq!(property.arbitrary_value.as_deref(), Some("color:red"));
+
+        let group = parse_candidate("hover:(p-4,text-white)").unwrap();
+        assert_eq!(
+            group.group,
+            Some(vec!["p-4".to_string(), "text-white".to_string()])
+        );
+
+        // doesn't know `foo`, but it's shaped like a class
+        assert_eq!(parse_candidate("-foo-2").unwrap().utility, ["-foo", "2"]);
+        assert_eq!(parse_candidate("p-4 flex"), None);
+        assert_eq!(parse_candidate(""), None);
+    }
+}
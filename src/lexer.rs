@@ -12,6 +12,7 @@ pub struct Context {
     pub file_contents: &'static str,
 }
 
+#[allow(clippy::upper_case_acronyms)]
 pub type DWS = SimpleSpan<usize, Context>;
 pub type Spanned<T> = (T, DWS);
 
@@ -20,6 +21,10 @@ pub enum Token {
     Ctrl(char),
     Unit(String),
     Raw(String),
+    /// `(--brand)` as `var(--brand)`, only a value after the name of a utility like `bg-(--brand)`
+    Var(String),
+    /// `(bg-red-500,text-white)`, the members of a variant group
+    Group(Vec<String>),
 }
 
 #[allow(dead_code)]
@@ -97,6 +102,83 @@ pub fn parse_raw_text<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<
     })
 }
 
+/// `(--brand)` -> `var(--brand)` and `(color:--brand)` -> `color:var(--brand)`, the custom
+/// property shorthand of `bg-(--brand)`
+pub fn parse_var_shorthand<'a>()
+-> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> + Clone {
+    let ident = any()
+        .filter(|c: &char| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+        .repeated()
+        .at_least(1)
+        .collect::<String>();
+    just('(')
+        .ignore_then(
+            any()
+                .filter(|c: &char| c.is_ascii_lowercase() || *c == '-')
+                .repeated()
+                .at_least(1)
+                .collect::<String>()
+                .then_ignore(just(':'))
+                .or_not(),
+        )
+        .then(just("--").ignore_then(ident))
+        .then_ignore(just(')'))
+        .map(|(data_type, name)| match data_type {
+            Some(data_type) => format!("{data_type}:var(--{name})"),
+            None => format!("var(--{name})"),
+        })
+}
+
+/// `(a,b-[c,d])` -> `["a", "b-[c,d]"]`, brackets and parens in the members are balanced
+pub fn parse_group<'a>() -> impl Parser<'a, &'a str, Vec<String>, extra::Err<Rich<'a, char>>> + Clone
+{
+    let balanced = recursive(|balanced| {
+        choice((
+            just('[')
+                .ignore_then(balanced.clone())
+                .then_ignore(just(']'))
+                .map(|inner| format!("[{inner}]")),
+            just('(')
+                .ignore_then(balanced)
+                .then_ignore(just(')'))
+                .map(|inner| format!("({inner})")),
+            any()
+                .filter(|c: &char| {
+                    !c.is_whitespace() && !matches!(c, '[' | ']' | '(' | ')' | '"' | '\'')
+                })
+                .map(String::from),
+        ))
+        .repeated()
+        .collect::<Vec<String>>()
+        .map(|parts| parts.concat())
+    });
+
+    just('(')
+        .ignore_then(balanced)
+        .then_ignore(just(')'))
+        .try_map(|inner: String, span| {
+            let mut members = Vec::new();
+            let mut member_start = 0;
+            let mut depth = 0usize;
+            for (i, c) in inner.char_indices() {
+                match c {
+                    '[' | '(' => depth += 1,
+                    ']' | ')' => depth -= 1,
+                    ',' if depth == 0 => {
+                        members.push(inner[member_start..i].to_string());
+                        member_start = i + 1;
+                    }
+                    _ => {}
+                }
+            }
+            members.push(inner[member_start..].to_string());
+            if members.iter().any(String::is_empty) {
+                return Err(Rich::custom(span, "empty variant group member"));
+            }
+            Ok(members)
+        })
+}
+
 pub fn parse_unit<'a>() -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> + Clone {
     any()
         .filter(|c: &char| {
@@ -121,16 +203,15 @@ pub fn lexer<'a>(
 ) -> impl Parser<'a, &'a str, (Vec<Spanned<Token>>, usize), extra::Err<Rich<'a, char>>> + Clone {
     choice((
         parse_raw_text().map(Token::Raw),
+        parse_var_shorthand().map(Token::Var),
+        parse_group().map(Token::Group),
         parse_unit().map(Token::Unit),
         any()
             .and_is(just("\\_"))
             .map(|_| Token::Ctrl('_'))
             .then_ignore(just("_")),
         any()
-            .filter(|x| match *x {
-                '-' | '*' | '[' | ']' | '(' | ')' | '_' | ':' => true,
-                _ => false,
-            })
+            .filter(|x| matches!(*x, '-' | '*' | '[' | ']' | '(' | ')' | '_' | ':' | '!'))
             .map(|c| if c == '_' { ' ' } else { c })
             .map(Token::Ctrl),
         // choice((just(" "), just("\n"), just("\t")))
@@ -224,9 +305,6 @@ mod tests {
                     Token::Unit("p".to_string()),
                     Token::Ctrl('-'),
                     Token::Unit("4".to_string()),
-                    Token::Unit("m".to_string()),
-                    Token::Ctrl('-'),
-                    Token::Unit("8".to_string()),
                 ],
             ),
             (
@@ -235,30 +313,58 @@ mod tests {
                     Token::Unit("p".to_string()),
                     Token::Ctrl('-'),
                     Token::Unit("4".to_string()),
-                    Token::Unit("m".to_string()),
+                ],
+            ),
+            (
+                "hover:(bg-[rgb(1,2,3)],text-white) p-4",
+                vec![
+                    Token::Unit("hover".to_string()),
+                    Token::Ctrl(':'),
+                    Token::Group(vec![
+                        "bg-[rgb(1,2,3)]".to_string(),
+                        "text-white".to_string(),
+                    ]),
+                ],
+            ),
+            (
+                "!p-4!",
+                vec![
+                    Token::Ctrl('!'),
+                    Token::Unit("p".to_string()),
                     Token::Ctrl('-'),
-                    Token::Unit("8".to_string()),
+                    Token::Unit("4".to_string()),
+                    Token::Ctrl('!'),
                 ],
             ),
+            (
+                "bg-(color:--brand)",
+                vec![
+                    Token::Unit("bg".to_string()),
+                    Token::Ctrl('-'),
+                    Token::Var("color:var(--brand)".to_string()),
+                ],
+            ),
+            (
+                "(--x,p-4)",
+                vec![Token::Group(vec!["--x".to_string(), "p-4".to_string()])],
+            ),
             (
                 "p-[100px] m-[3rem]",
                 vec![
                     Token::Unit("p".to_string()),
                     Token::Ctrl('-'),
                     Token::Raw("100px".to_string()),
-                    Token::Unit("m".to_string()),
-                    Token::Ctrl('-'),
-                    Token::Raw("3rem".to_string()),
                 ],
             ),
         ];
 
+        // the lexer only consumes the first candidate, the rest is skipped
         for (src, expected) in test_cases {
             let parser = lexer("test_file", src);
-            let mut result = parser
+            let (mut result, _) = parser
                 .parse(src)
                 .into_result()
-                .expect(&format!("errors lexing {src}"));
+                .unwrap_or_else(|_| panic!("errors lexing {src}"));
             result.iter_mut().for_each(token_to_empty_span);
             // duckwind_parser(Box::leak(Box::new(Default::default())), make_input)
             //     .parse(make_input(make_eoi("", src), &result));
@@ -1,26 +1,105 @@
 use std::{
-    collections::{HashMap, HashSet},
-    ops::Deref,
+    borrow::Cow,
+    cmp::Ordering,
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    ops::{Deref, Range},
+    str::FromStr,
 };
 
 use chumsky::{IterParser, Parser, container::Container, error::Rich, extra, prelude::any};
 
 use crate::{
-    config_css::{Property, Theme, Utility, Variant, config_parser},
+    cache::LruSet,
+    compiler::{OnCandidate, SourceRefs},
+    config_css::{
+        Property, Theme, UserConfig, Utility, ValueType, Variant, config_parser, insert_alpha,
+        is_color, parse_css_data_type, var_references,
+    },
     css_literals::{CssLiteral, data_type_parser},
-    lexer::{DWS, empty_span, lexer},
-    parser::{ParsedUnit, duckwind_parser, make_eoi, make_input},
+    export::unescape_class_name,
+    flatten::{Item, parse_items},
+    inspect::variant_name,
+    lexer::{DWS, Spanned, empty_span, lexer},
+    parser::{Parsed, ParsedUnit, duckwind_parser, make_eoi, make_input},
+    property_order::property_rank,
+    report::{LineIndex, class_attribute_values, dynamic_fragments},
+    scoping::split_selector_list,
 };
 
+mod buckets;
+mod cache;
+mod calc;
+mod color;
+mod compiler;
 mod config_css;
 mod css_literals;
+mod diagnostics;
+mod export;
+mod extract;
+mod flatten;
+#[cfg(feature = "arbitrary")]
+pub mod fuzzing;
+mod hashing;
+mod inline;
+mod inspect;
 mod lexer;
+mod media;
+mod merge;
+mod metrics;
+mod minify;
 mod parser;
+mod plugin;
+mod property_order;
+mod report;
+mod scoping;
+mod source_map;
+mod stylesheet;
+mod targets;
+
+pub use buckets::Bucket;
+pub use calc::fold_calc;
+pub use color::srgb_fallbacks;
+pub use compiler::{CandidateEvent, CandidateOutcome, Compiler, Delta};
+pub use config_css::Doc;
+pub use diagnostics::{ConfigError, Diagnostic};
+pub use export::rust_const;
+pub use extract::extract_markdown;
+pub use flatten::flatten_nesting;
+pub use hashing::{HASHED_CLASS_PREFIX, HashedClassNames};
+pub use inline::inject_stylesheet;
+pub use inspect::{ParsedCandidate, parse_candidate};
+pub use media::merge_media_queries;
+pub use metrics::Metrics;
+pub use minify::minify_css;
+pub use plugin::PLUGIN_ENTRY_POINT;
+pub use report::{Location, UsageReport};
+pub use scoping::{scope_at_rule, scope_stylesheet, shadow_stylesheet};
+pub use source_map::SourceMap;
+pub use stylesheet::{NormalizedStylesheet, RuleKey, StylesheetDifference, diff_stylesheets};
+pub use targets::{BrowserTargets, Feature, Version, lower_media_ranges};
 
 const DEFAULT_CONFIG: &str = include_str!("css/default_config.css");
 const THEME_CONFIG: &str = include_str!("css/theme.css");
 const PREFLIGHT: &str = include_str!("css/preflight.css");
 
+/// how many words that didn't generate a rule are remembered by `parse_full_string`
+const NEGATIVE_CACHE_CAPACITY: usize = 4096;
+
+/// the cascade layers of `EmitOptions::layers` in order, before the ones the configs add with
+/// `@layer name;`. utilities without a layer are in the last one
+const DEFAULT_LAYERS: &[&str] = &["theme", "base", "components", "utilities"];
+
+/// how many `@alias-variant`s may point to other aliases before it's treated as a cycle
+const MAX_VARIANT_ALIAS_DEPTH: usize = 8;
+
+/// config packs that can be loaded with `@preset name;`
+const PRESETS: &[(&str, &str)] = &[
+    #[cfg(feature = "typography")]
+    ("typography", include_str!("css/presets/typography.css")),
+    #[cfg(feature = "forms")]
+    ("forms", include_str!("css/presets/forms.css")),
+];
+
 pub fn ignore_whitespace<'a>()
 -> impl Parser<'a, &'a str, String, extra::Err<Rich<'a, char>>> + Clone {
     any().filter(|c: &char| *c == ' ').repeated().collect()
@@ -38,7 +117,174 @@ pub fn ignore_whitespace2<'a>()
 pub struct CssDef {
     pub pseudo_elements: Vec<String>,
     pub class_name: String,
+    /// the escaped name the rule is emitted under instead of `class_name`, set by
+    /// `EmitEnv::hash_class_names`
+    pub hashed_name: Option<String>,
     pub body: String,
+    /// ancestor selector the rule is nested under, see `CompileOptions::scope_selector`
+    pub scope_selector: Option<String>,
+    /// wrap the class in `:where()`, see `CompileOptions::low_specificity`
+    pub low_specificity: bool,
+    /// every declaration is `!important`, see `CompileOptions::important`
+    pub important: bool,
+    /// properties the utility sets, before variants are applied, used for ordering the rules
+    pub properties: Vec<String>,
+    pub variant_count: usize,
+    /// the variants as written, aliases expanded, like `["md", "hover"]`
+    pub variants: Vec<String>,
+    /// the breakpoint and container variants, `md:@max-lg:` -> `[Min(768), Max(512)]`
+    pub breakpoints: Vec<Breakpoint>,
+    /// `@group` of the utility, the one of the first member for variant groups
+    pub group: Option<String>,
+    /// `@layer` of the utility, `utilities` if it's `None`. see `EmitOptions::layers`
+    pub layer: Option<String>,
+    /// where the class was first found, for rules generated by scanning a named source
+    pub origin: Option<Location>,
+}
+
+/// the width in px of a breakpoint or container variant. `max-*` rules come before `min-*` ones,
+/// the widest first, and rules for wider screens after the ones for narrower screens, so the
+/// narrower `max-*` and the wider `min-*` win like in tailwind
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// `md:`, `@lg:`, `min-[600px]:`
+    Min(usize),
+    /// `max-md:`, `@max-lg:`, `max-[600px]:`
+    Max(usize),
+}
+
+impl Ord for Breakpoint {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self, other) {
+            (Breakpoint::Max(a), Breakpoint::Max(b)) => b.cmp(a),
+            (Breakpoint::Max(_), Breakpoint::Min(_)) => Ordering::Less,
+            (Breakpoint::Min(_), Breakpoint::Max(_)) => Ordering::Greater,
+            (Breakpoint::Min(a), Breakpoint::Min(b)) => a.cmp(b),
+        }
+    }
+}
+
+impl PartialOrd for Breakpoint {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// `48rem` -> `768`, lengths in other units than px, rem and em sort after all others
+fn width_in_px(width: &str) -> usize {
+    let width = width.trim();
+    let (number, factor) = if let Some(rem) = width.strip_suffix("rem") {
+        (rem, 16.0)
+    } else if let Some(em) = width.strip_suffix("em") {
+        (em, 16.0)
+    } else {
+        (width.strip_suffix("px").unwrap_or(width), 1.0)
+    };
+    number
+        .parse::<f64>()
+        .map_or(usize::MAX, |number| (number * factor).round() as usize)
+}
+
+/// the prelude of an arbitrary at-rule variant, `@media(min-width:900px)` ->
+/// `@media (min-width:900px)`
+fn arbitrary_at_rule(raw: &str) -> String {
+    let name_end = raw[1..]
+        .find(|c: char| !is_valid_css_char(c))
+        .map_or(raw.len(), |i| i + 1);
+    let (name, query) = raw.split_at(name_end);
+    format!("{name} {}", query.trim_start())
+}
+
+/// `not (a) and (b)` of the condition `(a) and (b)`, `(a)` of `not (a)`
+fn negated_condition(condition: &str) -> String {
+    let condition = condition.trim();
+    match condition.strip_prefix("not ") {
+        Some(negated) => negated.trim().to_string(),
+        None if condition.contains(" and ") || condition.contains(" or ") => {
+            format!("not ({condition})")
+        }
+        None => format!("not {condition}"),
+    }
+}
+
+/// the opposite of the block a variant wraps a rule in, `None` if there's no single one:
+/// `@media (hover: hover)` -> `@media not all and (hover: hover)`, `&:where(.dark, .dark *)` ->
+/// `&:not(:where(.dark, .dark *))`, `.dark &` -> `&:not(.dark *)`
+fn negate_prelude(prelude: &str) -> Option<String> {
+    if let Some(query) = prelude.strip_prefix("@media ") {
+        let query = query.trim();
+        if query.contains(',') {
+            return None;
+        }
+        return Some(match query.strip_prefix("not ") {
+            Some(negated) => format!("@media {}", negated.trim()),
+            None if query.starts_with('(') => format!("@media not all and {query}"),
+            None => format!("@media not {query}"),
+        });
+    }
+    if let Some(condition) = prelude.strip_prefix("@supports ") {
+        return Some(format!("@supports {}", negated_condition(condition)));
+    }
+    if let Some(query) = prelude.strip_prefix("@container ") {
+        let query = query.trim();
+        // `@container sidebar (width >= 24rem)` is about the container named `sidebar`
+        let (name, condition) = match query.split_once(' ') {
+            Some((name, condition)) if !name.contains('(') && name != "not" => {
+                (format!("{name} "), condition)
+            }
+            _ => (String::new(), query),
+        };
+        return Some(format!("@container {name}{}", negated_condition(condition)));
+    }
+    if prelude.starts_with('@') {
+        return None;
+    }
+
+    let mut negated = Vec::new();
+    for selector in split_selector_list(prelude) {
+        // only selectors that are about `&` itself, not the elements around it
+        let (_, last) = selector.rsplit_once('&')?;
+        let mut depth = 0usize;
+        for c in last.chars() {
+            match c {
+                '(' | '[' => depth += 1,
+                ')' | ']' => depth = depth.saturating_sub(1),
+                ' ' | '>' | '+' | '~' if depth == 0 => return None,
+                _ => {}
+            }
+        }
+        negated.push(match selector.strip_prefix('&') {
+            Some(rest) if !rest.is_empty() && !rest.contains('&') => rest.to_string(),
+            _ => selector.replace('&', "*"),
+        });
+    }
+    Some(format!("&:not({})", negated.join(", ")))
+}
+
+/// `@container sidebar (width >= 24rem)` for the container named `sidebar`, or any container
+fn container_query(name: Option<&str>, condition: &str) -> String {
+    match name {
+        Some(name) => format!("@container {name} {condition}"),
+        None => format!("@container {condition}"),
+    }
+}
+
+/// the container name of `@min-[600px]/sidebar`, a segment of its own after the raw value
+fn container_name(v: &[Spanned<ParsedUnit>]) -> Option<&str> {
+    match v.get(2) {
+        Some((ParsedUnit::String(name), _)) => name.strip_prefix('/'),
+        _ => None,
+    }
+}
+
+/// the width of a `min-width` query like `(min-width:900px)` or `(width >= 900px)`, so the rule
+/// sorts like a breakpoint
+fn min_width_of_query(query: &str) -> Option<&str> {
+    let (_, rest) = query
+        .split_once("min-width:")
+        .or_else(|| query.split_once("width>="))
+        .or_else(|| query.split_once("width >="))?;
+    Some(rest.split(')').next()?.trim())
 }
 
 pub fn is_valid_css_char(c: char) -> bool {
@@ -46,6 +292,375 @@ pub fn is_valid_css_char(c: char) -> bool {
     c.is_ascii_digit() || c.is_ascii_alphabetic() || c == '-' || c == '_' || !c.is_ascii()
 }
 
+/// byte ranges of the declarations (`name: value`, trimmed) in a rule body, nested blocks
+/// included
+pub fn declaration_ranges(body: &str) -> Vec<Range<usize>> {
+    let mut res = Vec::new();
+    let mut finish_declaration = |start: usize, end: usize| {
+        let declaration = &body[start..end];
+        let trimmed = declaration.trim();
+        if trimmed.contains(':') && !trimmed.starts_with('@') {
+            let trimmed_start = start + (declaration.len() - declaration.trim_start().len());
+            res.push(trimmed_start..trimmed_start + trimmed.len());
+        }
+    };
+
+    let mut declaration_start = 0;
+    let mut paren_depth = 0usize;
+    let mut quote = None;
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => paren_depth += 1,
+            (None, ')') => paren_depth = paren_depth.saturating_sub(1),
+            (None, ';' | '}') if paren_depth == 0 => finish_declaration(declaration_start, i),
+            _ => {}
+        }
+        if quote.is_none() && paren_depth == 0 && matches!(c, ';' | '{' | '}') {
+            declaration_start = i + c.len_utf8();
+        }
+    }
+    finish_declaration(declaration_start, body.len());
+    res
+}
+
+/// the properties set in a rule body, without custom properties
+pub fn declared_properties(body: &str) -> Vec<String> {
+    declaration_ranges(body)
+        .into_iter()
+        .filter_map(|range| {
+            let (name, _) = body[range].split_once(':')?;
+            let name = name.trim();
+            (!name.starts_with("--")).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// adds `!important` to every declaration in a rule body, nested blocks included
+pub fn mark_important(body: &str) -> String {
+    let mut res = body.to_string();
+    for range in declaration_ranges(body).into_iter().rev() {
+        if !body[range.clone()].ends_with("!important") {
+            res.insert_str(range.end, " !important");
+        }
+    }
+    res
+}
+
+/// `&:hover, &::after` -> `:where(&:hover), :where(&)::after`, pseudo-elements can't be
+/// inside `:where()`
+fn where_selector(selector: &str) -> String {
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    let mut pseudo_element_start = None;
+    let mut paren_depth = 0usize;
+    let mut finish_part = |start: usize, end: usize, pseudo_element_start: Option<usize>| {
+        let (inner, pseudo_element) =
+            selector[start..end].split_at(pseudo_element_start.unwrap_or(end) - start);
+        parts.push(format!(
+            ":where({}){}",
+            inner.trim(),
+            pseudo_element.trim_end()
+        ));
+    };
+    for (i, c) in selector.char_indices() {
+        match c {
+            '(' | '[' => paren_depth += 1,
+            ')' | ']' => paren_depth = paren_depth.saturating_sub(1),
+            ',' if paren_depth == 0 => {
+                finish_part(part_start, i, pseudo_element_start.take());
+                part_start = i + 1;
+            }
+            ':' if paren_depth == 0
+                && pseudo_element_start.is_none()
+                && selector[i..].starts_with("::") =>
+            {
+                pseudo_element_start = Some(i);
+            }
+            _ => {}
+        }
+    }
+    finish_part(part_start, selector.len(), pseudo_element_start);
+    parts.join(", ")
+}
+
+/// wraps the selectors of the nested blocks in a rule body in `:where()`, at-rules stay as
+/// they are
+pub fn wrap_nested_selectors(body: &str) -> String {
+    map_nested_selectors(body, where_selector)
+}
+
+/// `body` with `f` applied to the selectors of its nested rules
+fn map_nested_selectors(body: &str, f: impl Fn(&str) -> String) -> String {
+    let mut selectors = Vec::new();
+    let mut block_start = 0;
+    let mut paren_depth = 0usize;
+    let mut quote = None;
+    for (i, c) in body.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => paren_depth += 1,
+            (None, ')') => paren_depth = paren_depth.saturating_sub(1),
+            (None, '{') if paren_depth == 0 => {
+                let prelude = body[block_start..i].trim();
+                if !prelude.is_empty() && !prelude.starts_with('@') {
+                    let start = block_start + body[block_start..i].find(prelude).unwrap_or(0);
+                    selectors.push(start..start + prelude.len());
+                }
+            }
+            _ => {}
+        }
+        if quote.is_none() && paren_depth == 0 && matches!(c, ';' | '{' | '}') {
+            block_start = i + c.len_utf8();
+        }
+    }
+
+    let mut res = body.to_string();
+    for range in selectors.into_iter().rev() {
+        res.replace_range(range.clone(), &f(&body[range]));
+    }
+    res
+}
+
+/// a compound selector of a variant with what it adds to `&` in `:where()`, `&:hover` ->
+/// `&:where(:hover)`, `.dark` -> `:where(.dark)`. pseudo-elements can't be in `:where()`
+fn where_compound(compound: &str) -> String {
+    let mut depth = 0usize;
+    let mut pseudo_element_start = compound.len();
+    for (i, c) in compound.char_indices() {
+        match c {
+            '(' | '[' => depth += 1,
+            ')' | ']' => depth = depth.saturating_sub(1),
+            ':' if depth == 0 && compound[i..].starts_with("::") => {
+                pseudo_element_start = i;
+                break;
+            }
+            _ => {}
+        }
+    }
+    let (inner, pseudo_element) = compound.split_at(pseudo_element_start);
+    let already_zero = |s: &str| {
+        s.strip_prefix(":where(")
+            .is_some_and(|rest| rest.ends_with(')') && rest.find(')') == Some(rest.len() - 1))
+    };
+    match inner.strip_prefix('&') {
+        _ if inner.is_empty() || inner == "*" || already_zero(inner) => compound.to_string(),
+        Some(rest) if rest.is_empty() || already_zero(rest) || rest.contains('&') => {
+            compound.to_string()
+        }
+        Some(rest) => format!("&:where({rest}){pseudo_element}"),
+        None if inner.contains('&') => compound.to_string(),
+        None => format!(":where({inner}){pseudo_element}"),
+    }
+}
+
+/// the selector of a variant with everything but `&` in `:where()`, `&:hover` ->
+/// `&:where(:hover)`, `.dark &` -> `:where(.dark) &`, `& > *` stays
+fn where_variant_selector(selector: &str) -> String {
+    split_selector_list(selector)
+        .into_iter()
+        .map(|selector| {
+            let mut res = String::with_capacity(selector.len() + 8);
+            let mut compound_start = 0;
+            let mut depth = 0usize;
+            for (i, c) in selector.char_indices() {
+                match c {
+                    '(' | '[' => depth += 1,
+                    ')' | ']' => depth = depth.saturating_sub(1),
+                    ' ' | '>' | '+' | '~' if depth == 0 => {
+                        res.push_str(&where_compound(&selector[compound_start..i]));
+                        res.push(c);
+                        compound_start = i + 1;
+                    }
+                    _ => {}
+                }
+            }
+            res.push_str(&where_compound(&selector[compound_start..]));
+            res
+        })
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// the alpha of a `/50` or `/[0.5]` modifier as a percentage
+fn modifier_alpha(modifier: &str) -> Option<String> {
+    if let Some(raw) = modifier
+        .strip_prefix('[')
+        .and_then(|raw| raw.strip_suffix(']'))
+    {
+        return Some(match raw.parse::<f64>() {
+            Ok(fraction) => format!("{}%", (fraction * 10000.0).round() / 100.0),
+            Err(_) => raw.to_string(),
+        });
+    }
+    modifier.parse::<f64>().ok()?;
+    Some(format!("{modifier}%"))
+}
+
+/// `--theme:dark` -> `--theme: dark`
+fn style_query(raw: &str) -> String {
+    match raw.split_once(':') {
+        Some((property, value)) => format!("{}: {}", property.trim(), value.trim()),
+        None => raw.trim().to_string(),
+    }
+}
+
+/// the condition of `supports-[...]`: `display:grid` -> `(display: grid)`, function queries
+/// stay as they are, `selector(&::details-content)` -> `selector(::details-content)`
+fn supports_query(raw: &str) -> String {
+    let raw = raw.trim();
+    if let Some(selector) = raw
+        .strip_prefix("selector(")
+        .and_then(|rest| rest.strip_suffix(')'))
+    {
+        return format!(
+            "selector({})",
+            selector.trim().trim_start_matches('&').trim()
+        );
+    }
+
+    let function_name_len = raw
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(raw.len());
+    if raw.starts_with('(') || (function_name_len > 0 && raw[function_name_len..].starts_with('('))
+    {
+        raw.to_string()
+    } else {
+        format!("({})", style_query(raw))
+    }
+}
+
+/// the variants `resolve_internal_variant` knows, in `group-*`, `peer-*` and `has-*` they're
+/// resolved as well instead of being taken as pseudo classes
+const INTERNAL_VARIANTS: [&str; 9] = [
+    "data", "nth", "has", "theme", "aria", "not", "peer", "in", "group",
+];
+
+/// variant name -> the pseudo-element it adds to the selector, there without a config.
+/// `@pseudo-element` adds more
+const PSEUDO_ELEMENT_VARIANTS: [(&str, &str); 11] = [
+    ("before", "before"),
+    ("after", "after"),
+    ("placeholder", "placeholder"),
+    ("file", "file-selector-button"),
+    ("selection", "selection"),
+    ("first-letter", "first-letter"),
+    ("first-line", "first-line"),
+    ("backdrop", "backdrop"),
+    ("details-content", "details-content"),
+    ("spelling-error", "spelling-error"),
+    ("grammar-error", "grammar-error"),
+];
+
+/// `focus-visible` of the segments of `group-focus-visible`
+fn params_joined(params: &[(ParsedUnit, DWS)]) -> String {
+    params
+        .iter()
+        .map(|x| match &x.0 {
+            ParsedUnit::String(s) => s.as_str(),
+            ParsedUnit::Raw(_) => "",
+        })
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// the attribute selector of `data-[...]` and `aria-[...]` with its value quoted: `state=open` ->
+/// `[data-state="open"]`, `size~='large' i` -> `[data-size~="large" i]`, `state` -> `[data-state]`
+fn attribute_selector(prefix: &str, raw: &str) -> String {
+    let raw = raw.trim();
+    let Some(equals) = raw.find('=') else {
+        return format!("[{prefix}{raw}]");
+    };
+    let (name, operator) = match raw[..equals].strip_suffix(['~', '|', '^', '$', '*']) {
+        Some(name) => (name, &raw[name.len()..=equals]),
+        None => (&raw[..equals], "="),
+    };
+
+    let mut value = raw[equals + 1..].trim();
+    let mut flag = "";
+    if let Some((rest, case)) = value.rsplit_once(' ')
+        && matches!(case, "i" | "I" | "s" | "S")
+    {
+        value = rest.trim_end();
+        flag = case;
+    }
+    let value = match value.chars().next() {
+        Some(quote @ ('"' | '\'')) if value.len() > 1 && value.ends_with(quote) => {
+            let inner = &value[1..value.len() - 1];
+            if quote == '"' {
+                inner.to_string()
+            } else {
+                inner.replace("\\'", "'").replace('"', "\\\"")
+            }
+        }
+        _ => value.replace('"', "\\\""),
+    };
+    let flag = if flag.is_empty() {
+        String::new()
+    } else {
+        format!(" {flag}")
+    };
+    format!("[{prefix}{}{operator}\"{value}\"{flag}]", name.trim())
+}
+
+/// a whole candidate like `hover:bg-red-500`, `None` if it isn't one or has trailing text
+fn parse_candidate_units(candidate: &str) -> Option<Parsed> {
+    // the spans are never shown, so there's no need for the source to outlive the call
+    let (toks, end) = lexer("candidate", "").parse(candidate).into_output()?;
+    if end != candidate.len() {
+        return None;
+    }
+    let parsed = duckwind_parser(make_input)
+        .parse(make_input(make_eoi("candidate", ""), toks.as_slice()))
+        .into_output()?;
+    Some(parsed.0)
+}
+
+/// the candidates a variant group like `hover:(p-4,focus:(flex,grid))` stands for, each with the
+/// variants of the group in front of its own. a candidate that isn't a group stands for itself
+fn group_members(parsed: Parsed) -> Option<Vec<Parsed>> {
+    let Some(members) = &parsed.group else {
+        return Some(vec![parsed]);
+    };
+    let mut res = Vec::new();
+    for member in members {
+        for mut member in group_members(parse_candidate_units(member)?)? {
+            member.variants = parsed
+                .variants
+                .iter()
+                .cloned()
+                .chain(member.variants)
+                .collect();
+            member.important |= parsed.important;
+            res.push(member);
+        }
+    }
+    Some(res)
+}
+
+/// the variants of a chain like `dark:hover`, aliases are only parsed when a config is loaded
+fn parse_variant_chain(chain: &str) -> Option<Vec<Vec<Spanned<ParsedUnit>>>> {
+    Some(parse_candidate_units(&format!("{chain}:x"))?.variants)
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
 pub fn escape_string_for_css(s: &str) -> String {
     let mut res = String::with_capacity(s.len() * 2);
     for c in s.chars() {
@@ -57,14 +672,98 @@ pub fn escape_string_for_css(s: &str) -> String {
     res
 }
 
+/// group, variant count, breakpoints, variants, property ranks, class and scope, see
+/// `CssDef::canonical_sort_key`
+type CanonicalSortKey = (
+    usize,
+    usize,
+    Vec<Breakpoint>,
+    Vec<(usize, String)>,
+    Vec<usize>,
+    String,
+    Option<String>,
+);
+
 impl CssDef {
-    pub fn to_css(&self) -> String {
+    /// rules without variants come first, then breakpoints (`sm:` before `md:`), then
+    /// shorthands before longhands (`p-4` before `px-4` before `pt-4`), so later rules override
+    /// earlier ones like in tailwind
+    pub fn sort_key(&self) -> (usize, Vec<Breakpoint>, Vec<usize>) {
+        let mut ranks = self
+            .properties
+            .iter()
+            .map(|property| property_rank(property))
+            .collect::<Vec<_>>();
+        ranks.sort_unstable();
+        if ranks.is_empty() {
+            ranks.push(usize::MAX);
+        }
+        (self.variant_count, self.breakpoints.clone(), ranks)
+    }
+
+    /// `group_ranks` is the position of every group in the config, ungrouped rules come last.
+    /// `variant_ranks` is the position of every variant in the config, variants that aren't in
+    /// it like `md` or `[&>*]` come after them by name
+    pub fn canonical_sort_key(
+        &self,
+        group_ranks: &HashMap<&str, usize>,
+        variant_ranks: &HashMap<&str, usize>,
+    ) -> CanonicalSortKey {
+        let group_rank = self
+            .group
+            .as_deref()
+            .and_then(|group| group_ranks.get(group).copied())
+            .unwrap_or(usize::MAX);
+        let (_, _, ranks) = self.sort_key();
+        let variants = self
+            .variants
+            .iter()
+            .map(|variant| {
+                let rank = variant_ranks.get(variant.as_str()).copied();
+                (rank.unwrap_or(usize::MAX), variant.clone())
+            })
+            .collect();
+        (
+            group_rank,
+            self.variant_count,
+            self.breakpoints.clone(),
+            variants,
+            ranks,
+            self.class_name.clone(),
+            self.scope_selector.clone(),
+        )
+    }
+
+    /// the cascade layer the rule is emitted in with `EmitOptions::layers`
+    pub fn layer_name(&self) -> &str {
+        self.layer.as_deref().unwrap_or(DEFAULT_LAYERS[3])
+    }
+
+    /// the escaped class in the selector of the rule, the hashed name once there is one
+    pub fn emitted_class_name(&self) -> &str {
+        self.hashed_name.as_deref().unwrap_or(&self.class_name)
+    }
+
+    /// the selector of the rule, `#app .hover\\:p-4::after`
+    pub fn selector(&self) -> String {
         let mut res = String::new();
-        let mut opening_braces = 0;
-        res.push_str(&format!(".{}", self.class_name));
+        if let Some(scope_selector) = &self.scope_selector {
+            res.push_str(&format!("{scope_selector} "));
+        }
+        if self.low_specificity {
+            res.push_str(&format!(":where(.{})", self.emitted_class_name()));
+        } else {
+            res.push_str(&format!(".{}", self.emitted_class_name()));
+        }
         for pseudo_elements in &self.pseudo_elements {
             res.push_str(&format!("::{}", pseudo_elements));
         }
+        res
+    }
+
+    pub fn to_css(&self) -> String {
+        let mut res = self.selector();
+        let mut opening_braces = 0;
         res.push_str("{\n");
         opening_braces += 1;
         res.push_str(self.body.as_str());
@@ -82,14 +781,221 @@ pub struct EmitEnv {
     pub utilities: Vec<Utility>,
     pub variants: Vec<Variant>,
     pub theme: Theme,
+    /// themes defined with `@theme name(x) { ... }`
+    pub named_themes: BTreeMap<String, Theme>,
+    /// variant name -> the vars of `@theme dark { ... }` and the like, emitted in `:root` under
+    /// that custom variant
+    pub variant_themes: BTreeMap<String, Theme>,
     pub defs_generated: HashSet<String>,
     pub custom_properties: Vec<Property>,
+    pub diagnostics: Vec<Diagnostic>,
+    pub options: EmitOptions,
+    /// (prefix, scope selector, word) that didn't generate a new rule, cleared when a config is
+    /// loaded
+    negative_cache: LruSet<(Option<String>, Option<String>, String)>,
+    /// which rules the sources scanned with `parse_source` reference
+    source_refs: SourceRefs,
+    /// bucket name -> rules referenced through `in_bucket`
+    buckets: BTreeMap<String, HashSet<String>>,
+    /// name of a utility without a value -> its index in `utilities` and its body, filled when a
+    /// config is loaded so they resolve with one lookup
+    static_utilities: HashMap<String, (usize, String)>,
+    /// `@alias-variant` name -> the variants it stands for
+    variant_aliases: BTreeMap<String, Vec<Vec<Spanned<ParsedUnit>>>>,
+    /// `@pseudo-element` variant name -> the pseudo-element, `file` -> `file-selector-button`
+    pseudo_element_variants: BTreeMap<String, String>,
+    /// names of the loaded configs in load order, `Utility::config` and `Variant::config` index
+    /// into it
+    config_names: Vec<String>,
+    /// index of the bundled default config in `config_names`, its `dark` and `not-dark` give way
+    /// to `EmitOptions::dark_mode`
+    default_config: Option<usize>,
+    /// theme var (without `--`) -> index of the config that set it last
+    var_origins: HashMap<String, usize>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct EmitOptions {
+    /// replace `var(--x)` references to theme vars in the generated rules with their values,
+    /// only vars that are still referenced afterwards are emitted in `:root`
+    pub inline_vars: bool,
+    /// nest the whole stylesheet under this root selector, preflight and theme vars included,
+    /// so it can be embedded in pages that aren't styled with duckwind. rules generated with
+    /// their own `CompileOptions::scope_selector` keep that
+    pub scope: Option<String>,
+    /// wrap the stylesheet in `@scope (scope)` instead of prefixing every selector with the
+    /// scope, see `scope_at_rule`
+    pub scope_at_rule: bool,
+    /// mark every declaration of the rules `!important` when they're emitted, for pages whose
+    /// legacy css would win otherwise. `CompileOptions::important` does it for single documents
+    pub important: bool,
+    /// emit a stylesheet for a shadow root, `:root` and the preflight's document selectors
+    /// become `:host`, see `scoping::shadow_stylesheet`
+    pub shadow_dom: bool,
+    /// leave out `:root` and the theme blocks for pages that define the tokens themselves, see
+    /// `EmitEnv::to_theme_vars_stylesheet` for the other half
+    pub no_theme_vars: bool,
+    /// keep the specificity the selectors of variants add, `hover:p-4` is `.hover\:p-4:hover`
+    /// instead of `.hover\:p-4:where(:hover)`. with it a variant wins over the utilities after it
+    pub no_where_variants: bool,
+    /// evaluate `calc()`s of constants, `calc(var(--spacing) * 4)` -> `1rem`. the rules no longer
+    /// follow changes to the vars that were folded in, see `calc::fold_calc`
+    pub fold_calc: bool,
+    /// used instead of the bundled preflight, like the reset of a design system
+    pub preflight: Option<String>,
+    /// only generate rules for the utilities of these `@group`s, utilities without a group are
+    /// always enabled. `None` enables every group
+    pub utility_groups: Option<BTreeSet<String>>,
+    /// how the rules are ordered in the stylesheet
+    pub rule_order: RuleOrder,
+    /// what `dark:` and `not-dark:` check for
+    pub dark_mode: DarkMode,
+    /// prefix every rule with a comment saying which class it's for and where that was found,
+    /// like `/* from: index.html:42 — hover:bg-red-500 */`. `minify_css` strips them again
+    pub debug_comments: bool,
+    /// strip the whitespace and comments of every emitted stylesheet, see `minify_css`
+    pub minify: bool,
+    /// emit the theme vars, preflight and rules in `@layer theme, base, components, utilities`
+    /// like tailwind, `@layer name;` in a config puts the utilities after it in another layer
+    pub layers: bool,
+    /// emit rules with the same body as one rule with a selector list, where that doesn't
+    /// change which rule wins. see `merge::merge_identical_rules`
+    pub merge_rules: bool,
+    /// emit plain rules instead of nested ones for browsers without css nesting, see
+    /// `flatten_nesting`
+    pub flatten_nesting: bool,
+    /// merge neighbouring rules in the same media or container query into one block of it, see
+    /// `merge_media_queries`
+    pub merge_media_queries: bool,
+    /// the oldest browsers the stylesheets have to work in, css they don't support is lowered:
+    /// nesting is flattened, media ranges become `min-width` and modern colors get an srgb
+    /// fallback. `None` lowers nothing
+    pub targets: Option<BrowserTargets>,
+    /// an srgb declaration in front of every one with `oklch()`, `lab()` and the like, see
+    /// `srgb_fallbacks`
+    pub color_fallbacks: bool,
+    /// `@keyframes` of the theme to emit even if no rule animates with them, like ones only
+    /// the hand-written css uses. see `EmitEnv::used_keyframes`
+    pub keep_keyframes: BTreeSet<String>,
+    /// refuse to load configs that redefine a utility, variant or theme var of an earlier
+    /// config differently instead of overriding it, see `EmitEnv::try_load_named_config`
+    pub error_on_conflict: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum RuleOrder {
+    /// in the order the candidates were found, but rules with fewer variants first, breakpoints
+    /// ascending and shorthands before longhands so the cascade works out. depends on the order
+    /// of the sources
+    #[default]
+    Cascade,
+    /// exactly in the order the candidates were found, for debugging. the cascade doesn't work
+    /// out, `md:p-2 p-4` emits the media query first so `p-4` wins at every width
+    Source,
+    /// by utility group in config order, then breakpoints, then variants in config order, then
+    /// class name, so the output doesn't change when the same classes are found in another order
+    Canonical,
+}
+
+/// how `dark:` and `not-dark:` tell that the page is dark, a `@custom-variant dark` of a user
+/// config wins over it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum DarkMode {
+    /// `@media (prefers-color-scheme: dark)`, the variants of the default config
+    #[default]
+    Media,
+    /// `@media (prefers-color-scheme: dark)` unless a `light` class is on the element or an
+    /// ancestor, and always with a `dark` class there, for pages with a theme toggle
+    MediaAndClass,
+    /// a `dark` class on the element or an ancestor
+    Class,
+    /// `data-theme="dark"` on the element or an ancestor
+    DataAttribute,
+    /// any selector matching the element or an ancestor, like `.night`
+    Selector(String),
+}
+
+impl DarkMode {
+    /// the selector that turns on dark mode, `None` if the media query is involved
+    fn selector(&self) -> Option<&str> {
+        match self {
+            DarkMode::Media | DarkMode::MediaAndClass => None,
+            DarkMode::Class => Some(".dark"),
+            DarkMode::DataAttribute => Some("[data-theme=\"dark\"]"),
+            DarkMode::Selector(selector) => Some(selector),
+        }
+    }
+}
+
+impl FromStr for DarkMode {
+    type Err = String;
+
+    /// `media`, `media-and-class`, `class`, `data-attribute` or a selector
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(match s.trim() {
+            "" => return Err("the dark mode can't be empty".to_string()),
+            "media" => DarkMode::Media,
+            "media-and-class" => DarkMode::MediaAndClass,
+            "class" => DarkMode::Class,
+            "data-attribute" => DarkMode::DataAttribute,
+            selector => DarkMode::Selector(selector.to_string()),
+        })
+    }
+}
+
+impl EmitOptions {
+    fn utility_enabled(&self, utility: &Utility) -> bool {
+        match (&self.utility_groups, &utility.group) {
+            (Some(groups), Some(group)) => groups.contains(group),
+            _ => true,
+        }
+    }
+}
+
+/// settings for a single `parse_full_string` / `parse_tailwind_str` / `compile_classes` call, so
+/// documents with different settings can be compiled with the same env
+#[derive(Debug, Clone, Default)]
+pub struct CompileOptions {
+    /// classes have to start with this prefix like `tw:`, it's kept in the generated class name
+    pub prefix: Option<String>,
+    /// mark every generated declaration as `!important`
+    pub important: bool,
+    /// nest the generated rules under this ancestor selector, e.g. `#app`
+    pub scope_selector: Option<String>,
+    /// report classes passed to `compile_classes` that don't resolve as diagnostics
+    pub strict: bool,
+    /// wrap the generated selectors in `:where()`, variants included, so the rules have zero
+    /// specificity and any component css overrides them
+    pub low_specificity: bool,
+}
+
+impl CompileOptions {
+    pub fn with_prefix(prefix: impl Into<String>) -> Self {
+        CompileOptions {
+            prefix: Some(prefix.into()),
+            ..Default::default()
+        }
+    }
+}
+
+/// what the candidate at the start of some text resolved to, see `EmitEnv::resolve_candidate`
+#[derive(Debug, Clone)]
+pub enum ResolvedCandidate {
+    /// new rules were generated, one for every member of a variant group
+    Generated(Vec<CssDef>),
+    /// escaped and prefixed class name of a rule that was generated before
+    AlreadyGenerated(String),
 }
 
+/// the body of a rule for a utility without its variants, its `@group` and its `@layer`
+type UtilityBody = (String, Option<String>, Option<String>);
+
 #[derive(Debug, Clone)]
 pub enum SpecialParam {
     Transparency(String),
     LineHeight(String),
+    /// the type of an arbitrary value written in front of it, `bg-[color:var(--brand)]`
+    DataType(ValueType),
 }
 
 impl Default for EmitEnv {
@@ -99,21 +1005,178 @@ impl Default for EmitEnv {
 }
 
 impl EmitEnv {
-    pub fn parse_full_string<'prefix, 'text>(
+    #[tracing::instrument(level = "debug", skip_all, fields(len = txt.len()))]
+    pub fn parse_full_string(&mut self, options: &CompileOptions, txt: &str) {
+        let mut i = 0;
+        while i < txt.len() {
+            let cache_key = Self::candidate_word(options, &txt[i..]).map(|word| {
+                (
+                    options.prefix.clone(),
+                    options.scope_selector.clone(),
+                    word.to_string(),
+                )
+            });
+            if let Some(cache_key) = &cache_key
+                && self.negative_cache.contains(cache_key)
+            {
+                // same word as before, it can't generate anything new
+            } else if let Some((_, skip)) = self.parse_tailwind_str(options, &txt[i..]) {
+                i += skip;
+            } else if let Some(cache_key) = cache_key {
+                self.negative_cache.insert(cache_key);
+            }
+            i += 1;
+            while i < txt.len() && !txt.is_char_boundary(i) {
+                i += 1;
+            }
+        }
+    }
+
+    /// like `parse_full_string` but records where each class was found in `report`, and words
+    /// that look like classes but don't resolve
+    pub fn collect_usage(
         &mut self,
-        prefix: Option<&'prefix str>,
-        txt: &'text str,
+        options: &CompileOptions,
+        file: &str,
+        txt: &str,
+        report: &mut UsageReport,
     ) {
+        let line_index = LineIndex::new(txt);
+        let class_values = class_attribute_values(txt);
         let mut i = 0;
         while i < txt.len() {
-            if let Some((_, skip)) = self.parse_tailwind_str(prefix, &txt[i..]) {
-                i += skip;
+            if let Some((resolved, consumed)) = self.resolve_candidate(options, &txt[i..]) {
+                // every member of a variant group is a def of its own
+                if let ResolvedCandidate::Generated(generated) = resolved {
+                    let start = self.defs.len() - generated.len();
+                    for css_def in &mut self.defs[start..] {
+                        css_def.origin = Some(line_index.location(file, i));
+                    }
+                }
+                report
+                    .classes
+                    .entry(txt[i..i + consumed].to_string())
+                    .or_default()
+                    .push(line_index.location(file, i));
+                i += consumed;
+            } else if let Some(word) = self.unresolved_word(options, txt, &class_values, i) {
+                let usages = if self.has_unknown_value(word) {
+                    &mut report.unknown_values
+                } else {
+                    &mut report.unresolved
+                };
+                usages
+                    .entry(word.to_string())
+                    .or_default()
+                    .push(line_index.location(file, i));
             }
             i += 1;
             while i < txt.len() && !txt.is_char_boundary(i) {
                 i += 1;
             }
         }
+
+        for (offset, fragment) in dynamic_fragments(txt) {
+            if self.is_utility_prefix(options, fragment) {
+                report
+                    .dynamic
+                    .entry(fragment.to_string())
+                    .or_default()
+                    .push(line_index.location(file, offset));
+            }
+        }
+    }
+
+    /// the word at `i` if it looks like a class (a whole word with `-` or `:`), for candidates
+    /// that didn't resolve. outside of `class_values` only words with variants or for a utility
+    /// that exists count, not attribute names like `aria-label` or words like `en-US`
+    fn unresolved_word<'a>(
+        &self,
+        options: &CompileOptions,
+        txt: &'a str,
+        class_values: &[Range<usize>],
+        i: usize,
+    ) -> Option<&'a str> {
+        fn is_boundary(c: char) -> bool {
+            c.is_whitespace() || "\"'`<>{}=,;".contains(c)
+        }
+
+        if !txt[..i].chars().next_back().is_none_or(is_boundary) {
+            return None;
+        }
+        let word = Self::candidate_word(options, &txt[i..])?;
+        if !txt[i + word.len()..].chars().next().is_none_or(is_boundary)
+            || !word.contains(['-', ':'])
+        {
+            return None;
+        }
+        (class_values.iter().any(|value| value.contains(&i))
+            || self.has_candidate_syntax(options, word))
+        .then_some(word)
+    }
+
+    /// whether `word` is written like a class: every variant exists, like `hover:p-x`, or it
+    /// has none and starts with the name of a utility with values, like `bg-red-510`
+    fn has_candidate_syntax(&self, options: &CompileOptions, word: &str) -> bool {
+        let word = word
+            .strip_prefix(options.prefix.as_deref().unwrap_or_default())
+            .unwrap_or(word);
+        let mut segments = word.split(':').collect::<Vec<_>>();
+        let Some(utility) = segments.pop() else {
+            return false;
+        };
+        if !segments.is_empty() {
+            return segments.iter().all(|variant| self.is_variant_name(variant));
+        }
+        let base = utility.trim_start_matches(['-', '!']).trim_end_matches('!');
+        self.utilities.iter().any(|known| {
+            known.has_value
+                && self.options.utility_enabled(known)
+                && base
+                    .strip_prefix(known.name.as_str())
+                    .is_some_and(|rest| rest.starts_with('-'))
+        })
+    }
+
+    /// whether `variant` names a variant, without checking its parameters: `hover`, `md`,
+    /// `group-focus`, `max-[600px]` or an arbitrary one
+    fn is_variant_name(&self, variant: &str) -> bool {
+        if variant.starts_with(['[', '@', '*']) {
+            return true;
+        }
+        let first = variant.split('-').next().unwrap_or(variant);
+        INTERNAL_VARIANTS.contains(&first)
+            || matches!(first, "min" | "max" | "supports")
+            || self.find_variant(variant).is_some()
+            || self.follows_dark_mode(variant)
+            || self.get_breakpoint_var(variant).is_some()
+            || self.pseudo_element_variants.contains_key(variant)
+            || self.variant_aliases.contains_key(variant)
+    }
+
+    /// whether `fragment` is the start of a class with a value, like `hover:bg-` or `bg-red-`
+    fn is_utility_prefix(&self, options: &CompileOptions, fragment: &str) -> bool {
+        let Some(fragment) = fragment.strip_prefix(options.prefix.as_deref().unwrap_or_default())
+        else {
+            return false;
+        };
+        let Some(utility) = fragment.rsplit(':').next() else {
+            return false;
+        };
+        let base = utility.trim_start_matches('-').trim_end_matches('-');
+        self.utilities.iter().any(|known| {
+            known.has_value
+                && self.options.utility_enabled(known)
+                && base
+                    .strip_prefix(known.name.as_str())
+                    .is_some_and(|rest| rest.is_empty() || rest.starts_with('-'))
+        })
+    }
+
+    /// like `parse_full_string` but only scans the inline html, mdx component tags and markup code
+    /// blocks of a markdown / mdx document
+    pub fn parse_markdown_string(&mut self, options: &CompileOptions, txt: &str) {
+        self.parse_full_string(options, &extract_markdown(txt));
     }
 
     pub fn get_breakpoint_var(&self, name: &str) -> Option<String> {
@@ -160,12 +1223,32 @@ impl EmitEnv {
         )
     }
 
+    /// the condition an internal variant puts on `&`, `has-checked` -> `:has(:checked)`. `None`
+    /// if `v` isn't an internal variant, `Some(None)` if it is one but can't be resolved
+    fn internal_condition(&self, v: &[(ParsedUnit, DWS)]) -> Option<Option<String>> {
+        let ParsedUnit::String(name) = &v[0].0 else {
+            return None;
+        };
+        if !INTERNAL_VARIANTS.contains(&name.as_str()) {
+            return None;
+        }
+        Some(self.resolve_internal_variant("", v).map(|resolved| {
+            let open = resolved.find('{').expect("variants have a block");
+            resolved[1..open].trim_end().to_string()
+        }))
+    }
+
     pub fn resolve_internal_variant(&self, body: &str, v: &[(ParsedUnit, DWS)]) -> Option<String> {
+        // all internal variants need at least one parameter
+        if v.len() < 2 {
+            return None;
+        }
+
         Some(match &v[0].0 {
             ParsedUnit::String(s) => match s.as_str() {
                 "data" => {
                     if let ParsedUnit::Raw(r) = &v[1].0 {
-                        format!("&[data-{r}] {{\n{body}\n}}")
+                        format!("&{} {{\n{body}\n}}", attribute_selector("data-", r))
                     } else {
                         let joined = v[1..]
                             .iter()
@@ -181,124 +1264,118 @@ impl EmitEnv {
                         format!("&[data-{joined}] {{\n{body}\n}}")
                     }
                 }
-                "nth" => match &v[1].0 {
-                    ParsedUnit::Raw(raw_value) => {
-                        format!("&:nth-child({raw_value}) {{\n{body}\n}}")
+                "nth" => {
+                    // `nth-last-of-type-[2n]` is split into `nth`, `last`, `of`, `type` and the param
+                    let (param, name) = v.split_last().expect("at least two parts");
+                    let name = name
+                        .iter()
+                        .map(|x| match &x.0 {
+                            ParsedUnit::String(s) => Some(s.as_str()),
+                            _ => None,
+                        })
+                        .collect::<Option<Vec<_>>>()?
+                        .join("-");
+                    let pseudo_class = match name.as_str() {
+                        "nth" => "nth-child",
+                        "nth-last" => "nth-last-child",
+                        "nth-of-type" => "nth-of-type",
+                        "nth-last-of-type" => "nth-last-of-type",
+                        _ => return None,
+                    };
+                    let param = match &param.0 {
+                        ParsedUnit::Raw(raw_value) => raw_value.as_str(),
+                        ParsedUnit::String(s) if s == "odd" || s == "even" => s.as_str(),
+                        _ => return None,
+                    };
+                    format!("&:{pseudo_class}({param}) {{\n{body}\n}}")
+                }
+                "has" => match &v[1].0 {
+                    ParsedUnit::String(_) => match self.internal_condition(&v[1..]) {
+                        Some(condition) => format!("&:has({}) {{\n{body}\n}}", condition?),
+                        None => format!("&:has(:{}) {{\n{body}\n}}", params_joined(&v[1..])),
+                    },
+                    ParsedUnit::Raw(raw_param) => {
+                        format!("&:has({raw_param}) {{\n{body}\n}}")
                     }
-                    _ => return None,
                 },
-                "nth-last" => match &v[1].0 {
-                    ParsedUnit::Raw(raw_value) => {
-                        format!("&:nth-last-child({raw_value}) {{\n{body}\n}}")
+                "theme" => {
+                    let name = v[1..]
+                        .iter()
+                        .map(|x| match &x.0 {
+                            ParsedUnit::String(s) => s.to_string(),
+                            ParsedUnit::Raw(r) => r.to_string(),
+                        })
+                        .collect::<Vec<String>>()
+                        .join("-");
+                    if !self.named_themes.contains_key(&name) {
+                        return None;
                     }
-                    _ => return None,
-                },
-                "nth-of-type" => match &v[1].0 {
-                    ParsedUnit::Raw(raw_value) => {
-                        format!("&:nth-of-type({raw_value}) {{\n{body}\n}}")
+                    format!(
+                        "&:where([data-theme=\"{name}\"], [data-theme=\"{name}\"] *) {{\n{body}\n}}"
+                    )
+                }
+                "aria" => {
+                    let selector = match &v[1].0 {
+                        ParsedUnit::String(next) => match next.as_str() {
+                            "busy" | "checked" | "disabled" | "expanded" | "hidden" | "pressed"
+                            | "readonly" | "required" | "selected" => {
+                                format!("[aria-{next}=\"true\"]")
+                            }
+                            _ => return None,
+                        },
+                        ParsedUnit::Raw(raw_next) => attribute_selector("aria-", raw_next),
+                    };
+                    format!("&{selector} {{\n{body}\n}}")
+                }
+                "not" => match &v[1..] {
+                    [(ParsedUnit::Raw(raw), _)]
+                        if !raw.contains('&') && !raw.starts_with(['@', ':']) =>
+                    {
+                        format!("&:not({raw}) {{\n{body}\n}}")
                     }
-                    _ => return None,
-                },
-                "nth-last-of-type" => match &v[1].0 {
-                    ParsedUnit::Raw(raw_value) => {
-                        format!("&:nth-last-of-type({raw_value}) {{\n{body}\n}}")
+                    _ => {
+                        let other = self.resolve_internal_variant(body, &v[1..])?;
+                        let (selector, rest) = other.split_once(" {\n")?;
+                        format!("&:not({}) {{\n{rest}", selector.strip_prefix('&')?)
                     }
-                    _ => return None,
                 },
-                "has" => match &v[1].0 {
-                    ParsedUnit::String(_) => {
-                        let joined = v[1..]
-                            .iter()
-                            .map(|x| match &x.0 {
-                                ParsedUnit::String(s) => s.to_string(),
-                                _ => String::new(),
-                            })
-                            .collect::<Vec<String>>()
-                            .join("-");
-                        format!("&:has(:{joined}) {{\n{body}\n}}")
-                    }
-                    ParsedUnit::Raw(raw_param) => {
-                        format!("&:has({raw_param}) {{\n{body}\n}}")
-                    }
-                },
-                "aria" => match &v[1].0 {
-                    ParsedUnit::String(next) => match next.as_str() {
-                        "busy" => r#"&[aria-busy="true"]"#.to_string(),
-                        "checked" => r#"&[aria-checked="true"]"#.to_string(),
-                        "disabled" => r#"&[aria-disabled="true"]"#.to_string(),
-                        "expanded" => r#"&[aria-expanded="true"]"#.to_string(),
-                        "hidden" => r#"&[aria-hidden="true"]"#.to_string(),
-                        "pressed" => r#"&[aria-pressed="true"]"#.to_string(),
-                        "readonly" => r#"&[aria-readonly="true"]"#.to_string(),
-                        "required" => r#"&[aria-required="true"]"#.to_string(),
-                        "selected" => r#"&[aria-selected="true"]"#.to_string(),
-                        _ => return None,
-                    },
-                    ParsedUnit::Raw(raw_next) => {
-                        format!("&[aria-{raw_next}]")
-                    }
-                },
-                "not" => {
-                    let mut other = self.resolve_internal_variant(body, &v[1..])?;
-                    if let Some(mut start) = other.find(|c: char| !c.is_whitespace()) {
-                        if &other[start..start + 1] == "&" && start + 1 < other.len() {
-                            start += 1;
-                        }
-
-                        if let Some(newline_index) = other[start..].find('\n') {
-                            other.insert(newline_index, ')');
-                            other.insert_str(start, ":not(");
-                        }
-                    }
-                    other
-                }
-                "peer" => match &v[1].0 {
-                    ParsedUnit::String(param_1) => {
-                        let joined = v[1..]
-                            .iter()
-                            .map(|x| match &x.0 {
-                                ParsedUnit::String(s) => s.to_string(),
-                                _ => String::new(),
-                            })
-                            .collect::<Vec<String>>()
-                            .join("-");
-                        if let Some((param, peer_name)) = param_1.split_once("/") {
-                            if param == "has" || param == "not" {
-                                let mut input =
-                                    vec![(ParsedUnit::String(param.to_string()), empty_span())];
-                                input.extend_from_slice(&v[2..]);
-                                let res = self.resolve_internal_variant(body, input.as_slice())?;
-                                let cond = &res[1..res.find("{").unwrap()];
-                                format!(
-                                    "&:is(:where(.peer{}){cond} ~ *) {{\n{body}\n}}",
-                                    escape_string_for_css(&format!("/{peer_name}")),
-                                )
-                            } else {
-                                format!(
-                                    "&:is(:where(.peer{}):is(:{joined}) ~ *) {{\n{body}\n}}",
-                                    escape_string_for_css(&format!("/{peer_name}"))
-                                )
+                "group" | "peer" => {
+                    // `group-hover/sidebar` and `group-[.x]/sidebar` are relative to a named group
+                    let mut name = None;
+                    let params = v[1..]
+                        .iter()
+                        .filter_map(|(unit, span)| match unit {
+                            ParsedUnit::String(param) if param.contains('/') => {
+                                let (param, group_name) = param.split_once('/').expect("has a /");
+                                name = Some(group_name);
+                                (!param.is_empty())
+                                    .then(|| (ParsedUnit::String(param.to_string()), *span))
                             }
-                        } else if param_1 == "has" || param_1 == "not" {
-                            let mut input =
-                                vec![(ParsedUnit::String(param_1.to_string()), empty_span())];
-                            input.extend_from_slice(&v[2..]);
-                            let res = self.resolve_internal_variant(body, input.as_slice())?;
-                            let cond = &res[1..res.find("{").unwrap()];
-                            format!("&:is(:where(.peer){cond} ~ *) {{\n{body}\n}}",)
-                        } else {
-                            format!("&:is(:where(.peer):is(:{joined}) ~ *) {{\n{body}\n}}",)
+                            _ => Some((unit.clone(), *span)),
+                        })
+                        .collect::<Vec<_>>();
+                    let class = match name {
+                        Some(name) => format!(".{s}{}", escape_string_for_css(&format!("/{name}"))),
+                        None => format!(".{s}"),
+                    };
+                    let combinator = if s == "peer" { " ~ *" } else { " *" };
+                    let condition = match params.as_slice() {
+                        [(ParsedUnit::Raw(raw), _)] if raw.contains('&') => {
+                            let replaced =
+                                raw.replace('&', &format!(":where({class}){combinator}"));
+                            return Some(format!("&:is({replaced}) {{\n{body}\n}}"));
                         }
-                    }
-                    ParsedUnit::Raw(param_1) => {
-                        if param_1.contains("&") {
-                            let replaced = param_1.replace("&", ":where(.peer) ~ *");
-                            format!("&:is({replaced}) {{\n{body}\n}}")
-                        } else {
-                            format!("&:is(:where(.peer):is({param_1}) ~ *) {{\n{body}\n}}",)
+                        [(ParsedUnit::Raw(raw), _)] => format!(":is({raw})"),
+                        [(ParsedUnit::String(_), _), ..] => {
+                            match self.internal_condition(&params) {
+                                Some(condition) => condition?,
+                                None => format!(":is(:{})", params_joined(&params)),
+                            }
                         }
-                    }
-                },
+                        _ => return None,
+                    };
+                    format!("&:is(:where({class}){condition}{combinator}) {{\n{body}\n}}")
+                }
                 "in" => match &v[1].0 {
                     ParsedUnit::String(param_1) => {
                         if param_1 == "has" || param_1 == "not" {
@@ -306,7 +1383,7 @@ impl EmitEnv {
                                 vec![(ParsedUnit::String(param_1.to_string()), empty_span())];
                             input.extend_from_slice(&v[2..]);
                             let res = self.resolve_internal_variant(body, input.as_slice())?;
-                            let cond = &res[1..res.find("{").unwrap()];
+                            let cond = res[1..res.find("{").unwrap()].trim_end();
                             format!("&:is(:where({cond}) *) {{\n{body}\n}}",)
                         } else {
                             let joined = v[1..]
@@ -324,53 +1401,6 @@ impl EmitEnv {
                         format!("&:is(:where({param_1}) *) {{\n{body}\n}}")
                     }
                 },
-                "group" => match &v[1].0 {
-                    ParsedUnit::String(param_1) => {
-                        let joined = v[1..]
-                            .iter()
-                            .map(|x| match &x.0 {
-                                ParsedUnit::String(s) => s.to_string(),
-                                _ => String::new(),
-                            })
-                            .collect::<Vec<String>>()
-                            .join("-");
-                        if let Some((param, group_name)) = param_1.split_once("/") {
-                            if param == "has" || param == "not" {
-                                let mut input =
-                                    vec![(ParsedUnit::String(param.to_string()), empty_span())];
-                                input.extend_from_slice(&v[2..]);
-                                let res = self.resolve_internal_variant(body, input.as_slice())?;
-                                let cond = &res[1..res.find("{").unwrap()];
-                                format!(
-                                    "&:is(:where(.group{}){cond} *) {{\n{body}\n}}",
-                                    escape_string_for_css(&format!("/{group_name}")),
-                                )
-                            } else {
-                                format!(
-                                    "&:is(:where(.group{}):is(:{joined}) *) {{\n{body}\n}}",
-                                    escape_string_for_css(&format!("/{group_name}")),
-                                )
-                            }
-                        } else if param_1 == "has" || param_1 == "not" {
-                            let mut input =
-                                vec![(ParsedUnit::String(param_1.to_string()), empty_span())];
-                            input.extend_from_slice(&v[2..]);
-                            let res = self.resolve_internal_variant(body, input.as_slice())?;
-                            let cond = &res[1..res.find("{").unwrap()];
-                            format!("&:is(:where(.group){cond} *) {{\n{body}\n}}",)
-                        } else {
-                            format!("&:is(:where(.group):is(:{joined}) *) {{\n{body}\n}}",)
-                        }
-                    }
-                    ParsedUnit::Raw(param_1) => {
-                        if param_1.contains("&") {
-                            let replaced = param_1.replace("&", ":where(.group) *");
-                            format!("&:is({replaced}) {{\n{body}\n}}")
-                        } else {
-                            format!("&:is(:where(.group):is({param_1}) *) {{\n{body}\n}}")
-                        }
-                    }
-                },
                 _ => return None,
             },
             _ => return None,
@@ -378,94 +1408,1311 @@ impl EmitEnv {
     }
 
     pub fn new_with_default_config() -> Self {
-        let mut res = EmitEnv {
-            defs: Vec::new(),
-            utilities: Vec::new(),
-            variants: Vec::new(),
-            theme: Theme {
-                vars: HashMap::new(),
-                keyframes: HashMap::new(),
-            },
-            defs_generated: HashSet::new(),
-            custom_properties: Vec::new(),
-        };
-        res.load_config(DEFAULT_CONFIG);
-        res.load_config(THEME_CONFIG);
+        let mut res = EmitEnv::new();
+        if res.try_load_named_config("default", DEFAULT_CONFIG).is_ok() {
+            res.default_config = Some(res.config_names.len() - 1);
+        }
+        let _ = res.try_load_named_config("default theme", THEME_CONFIG);
         res
     }
 
     pub fn new() -> Self {
-        let res = EmitEnv {
+        EmitEnv {
             defs: Vec::new(),
             utilities: Vec::new(),
             variants: Vec::new(),
-            theme: Theme {
-                vars: HashMap::new(),
-                keyframes: HashMap::new(),
-            },
+            theme: Theme::default(),
+            named_themes: BTreeMap::new(),
+            variant_themes: BTreeMap::new(),
             defs_generated: HashSet::new(),
             custom_properties: Vec::new(),
-        };
-        res
+            diagnostics: Vec::new(),
+            options: EmitOptions::default(),
+            negative_cache: LruSet::new(NEGATIVE_CACHE_CAPACITY),
+            source_refs: SourceRefs::default(),
+            buckets: BTreeMap::new(),
+            static_utilities: HashMap::new(),
+            variant_aliases: BTreeMap::new(),
+            pseudo_element_variants: PSEUDO_ELEMENT_VARIANTS
+                .iter()
+                .map(|(name, pseudo_element)| (name.to_string(), pseudo_element.to_string()))
+                .collect(),
+            config_names: Vec::new(),
+            default_config: None,
+            var_origins: HashMap::new(),
+        }
+    }
+
+    /// loads one of the bundled config packs, returns false if there is no preset with that name
+    /// (or it was disabled with its cargo feature)
+    pub fn load_preset(&mut self, name: &str) -> bool {
+        match PRESETS.iter().find(|(preset_name, _)| *preset_name == name) {
+            Some((_, preset_src)) => self
+                .try_load_named_config(&format!("preset {name}"), preset_src)
+                .is_ok(),
+            None => false,
+        }
     }
 
     pub fn load_config(&mut self, s: &str) -> bool {
-        let parsed_config = config_parser().parse(s).into_result().ok();
-        if let Some(parsed_config) = parsed_config {
+        self.try_load_config(s).is_ok()
+    }
+
+    /// like `load_config` but says why the config couldn't be loaded
+    pub fn try_load_config(&mut self, s: &str) -> Result<(), Vec<ConfigError>> {
+        let name = format!("config {}", self.config_names.len() + 1);
+        self.try_load_named_config(&name, s)
+    }
+
+    /// loads a config under a name, like its path, that `utility_origin` and friends report.
+    /// definitions of later configs override those of earlier ones: utilities and variants with
+    /// the same name (when both match a class) and theme vars. with
+    /// `EmitOptions::error_on_conflict` a config that overrides something isn't loaded
+    #[tracing::instrument(level = "debug", skip_all, fields(len = s.len()))]
+    pub fn try_load_named_config(&mut self, name: &str, s: &str) -> Result<(), Vec<ConfigError>> {
+        let parsed_config = config_parser().parse(s).into_result().map_err(|errors| {
+            errors
+                .into_iter()
+                .map(|error| ConfigError {
+                    message: error.to_string(),
+                    span: error.span().into_range(),
+                })
+                .collect::<Vec<_>>()
+        });
+        if let Ok(mut parsed_config) = parsed_config {
+            if self.options.error_on_conflict {
+                let conflicts = self.conflicts(&parsed_config);
+                if !conflicts.is_empty() {
+                    return Err(conflicts);
+                }
+            }
+            let config = self.config_names.len();
+            self.config_names.push(name.to_string());
+            for utility in &mut parsed_config.utilities {
+                utility.config = config;
+            }
+            for variant in &mut parsed_config.variants {
+                variant.config = config;
+            }
+
+            tracing::debug!(
+                utilities = parsed_config.utilities.len(),
+                variants = parsed_config.variants.len(),
+                themes = parsed_config.themes.len(),
+                "loaded config"
+            );
+
+            for preset in parsed_config.presets {
+                if !self.load_preset(&preset) {
+                    let diagnostic = Diagnostic::UnknownPreset(preset);
+                    if !self.diagnostics.contains(&diagnostic) {
+                        self.diagnostics.push(diagnostic);
+                    }
+                }
+            }
+
+            for path in parsed_config.plugins {
+                let loaded = plugin::load_plugin_config(&path).and_then(|plugin_config| {
+                    self.try_load_named_config(&path, &plugin_config)
+                        .map_err(|_| "couldn't load the config it returned".to_string())
+                });
+                if let Err(reason) = loaded {
+                    self.diagnostics
+                        .push(Diagnostic::PluginLoadFailed(path, reason));
+                }
+            }
+
             self.utilities.extend(parsed_config.utilities);
             self.variants.extend(parsed_config.variants);
 
-            for theme in parsed_config.themes {
-                self.theme.vars.extend(theme.vars);
+            self.pseudo_element_variants
+                .extend(parsed_config.pseudo_elements);
+            for (name, target) in parsed_config.variant_aliases {
+                match parse_variant_chain(&target) {
+                    Some(variants) => {
+                        self.variant_aliases.insert(name, variants);
+                    }
+                    None => self
+                        .diagnostics
+                        .push(Diagnostic::InvalidVariantAlias(name, target)),
+                }
+            }
+
+            for mut theme in parsed_config.themes {
+                let named_theme = match (&theme.name, &theme.variant) {
+                    (Some(name), _) => self.named_themes.get(name),
+                    (None, Some(variant)) => self.variant_themes.get(variant),
+                    (None, None) => None,
+                };
+                theme.pair_scheme_vars(|var| {
+                    named_theme
+                        .and_then(|named_theme| named_theme.vars.get(var))
+                        .or_else(|| self.theme.vars.get(var))
+                        .cloned()
+                });
                 self.theme.keyframes.extend(theme.keyframes);
+                if let Some(variant) = theme.variant {
+                    if !self.variants.iter().any(|known| known.name == variant) {
+                        self.diagnostics
+                            .push(Diagnostic::UnknownThemeVariant(variant));
+                        continue;
+                    }
+                    self.theme.dynamic_vars.extend(theme.vars.keys().cloned());
+                    self.variant_themes
+                        .entry(variant.clone())
+                        .or_insert_with(|| Theme {
+                            variant: Some(variant),
+                            ..Default::default()
+                        })
+                        .vars
+                        .extend(theme.vars);
+                } else if let Some(name) = theme.name {
+                    self.theme.dynamic_vars.extend(theme.vars.keys().cloned());
+                    self.named_themes
+                        .entry(name.clone())
+                        .or_insert_with(|| Theme {
+                            name: Some(name),
+                            ..Default::default()
+                        })
+                        .vars
+                        .extend(theme.vars);
+                } else {
+                    self.var_origins
+                        .extend(theme.vars.keys().map(|var| (var.clone(), config)));
+                    self.theme.vars.extend(theme.vars);
+                }
+            }
+
+            let mut cycles = self.theme.var_cycles();
+            for named_theme in self
+                .named_themes
+                .values()
+                .chain(self.variant_themes.values())
+            {
+                let mut merged = self.theme.clone();
+                merged.vars.extend(named_theme.vars.clone());
+                cycles.extend(merged.var_cycles());
+            }
+
+            for cycle in cycles {
+                let diagnostic = Diagnostic::ThemeVarCycle(cycle);
+                if !self.diagnostics.contains(&diagnostic) {
+                    self.diagnostics.push(diagnostic);
+                }
             }
-            true
+            // the new utilities and theme change what a class resolves to
+            self.clear_resolution_caches();
+            self.check_value_namespaces();
+            Ok(())
         } else {
-            false
+            tracing::debug!("couldn't parse config");
+            parsed_config.map(|_| ())
         }
     }
 
+    /// what a config would override with a different definition, with the span of the
+    /// definition in its source. defining something again the same way isn't a conflict
+    fn conflicts(&self, config: &UserConfig) -> Vec<ConfigError> {
+        let mut res = Vec::new();
+        let mut conflict = |what: String, origin: usize, span: &Range<usize>| {
+            res.push(ConfigError {
+                message: format!("{what} is already defined by {}", self.config_names[origin]),
+                span: span.clone(),
+            });
+        };
+        for utility in &config.utilities {
+            if let Some(known) =
+                self.utilities.iter().rev().find(|known| {
+                    known.name == utility.name && known.has_value == utility.has_value
+                })
+                && !known.same_definition(utility)
+            {
+                let name = utility.display_name();
+                conflict(format!("utility {name}"), known.config, &utility.span);
+            }
+        }
+        for variant in &config.variants {
+            if let Some(known) = self
+                .variants
+                .iter()
+                .rev()
+                .find(|known| known.name == variant.name)
+                && !known
+                    .body
+                    .split_whitespace()
+                    .eq(variant.body.split_whitespace())
+            {
+                conflict(
+                    format!("variant {}", variant.name),
+                    known.config,
+                    &variant.span,
+                );
+            }
+        }
+        for theme in config
+            .themes
+            .iter()
+            .filter(|theme| theme.name.is_none() && theme.variant.is_none())
+        {
+            for (var, value) in &theme.vars {
+                if let Some(&origin) = self.var_origins.get(var)
+                    && self.theme.vars.get(var) != Some(value)
+                    && let Some(span) = theme.var_spans.get(var)
+                {
+                    conflict(format!("theme var --{var}"), origin, span);
+                }
+            }
+        }
+        res.sort_by_key(|error| error.span.start);
+        res
+    }
+
+    /// names of the loaded configs in load order
+    pub fn config_names(&self) -> &[String] {
+        &self.config_names
+    }
+
+    /// the config the utility comes from, `name` as written after `@utility`, like `p-*`. with
+    /// several definitions the last config wins
+    pub fn utility_origin(&self, name: &str) -> Option<&str> {
+        let (utility_name, has_value) = match name.strip_suffix("-*") {
+            Some(utility_name) => (utility_name, true),
+            None => (name, false),
+        };
+        let utility = self
+            .utilities
+            .iter()
+            .rev()
+            .find(|utility| utility.name == utility_name && utility.has_value == has_value)?;
+        self.config_names.get(utility.config).map(String::as_str)
+    }
+
+    pub fn variant_origin(&self, name: &str) -> Option<&str> {
+        let variant = self
+            .variants
+            .iter()
+            .rev()
+            .find(|variant| variant.name == name)?;
+        self.config_names.get(variant.config).map(String::as_str)
+    }
+
+    /// the config that set a theme var (without `--`) last
+    pub fn theme_var_origin(&self, name: &str) -> Option<&str> {
+        let config = *self.var_origins.get(name)?;
+        self.config_names.get(config).map(String::as_str)
+    }
+
+    /// reports `--value()` calls whose theme namespaces (`--icon-*`) have no vars at all, every
+    /// use of them would silently not match. checked again on every load, a later config can
+    /// add the vars
+    fn check_value_namespaces(&mut self) {
+        self.diagnostics
+            .retain(|diagnostic| !matches!(diagnostic, Diagnostic::EmptyValueNamespace(..)));
+        let mut has_vars = HashMap::new();
+        let mut found = Vec::new();
+        for utility in &self.utilities {
+            for namespaces in utility.value_namespaces() {
+                let any_vars = namespaces.iter().any(|&(before, after)| {
+                    *has_vars.entry((before, after)).or_insert_with(|| {
+                        let themes = self
+                            .named_themes
+                            .values()
+                            .chain(self.variant_themes.values());
+                        self.theme
+                            .vars
+                            .keys()
+                            .chain(self.theme.dynamic_vars.iter())
+                            .chain(themes.flat_map(|theme| theme.vars.keys()))
+                            .any(|var| {
+                                var.len() > before.len() + after.len()
+                                    && var.starts_with(before)
+                                    && var.ends_with(after)
+                            })
+                    })
+                });
+                if !any_vars {
+                    let name = utility.display_name();
+                    let namespaces = namespaces
+                        .iter()
+                        .map(|(before, after)| format!("--{before}*{after}"))
+                        .collect::<Vec<_>>()
+                        .join(", ");
+                    let diagnostic = Diagnostic::EmptyValueNamespace(name, namespaces);
+                    if !found.contains(&diagnostic) {
+                        found.push(diagnostic);
+                    }
+                }
+            }
+        }
+        self.diagnostics.extend(found);
+    }
+
+    #[tracing::instrument(level = "debug", skip(self))]
     pub fn to_css_stylesheet(&self, with_preflight: bool) -> String {
-        let mut result = if with_preflight {
-            PREFLIGHT.to_string()
+        let rules = self.rules_to_css(self.defs.iter(), true);
+        let mut result = self.stylesheet_head(with_preflight, &rules);
+        result.push_str(&rules);
+
+        if self.options.shadow_dom {
+            result = shadow_stylesheet(&result);
+        }
+        self.finish_stylesheet(result)
+    }
+
+    /// with media queries merged, lowered for `EmitOptions::targets` and minified as the options
+    /// say, the last step of every emitted stylesheet
+    fn finish_stylesheet(&self, css: String) -> String {
+        let css = if self.options.merge_media_queries {
+            merge_media_queries(&css)
+        } else {
+            css
+        };
+        let css = if self.targets_support(Feature::MediaRanges) {
+            css
         } else {
-            String::new()
+            lower_media_ranges(&css)
         };
-        result.push_str(":root {\n");
-        for var in self.theme.vars.iter() {
-            result.push_str(&format!("--{}: {};\n", var.0, var.1));
+        if self.options.minify {
+            minify_css(&css)
+        } else {
+            css
         }
-        result.push_str("}\n");
+    }
+
+    /// whether every browser of `EmitOptions::targets` supports `feature`
+    fn targets_support(&self, feature: Feature) -> bool {
+        self.options
+            .targets
+            .is_none_or(|targets| targets.supports(feature))
+    }
 
-        for keyframes in self.theme.keyframes.iter() {
+    /// `@media {condition} { body }`, stacked width variants like `sm:max-lg:` become one range
+    /// `@media (width >= 40rem) and (width < 64rem)`. without range syntax the queries stay
+    /// nested, `lower_media_ranges` only lowers queries with a single condition
+    fn width_media_block(&self, condition: &str, body: &str) -> String {
+        if self.targets_support(Feature::MediaRanges)
+            && let [
+                Item::Block {
+                    prelude, source, ..
+                },
+            ] = parse_items(body).as_slice()
+            && let Some(inner) = prelude.strip_prefix("@media ")
+            && inner.trim_start().starts_with("(width")
+            && !inner.contains(',')
+            && !inner.contains(" or ")
+        {
+            let open = source.find('{').expect("blocks have a brace");
+            let inner_body = &source[open + 1..source.len() - 1];
+            return format!("@media {} and {condition} {{{inner_body}}}", inner.trim());
+        }
+        format!("@media {condition} {{\n{body}\n}}")
+    }
+
+    /// `EmitOptions::preflight` or the bundled preflight, not copied until it's emitted
+    pub fn preflight(&self) -> &str {
+        self.options.preflight.as_deref().unwrap_or(PREFLIGHT)
+    }
+
+    /// only the preflight, scoped like the stylesheet, for serving it separately from
+    /// `to_css_stylesheet(false)`
+    pub fn to_preflight_stylesheet(&self) -> String {
+        let mut result = match &self.options.scope {
+            Some(scope) => self.scoped(self.preflight(), scope),
+            None => self.preflight().to_string(),
+        };
+        if self.options.shadow_dom {
+            result = shadow_stylesheet(&result);
+        }
+        self.finish_stylesheet(result)
+    }
+
+    /// `defs` in the order of the stylesheet, see `EmitOptions::rule_order`
+    fn sorted_defs<'a>(&self, defs: impl Iterator<Item = &'a CssDef>) -> Vec<&'a CssDef> {
+        let mut defs = defs.collect::<Vec<_>>();
+        match self.options.rule_order {
+            RuleOrder::Cascade => defs.sort_by_cached_key(|def| def.sort_key()),
+            RuleOrder::Source => {}
+            RuleOrder::Canonical => {
+                let mut group_ranks = HashMap::new();
+                for group in self.utilities.iter().filter_map(|u| u.group.as_deref()) {
+                    let rank = group_ranks.len();
+                    group_ranks.entry(group).or_insert(rank);
+                }
+                let mut variant_ranks = HashMap::new();
+                for variant in &self.variants {
+                    let rank = variant_ranks.len();
+                    variant_ranks.entry(variant.name.as_str()).or_insert(rank);
+                }
+                defs.sort_by_cached_key(|def| def.canonical_sort_key(&group_ranks, &variant_ranks));
+            }
+        }
+        if self.options.layers {
+            self.group_by_layer(&mut defs);
+        }
+        defs
+    }
+
+    /// the cascade layers in the order of the `@layer` statement, see `EmitOptions::layers`
+    pub fn layer_names(&self) -> Vec<&str> {
+        let mut res = DEFAULT_LAYERS.to_vec();
+        for layer in self.utilities.iter().filter_map(|u| u.layer.as_deref()) {
+            if !res.contains(&layer) {
+                res.push(layer);
+            }
+        }
+        res
+    }
+
+    /// moves the rules of every layer next to each other, in the order of the layers
+    fn group_by_layer(&self, defs: &mut [&CssDef]) {
+        let layers = self.layer_names();
+        defs.sort_by_key(|def| layers.iter().position(|l| *l == def.layer_name()));
+    }
+
+    /// the rules of `defs` with an `@layer` block around the rules of each layer, `defs` have
+    /// to be grouped by layer already
+    fn layered_rules_css(&self, defs: &[&CssDef]) -> String {
+        let mut res = String::new();
+        for layer_defs in defs.chunk_by(|a, b| a.layer_name() == b.layer_name()) {
+            let layer = layer_defs[0].layer_name();
+            res.push_str(&format!("@layer {layer} {{\n"));
+            res.push_str(&self.defs_css(layer_defs));
+            res.push_str("}\n");
+        }
+        res
+    }
+
+    /// the rules sorted like in the stylesheet, optionally followed by the `@property` rules of
+    /// the custom properties
+    fn rules_to_css<'a>(
+        &self,
+        defs: impl Iterator<Item = &'a CssDef>,
+        with_properties: bool,
+    ) -> String {
+        let defs = self.sorted_defs(defs);
+        let mut rules = self.scoped_rules(if self.options.layers {
+            self.layered_rules_css(&defs)
+        } else {
+            self.defs_css(&defs)
+        });
+
+        rules.push('\n');
+
+        if with_properties {
+            self.custom_properties
+                .iter()
+                .map(|prop| prop.to_css_def())
+                .for_each(|prop_css| {
+                    rules.push_str(&prop_css);
+                    rules.push('\n');
+                });
+        }
+
+        if self.options.inline_vars {
+            rules = self.theme.inline_vars(&rules);
+        }
+        if self.options.fold_calc {
+            rules = fold_calc(&rules, &self.theme);
+        }
+        rules
+    }
+
+    /// preflight, theme vars and keyframes, with inlined vars only those `rules` still use
+    fn stylesheet_head(&self, with_preflight: bool, rules: &str) -> String {
+        let mut result = String::new();
+        if self.options.layers {
+            result.push_str(&format!("@layer {};\n", self.layer_names().join(", ")));
+        }
+        let in_layer = |layer: &str, css: &str| {
+            if self.options.layers {
+                format!("@layer {layer} {{\n{css}}}\n")
+            } else {
+                css.to_string()
+            }
+        };
+        if with_preflight {
+            result.push_str(&in_layer("base", self.preflight()));
+        }
+        if !self.options.no_theme_vars {
+            result.push_str(&in_layer(
+                "theme",
+                &self.theme_vars_css(with_preflight, rules),
+            ));
+        }
+
+        // only the used ones, sorted so the output is the same on every run
+        let used = self.used_keyframes();
+        let mut keyframes = self
+            .theme
+            .keyframes
+            .iter()
+            .filter(|(name, _)| {
+                used.contains(name.as_str()) || self.options.keep_keyframes.contains(*name)
+            })
+            .collect::<Vec<_>>();
+        keyframes.sort_unstable_by_key(|(name, _)| *name);
+        for keyframes in keyframes {
             result.push_str(&format!("@keyframes {} ", keyframes.0));
-            result.push_str(&keyframes.1);
+            result.push_str(keyframes.1);
             result.push('\n');
         }
 
-        for def in self.defs.iter() {
-            result.push_str(&def.to_css());
+        if let Some(scope) = &self.options.scope {
+            result = self.scoped(&result, scope);
         }
+        result
+    }
+
+    /// `css` scoped to `scope` the way `EmitOptions::scope_at_rule` says
+    fn scoped(&self, css: &str, scope: &str) -> String {
+        if self.options.scope_at_rule {
+            scope_at_rule(css, scope)
+        } else {
+            scope_stylesheet(css, scope)
+        }
+    }
 
-        result.push('\n');
+    /// the generated rules in one `@scope` block with `EmitOptions::scope_at_rule`, each rule is
+    /// scoped on its own otherwise
+    fn scoped_rules(&self, rules: String) -> String {
+        match &self.options.scope {
+            Some(scope) if self.options.scope_at_rule => scope_at_rule(&rules, scope),
+            _ => rules,
+        }
+    }
+
+    /// only `:root` and the blocks of named and variant themes, for shipping the tokens
+    /// separately from a stylesheet emitted with `EmitOptions::no_theme_vars`
+    pub fn to_theme_vars_stylesheet(&self) -> String {
+        let rules = self.rules_to_css(self.defs.iter(), true);
+        let mut result = self.theme_vars_css(true, &rules);
+        if let Some(scope) = &self.options.scope {
+            result = self.scoped(&result, scope);
+        }
+        if self.options.shadow_dom {
+            result = shadow_stylesheet(&result);
+        }
+        self.finish_stylesheet(result)
+    }
 
-        self.custom_properties
+    /// the theme vars `rules` (and the preflight) use, directly or through other vars
+    fn used_theme_vars(&self, with_preflight: bool, rules: &str) -> HashSet<String> {
+        let mut used = HashSet::new();
+        let mut to_check = var_references(rules);
+        if with_preflight {
+            to_check.extend(var_references(self.preflight()));
+        }
+        while let Some(var) = to_check.pop() {
+            if self.theme.has_var(&var) && used.insert(var.clone()) {
+                if let Some(value) = self.theme.vars.get(&var) {
+                    to_check.extend(var_references(value));
+                }
+                for theme in self
+                    .named_themes
+                    .values()
+                    .chain(self.variant_themes.values())
+                {
+                    if let Some(value) = theme.vars.get(&var) {
+                        to_check.extend(var_references(value));
+                    }
+                }
+            }
+        }
+        used
+    }
+
+    /// `:root` and the theme blocks, with inlined vars only those `rules` (and the preflight)
+    /// still use
+    fn theme_vars_css(&self, with_preflight: bool, rules: &str) -> String {
+        let mut result = ":root {\n".to_string();
+        // `light-dark()` only picks the dark value if the page allows dark schemes
+        if (self.theme.vars.values())
+            .chain(
+                (self.named_themes.values())
+                    .chain(self.variant_themes.values())
+                    .flat_map(|theme| theme.vars.values()),
+            )
+            .any(|value| value.contains("light-dark("))
+        {
+            result.push_str("color-scheme: light dark;\n");
+        }
+        if self.options.inline_vars {
+            let used = self.used_theme_vars(with_preflight, rules);
+            for var in self
+                .theme
+                .sorted_vars()
+                .into_iter()
+                .filter(|var| used.contains(var.0))
+            {
+                result.push_str(&format!("--{}: {};\n", var.0, var.1));
+            }
+            result.push_str("}\n");
+
+            for (name, theme) in self.named_themes.iter() {
+                result.push_str(&format!("[data-theme=\"{name}\"] {{\n"));
+                for var in theme
+                    .sorted_vars()
+                    .into_iter()
+                    .filter(|var| used.contains(var.0))
+                {
+                    result.push_str(&format!("--{}: {};\n", var.0, var.1));
+                }
+                result.push_str("}\n");
+            }
+            for (variant, theme) in self.variant_themes.iter() {
+                result.push_str(
+                    &self.variant_theme_css(
+                        variant,
+                        theme
+                            .sorted_vars()
+                            .into_iter()
+                            .filter(|var| used.contains(var.0)),
+                    ),
+                );
+            }
+        } else {
+            for var in self.theme.sorted_vars() {
+                result.push_str(&format!("--{}: {};\n", var.0, var.1));
+            }
+            result.push_str("}\n");
+
+            for (name, theme) in self.named_themes.iter() {
+                result.push_str(&format!("[data-theme=\"{name}\"] {{\n"));
+                for var in theme.sorted_vars() {
+                    result.push_str(&format!("--{}: {};\n", var.0, var.1));
+                }
+                result.push_str("}\n");
+            }
+            for (variant, theme) in self.variant_themes.iter() {
+                result.push_str(&self.variant_theme_css(variant, theme.sorted_vars().into_iter()));
+            }
+        }
+        // the vars of `@theme dark` are nested in `:root`
+        self.flattened(result)
+    }
+
+    /// `css` without nesting if `EmitOptions::flatten_nesting` or the targets ask for it
+    fn flattened(&self, css: String) -> String {
+        if self.options.flatten_nesting || !self.targets_support(Feature::Nesting) {
+            flatten_nesting(&css)
+        } else {
+            css
+        }
+    }
+
+    /// the `@custom-variant` called `name`, the last one wins
+    fn find_variant(&self, name: &str) -> Option<&Variant> {
+        self.variants.iter().rev().find(|known| known.name == name)
+    }
+
+    /// whether `dark:` and `not-dark:` follow `EmitOptions::dark_mode` instead of a
+    /// `@custom-variant`, which they do unless a user config defines them
+    fn follows_dark_mode(&self, name: &str) -> bool {
+        matches!(name, "dark" | "not-dark")
+            && self.options.dark_mode != DarkMode::Media
+            && self
+                .find_variant(name)
+                .is_none_or(|variant| Some(variant.config) == self.default_config)
+    }
+
+    /// `body` under the variant called `name`, see `find_variant` and `follows_dark_mode`. with
+    /// `where_selectors` the selectors the variant adds are in `:where()`, see
+    /// `where_variant_selector`
+    fn instantiate_variant(&self, name: &str, body: &str, where_selectors: bool) -> Option<String> {
+        let wrap = |selector: String| {
+            if where_selectors {
+                where_variant_selector(&selector)
+            } else {
+                selector
+            }
+        };
+        if !self.follows_dark_mode(name) {
+            let variant = self.find_variant(name)?;
+            if !where_selectors {
+                return Some(variant.instantiate(body));
+            }
+            let (before, after) = variant.body.split_at(variant.target);
+            return Some(format!(
+                "{}{body}{}",
+                map_nested_selectors(before, where_variant_selector),
+                map_nested_selectors(after, where_variant_selector)
+            ));
+        }
+        let dark = name == "dark";
+        let under = |dark_selector: &str, body: &str| {
+            let prelude = if dark {
+                format!("&:where({dark_selector}, {dark_selector} *)")
+            } else {
+                format!("&:not(:where({dark_selector}, {dark_selector} *))")
+            };
+            format!("{} {{\n{body}\n}}", wrap(prelude))
+        };
+        Some(match self.options.dark_mode.selector() {
+            Some(selector) => under(selector, body),
+            // dark with `.dark`, or with a dark system theme unless there's `.light`
+            None if dark => format!(
+                "@media (prefers-color-scheme: dark) {{\n{} {{\n{body}\n}}\n}}\n{}",
+                wrap("&:not(:where(.light, .light *))".to_string()),
+                under(".dark", body)
+            ),
+            None => under(
+                ".dark",
+                &format!(
+                    "@media not (prefers-color-scheme: dark) {{\n{body}\n}}\n{} {{\n{body}\n}}",
+                    wrap("&:where(.light, .light *)".to_string())
+                ),
+            ),
+        })
+    }
+
+    /// the vars of a `@theme dark { ... }`, in `:root` nested in the custom variant
+    fn variant_theme_css<'a>(
+        &self,
+        variant: &str,
+        vars: impl Iterator<Item = (&'a String, &'a String)>,
+    ) -> String {
+        let vars = vars
+            .map(|(name, value)| format!("\n--{name}: {value};"))
+            .collect::<String>();
+        match self.instantiate_variant(variant, &vars, false) {
+            Some(vars) => format!(":root {{\n{vars}\n}}\n"),
+            None => String::new(),
+        }
+    }
+
+    /// `CssDef::to_css` under `EmitOptions::scope` if the rule isn't scoped already
+    fn def_to_css(&self, css_def: &CssDef) -> String {
+        self.rule_css(&[css_def])
+    }
+
+    /// one rule for `defs`, which all have the same body and are either all scoped with
+    /// `CompileOptions::scope_selector` or none are
+    fn rule_css(&self, defs: &[&CssDef]) -> String {
+        let selectors = defs
             .iter()
-            .map(|prop| prop.to_css_def())
-            .for_each(|prop_css| {
-                result.push_str(&prop_css);
-                result.push('\n');
-            });
+            .map(|css_def| css_def.selector())
+            .collect::<Vec<_>>();
+        let mut body = Cow::Borrowed(defs[0].body.as_str());
+        if self.options.important {
+            body = Cow::Owned(mark_important(&body));
+        }
+        if self.options.color_fallbacks || !self.targets_support(Feature::ModernColors) {
+            body = Cow::Owned(srgb_fallbacks(&body));
+        }
+        let css = format!("{}{{\n{body}\n}}\n", selectors.join(",\n"));
+        let mut css = self.flattened(match &self.options.scope {
+            Some(scope) if defs[0].scope_selector.is_none() && !self.options.scope_at_rule => {
+                scope_stylesheet(&css, scope)
+            }
+            _ => css,
+        });
+        if self.options.debug_comments {
+            for css_def in defs.iter().rev() {
+                // a class can contain `*/` in an arbitrary value
+                let class = unescape_class_name(&css_def.class_name).replace("*/", "* /");
+                let comment = match &css_def.origin {
+                    Some(origin) => {
+                        format!("/* from: {}:{} — {class} */\n", origin.file, origin.line)
+                    }
+                    None => format!("/* {class} */\n"),
+                };
+                css.insert_str(0, &comment);
+            }
+        }
+        css
+    }
 
-        result
+    /// the rules generated since `defs` had `start` entries, for sending only what changed
+    pub fn defs_to_css_since(&self, start: usize) -> String {
+        let mut defs = self.defs[start.min(self.defs.len())..]
+            .iter()
+            .collect::<Vec<_>>();
+        let rules = self.scoped_rules(if self.options.layers {
+            self.group_by_layer(&mut defs);
+            self.layered_rules_css(&defs)
+        } else {
+            self.defs_css(&defs)
+        });
+        if self.options.inline_vars {
+            self.finish_stylesheet(self.theme.inline_vars(&rules))
+        } else {
+            self.finish_stylesheet(rules)
+        }
+    }
+
+    /// the generated rules as json, keyed on the class as written, see `export::defs_to_json`
+    pub fn defs_to_json(&self) -> String {
+        let mut defs = self.defs.iter().collect::<Vec<_>>();
+        defs.sort_by_cached_key(|def| def.sort_key());
+        export::defs_to_json(&defs)
+    }
+
+    /// the `@keyframes` the generated rules animate with, named in an `animation` /
+    /// `animation-name` like `animate-[wiggle_1s_ease-in-out_infinite]` or in a theme var it
+    /// references like `--animate-spin`
+    pub fn used_keyframes(&self) -> BTreeSet<&str> {
+        let mut values = Vec::new();
+        for css_def in self.defs.iter() {
+            for range in declaration_ranges(&css_def.body) {
+                if let Some((property, value)) = css_def.body[range].split_once(':')
+                    && matches!(property.trim(), "animation" | "animation-name")
+                {
+                    values.push(value.to_string());
+                }
+            }
+        }
+
+        let mut checked_vars = HashSet::new();
+        let mut words = HashSet::new();
+        while let Some(value) = values.pop() {
+            for var in var_references(&value) {
+                if checked_vars.insert(var.clone()) {
+                    let themes = std::iter::once(&self.theme)
+                        .chain(self.named_themes.values())
+                        .chain(self.variant_themes.values());
+                    values.extend(themes.filter_map(|theme| theme.vars.get(&var).cloned()));
+                }
+            }
+            words.extend(
+                value
+                    .split(|c: char| !is_valid_css_char(c))
+                    .map(str::to_string),
+            );
+        }
+
+        self.theme
+            .keyframes
+            .keys()
+            .filter(|name| words.contains(name.as_str()))
+            .map(String::as_str)
+            .collect()
+    }
+
+    /// numbers about the stylesheet `to_css_stylesheet` would generate
+    pub fn metrics(&self, with_preflight: bool) -> Metrics {
+        let output = self.to_css_stylesheet(with_preflight);
+        let rules = self.defs.iter().map(CssDef::to_css).collect::<String>();
+
+        let declarations = NormalizedStylesheet::parse(&rules)
+            .map(|sheet| sheet.rules.values().map(|decls| decls.len()).sum())
+            .unwrap_or_default();
+
+        // the themes `theme_vars_css` emits, a variant theme only if its variant exists
+        let themes = || {
+            std::iter::once(&self.theme)
+                .chain(self.named_themes.values())
+                .chain(
+                    (self.variant_themes.iter())
+                        .filter(|(variant, _)| {
+                            self.instantiate_variant(variant, "", false).is_some()
+                        })
+                        .map(|(_, theme)| theme),
+                )
+        };
+        let theme_vars_emitted = if self.options.no_theme_vars {
+            0
+        } else if self.options.inline_vars {
+            let rules = self.rules_to_css(self.defs.iter(), true);
+            let used = self.used_theme_vars(with_preflight, &rules);
+            themes()
+                .flat_map(|theme| theme.vars.keys())
+                .filter(|var| used.contains(*var))
+                .count()
+        } else {
+            themes().map(|theme| theme.vars.len()).sum()
+        };
+
+        Metrics {
+            rules: self.defs.len(),
+            declarations,
+            theme_vars_emitted,
+            theme_vars_total: self.theme.vars.len()
+                + (self.named_themes.values())
+                    .chain(self.variant_themes.values())
+                    .map(|theme| theme.vars.len())
+                    .sum::<usize>(),
+            keyframes_used: self.used_keyframes().len(),
+            keyframes_total: self.theme.keyframes.len(),
+            preflight_bytes: if with_preflight {
+                self.preflight().len()
+            } else {
+                0
+            },
+            output_bytes: output.len(),
+            estimated_gzip_bytes: metrics::estimate_gzip_size(&output),
+        }
+    }
+
+    /// like `to_css_stylesheet` but with sorted selectors and declarations and canonical
+    /// whitespace, meant for snapshot tests where incidental ordering changes shouldn't matter
+    pub fn to_normalized_css(&self, with_preflight: bool) -> String {
+        NormalizedStylesheet::parse(&self.to_css_stylesheet(with_preflight))
+            .expect("generated stylesheet should be parsable")
+            .to_css()
+    }
+
+    /// the part of `src` the lexer can look at, `None` if the outcome could depend on more than
+    /// that (raw values may contain whitespace, a variant group goes on after a `,`) or it's
+    /// trivially not a candidate
+    fn candidate_word<'a>(options: &CompileOptions, src: &'a str) -> Option<&'a str> {
+        let prefix_len = match &options.prefix {
+            Some(prefix) if src.starts_with(prefix.as_str()) => prefix.len(),
+            Some(_) => return None,
+            None => 0,
+        };
+        let word_len = src[prefix_len..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || "/#@%*[].-()_:\\!".contains(c)))
+            .map_or(src.len(), |len| prefix_len + len);
+        let word = &src[..word_len];
+        let group_closed = word.matches('(').count() == word.matches(')').count();
+        (word_len > prefix_len && !word[prefix_len..].contains('[') && group_closed).then_some(word)
+    }
+
+    /// documentation of the utility a class uses, for hovers and style guides. variants are
+    /// ignored, `hover:p-4` gives the doc of `@utility p-*`
+    pub fn utility_doc(&self, class: &str) -> Option<&Doc> {
+        let utility = class.rsplit(':').next()?.trim_start_matches('-');
+        self.utilities
+            .iter()
+            .filter(|known| {
+                if known.has_value {
+                    utility
+                        .strip_prefix(known.name.as_str())
+                        .is_some_and(|value| value.starts_with('-'))
+                } else {
+                    known.name == utility
+                }
+            })
+            .max_by_key(|known| known.name.len())?
+            .doc
+            .as_ref()
+    }
+
+    /// documentation of a `@custom-variant`, the last one wins if it's defined more than once
+    pub fn variant_doc(&self, name: &str) -> Option<&Doc> {
+        self.variants
+            .iter()
+            .rev()
+            .find(|variant| variant.name == name)?
+            .doc
+            .as_ref()
+    }
+
+    /// the `@group`s of the loaded utilities
+    pub fn utility_groups(&self) -> BTreeSet<&str> {
+        self.utilities
+            .iter()
+            .filter_map(|utility| utility.group.as_deref())
+            .collect()
+    }
+
+    /// a suggestion for a candidate that didn't resolve, like a utility with a similar name
+    pub fn unresolved_hint(&self, candidate: &str) -> Option<String> {
+        let utility = candidate.rsplit(':').next()?.trim_start_matches('-');
+        let (distance, name, has_value) = self.closest_utility(utility)?;
+
+        if distance == 0 && has_value {
+            Some(format!(
+                "`{name}-*` exists, but `{}` isn't one of its values",
+                utility[name.len()..].trim_start_matches('-')
+            ))
+        } else if distance <= 2 && distance < utility.len().min(name.len()) {
+            Some(format!(
+                "did you mean `{name}{}`?",
+                if has_value { "-*" } else { "" }
+            ))
+        } else {
+            None
+        }
+    }
+
+    /// whether a candidate that didn't resolve names a utility with values but not one of them,
+    /// like `bg-red-510`
+    fn has_unknown_value(&self, candidate: &str) -> bool {
+        let utility = candidate.rsplit(':').next().unwrap_or(candidate);
+        self.closest_utility(utility.trim_start_matches('-'))
+            .is_some_and(|(distance, _, has_value)| distance == 0 && has_value)
     }
 
-    pub fn parse_tailwind_str<'prefix, 'src>(
+    /// the utility whose name is closest to `utility` by edit distance, the distance and whether
+    /// it takes a value. utilities with values are compared to as many segments as they have
+    fn closest_utility(&self, utility: &str) -> Option<(usize, &str, bool)> {
+        self.utilities
+            .iter()
+            .filter(|known| self.options.utility_enabled(known))
+            .map(|known| {
+                let compared = if known.has_value {
+                    let segments = known.name.split('-').count();
+                    utility
+                        .split('-')
+                        .take(segments)
+                        .collect::<Vec<_>>()
+                        .join("-")
+                } else {
+                    utility.to_string()
+                };
+                (
+                    levenshtein(&compared, &known.name),
+                    known.name.as_str(),
+                    known.has_value,
+                )
+            })
+            .min()
+    }
+
+    /// compiles a list of class names instead of scanning text for candidates, returns the newly
+    /// generated rules
+    pub fn compile_classes<'a>(
+        &mut self,
+        options: &CompileOptions,
+        classes: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<CssDef> {
+        let mut generated = Vec::new();
+        for class in classes {
+            if let Some((css_defs, _)) = self.parse_tailwind_str(options, class) {
+                generated.extend(css_defs);
+            } else if options.strict
+                && !self
+                    .defs_generated
+                    .contains(&Self::generated_key(options, &escape_string_for_css(class)))
+            {
+                let diagnostic = Diagnostic::UnknownClass(class.to_string());
+                if !self.diagnostics.contains(&diagnostic) {
+                    self.diagnostics.push(diagnostic);
+                }
+            }
+        }
+        generated
+    }
+
+    /// like `parse_full_string` but remembers which rules `file_id` references, replacing what
+    /// was remembered from an earlier scan of it. rules that were generated for sources and no
+    /// source references anymore are dropped
+    pub fn parse_source(&mut self, options: &CompileOptions, file_id: &str, txt: &str) -> Delta {
+        self.parse_source_observed(options, file_id, txt, None)
+    }
+
+    /// `parse_source` calling `on_candidate` for every candidate, see `scan_references`
+    pub(crate) fn parse_source_observed(
         &mut self,
-        prefix: Option<&'prefix str>,
-        mut src: &'src str,
-    ) -> Option<(CssDef, usize)> {
-        let leaked = src.to_string().leak() as &'static str;
+        options: &CompileOptions,
+        file_id: &str,
+        txt: &str,
+        mut on_candidate: Option<OnCandidate<'_>>,
+    ) -> Delta {
+        let first_new = self.defs.len();
+        let mut generated_at = Vec::new();
+        let mut record = |offset: usize, candidate: &str, outcome| {
+            if outcome == CandidateOutcome::Generated {
+                generated_at.push(offset);
+            }
+            if let Some(on_candidate) = on_candidate.as_deref_mut() {
+                on_candidate(offset, candidate, outcome);
+            }
+        };
+        let (mut added_rules, keys) = self.scan_references(options, txt, Some(&mut record));
+
+        let line_index = LineIndex::new(txt);
+        for ((css_def, added), offset) in self.defs[first_new..]
+            .iter_mut()
+            .zip(added_rules.iter_mut())
+            .zip(generated_at)
+        {
+            css_def.origin = Some(line_index.location(file_id, offset));
+            added.origin = css_def.origin.clone();
+        }
+        for css_def in &added_rules {
+            self.source_refs
+                .owned
+                .insert(Self::generated_key(options, &css_def.class_name));
+        }
+        let unused = self.source_refs.replace(file_id, keys);
+        Delta {
+            added_rules,
+            removed_rules: self.remove_rules(unused),
+        }
+    }
+
+    /// generates the rules for the candidates in `txt` like `parse_full_string`, returns the
+    /// newly generated rules and the keys of every rule the text references. `on_candidate` is
+    /// called with the offset, text and outcome of every candidate
+    pub(crate) fn scan_references(
+        &mut self,
+        options: &CompileOptions,
+        txt: &str,
+        mut on_candidate: Option<OnCandidate<'_>>,
+    ) -> (Vec<CssDef>, HashSet<String>) {
+        let mut generated = Vec::new();
+        let mut keys = HashSet::new();
+        let class_values = if on_candidate.is_some() {
+            class_attribute_values(txt)
+        } else {
+            Vec::new()
+        };
+        let mut i = 0;
+        while i < txt.len() {
+            if let Some((resolved, consumed)) = self.resolve_candidate(options, &txt[i..]) {
+                let (class_name, outcome) = match resolved {
+                    ResolvedCandidate::Generated(css_defs) => {
+                        let class_name = css_defs[0].class_name.clone();
+                        generated.extend(css_defs);
+                        (class_name, CandidateOutcome::Generated)
+                    }
+                    ResolvedCandidate::AlreadyGenerated(class_name) => {
+                        (class_name, CandidateOutcome::AlreadyGenerated)
+                    }
+                };
+                if let Some(on_candidate) = on_candidate.as_deref_mut() {
+                    on_candidate(i, &txt[i..i + consumed], outcome);
+                }
+                keys.insert(Self::generated_key(options, &class_name));
+                i += consumed;
+            } else if let Some(on_candidate) = on_candidate.as_deref_mut()
+                && let Some(word) = self.unresolved_word(options, txt, &class_values, i)
+            {
+                on_candidate(i, word, CandidateOutcome::Unresolved);
+            }
+            i += 1;
+            while i < txt.len() && !txt.is_char_boundary(i) {
+                i += 1;
+            }
+        }
+        (generated, keys)
+    }
+
+    /// forgets a source scanned with `parse_source`, e.g. because the file was deleted, and
+    /// returns the rules that were dropped because nothing references them anymore
+    pub fn retract_source(&mut self, file_id: &str) -> Vec<CssDef> {
+        let unused = self.source_refs.remove(file_id);
+        self.remove_rules(unused)
+    }
+
+    /// forgets the words known not to resolve and instantiates the static utilities again with
+    /// the current theme
+    fn clear_resolution_caches(&mut self) {
+        self.negative_cache.clear();
+        self.static_utilities = (self.utilities.iter().enumerate())
+            .filter(|(_, utility)| !utility.has_value)
+            .filter_map(|(index, utility)| {
+                let body = utility
+                    .instantiate(&self.theme, None, None, false, false)
+                    .ok()?;
+                Some((utility.name.clone(), (index, body)))
+            })
+            .collect();
+    }
+
+    /// drops generated rules, they're generated again the next time their class is seen
+    fn remove_rules(&mut self, keys: Vec<String>) -> Vec<CssDef> {
+        if keys.is_empty() {
+            return Vec::new();
+        }
+        let keys = keys.into_iter().collect::<HashSet<_>>();
+        for key in &keys {
+            self.defs_generated.remove(key);
+        }
+        // words in the cache may resolve to the dropped rules
+        self.clear_resolution_caches();
+        let (removed, kept) = std::mem::take(&mut self.defs)
+            .into_iter()
+            .partition(|css_def| keys.contains(&Self::def_key(css_def)));
+        self.defs = kept;
+        removed
+    }
+
+    /// replaces the variants defined with `@alias-variant` with the ones they stand for, `None`
+    /// if aliases point to each other in a cycle
+    fn expand_variant_aliases(
+        &self,
+        variants: &[Vec<Spanned<ParsedUnit>>],
+        depth: usize,
+    ) -> Option<Vec<Vec<Spanned<ParsedUnit>>>> {
+        if self.variant_aliases.is_empty() {
+            return Some(variants.to_vec());
+        }
+        if depth > MAX_VARIANT_ALIAS_DEPTH {
+            return None;
+        }
+
+        let mut res = Vec::with_capacity(variants.len());
+        for variant in variants {
+            let name = variant
+                .iter()
+                .map(|(unit, _)| match unit {
+                    ParsedUnit::String(s) => Some(s.as_str()),
+                    ParsedUnit::Raw(_) => None,
+                })
+                .collect::<Option<Vec<_>>>()
+                .map(|parts| parts.join("-"));
+            match name.and_then(|name| self.variant_aliases.get(&name)) {
+                Some(aliased) => res.extend(self.expand_variant_aliases(aliased, depth + 1)?),
+                None => res.push(variant.clone()),
+            }
+        }
+        Some(res)
+    }
+
+    /// `defs_generated` is keyed on the emitted rule, so the same class under another prefix,
+    /// scope or with other `CompileOptions::important` and `low_specificity` isn't dropped
+    fn generated_key(options: &CompileOptions, class_name: &str) -> String {
+        Self::rule_key(
+            &options.scope_selector,
+            options.important,
+            options.low_specificity,
+            class_name,
+        )
+    }
+
+    /// the `generated_key` of the options `css_def` was generated with
+    fn def_key(css_def: &CssDef) -> String {
+        Self::rule_key(
+            &css_def.scope_selector,
+            css_def.important,
+            css_def.low_specificity,
+            &css_def.class_name,
+        )
+    }
+
+    fn rule_key(
+        scope_selector: &Option<String>,
+        important: bool,
+        low_specificity: bool,
+        class_name: &str,
+    ) -> String {
+        let mut key = match scope_selector {
+            Some(scope_selector) => format!("{scope_selector} {class_name}"),
+            None => class_name.to_string(),
+        };
+        if low_specificity {
+            key = format!(":where({key})");
+        }
+        if important {
+            key.push_str(" !important");
+        }
+        key
+    }
+
+    /// generates the rules for the candidate at the start of `src`, returns `None` if there is no
+    /// resolvable candidate or it was generated before
+    pub fn parse_tailwind_str(
+        &mut self,
+        options: &CompileOptions,
+        src: &str,
+    ) -> Option<(Vec<CssDef>, usize)> {
+        match self.resolve_candidate(options, src)? {
+            (ResolvedCandidate::Generated(css_defs), end) => Some((css_defs, end)),
+            (ResolvedCandidate::AlreadyGenerated(_), _) => None,
+        }
+    }
+
+    /// like `parse_tailwind_str` but tells apart candidates that were already generated, the
+    /// returned length includes the prefix
+    pub fn resolve_candidate(
+        &mut self,
+        options: &CompileOptions,
+        mut src: &str,
+    ) -> Option<(ResolvedCandidate, usize)> {
+        let prefix = options.prefix.as_deref();
 
         if let Some(prefix) = prefix {
             if !src.starts_with(prefix) {
@@ -475,30 +2722,110 @@ impl EmitEnv {
             src = &src[prefix.len()..];
         }
 
-        let (toks, end) = lexer("test", leaked).parse(src).into_output()?;
+        let (toks, end) = lexer("test", "").parse(src).into_output()?;
         src = &src[..end];
+        let consumed = prefix.map_or(0, str::len) + end;
 
         let parsed = duckwind_parser(make_input)
-            .parse(make_input(make_eoi("test", leaked), toks.as_slice()))
+            .parse(make_input(make_eoi("test", ""), toks.as_slice()))
             .into_output()?;
 
-        let mut css_def = CssDef::default();
-        let class_name = escape_string_for_css(src);
+        let css_def = CssDef {
+            // `tw:` like in tailwind 4 has to be escaped too
+            class_name: format!(
+                "{}{}",
+                escape_string_for_css(prefix.unwrap_or_default()),
+                escape_string_for_css(src)
+            ),
+            scope_selector: options.scope_selector.clone(),
+            low_specificity: options.low_specificity,
+            important: options.important,
+            ..Default::default()
+        };
 
-        if self.defs_generated.contains(&class_name) {
-            return None;
+        let generated_key = Self::generated_key(options, &css_def.class_name);
+        if self.defs_generated.contains(&generated_key) {
+            tracing::trace!(class = css_def.class_name, "already generated");
+            return Some((
+                ResolvedCandidate::AlreadyGenerated(css_def.class_name),
+                consumed,
+            ));
         }
 
-        css_def.class_name = format!("{}{class_name}", prefix.unwrap_or_default());
+        // a variant group is a rule for every member, so they are ordered like the members
+        // written on their own
+        let members = group_members(parsed.0).filter(|members| !members.is_empty())?;
+        let mut generated = Vec::new();
+        for member in members {
+            let mut css_def = css_def.clone();
+            (css_def.body, css_def.group, css_def.layer) = self.utility_body(&member.utility)?;
+            css_def.properties = declared_properties(&css_def.body);
+            let variants = self.expand_variant_aliases(&member.variants, 0)?;
+            css_def.variant_count = variants.len();
+            css_def.variants = variants.iter().map(|v| variant_name(v)).collect();
+            self.apply_variants(&mut css_def, &variants, !self.options.no_where_variants)?;
+
+            if options.important || member.important {
+                css_def.body = mark_important(&css_def.body);
+            }
+            if options.low_specificity {
+                css_def.body = wrap_nested_selectors(&css_def.body);
+            }
+            generated.push(css_def);
+        }
+
+        self.defs.extend(generated.iter().cloned());
+        self.defs_generated.push(generated_key);
+        tracing::trace!(class = generated[0].class_name, "generated rule");
+        Some((ResolvedCandidate::Generated(generated), consumed))
+    }
+
+    /// the body of the rule for a utility without its variants, `None` if it doesn't exist.
+    /// `-mt-[5px]` is `mt-[5px]` with its value negated unless there is a `-mt-*` for it
+    fn utility_body(&mut self, utility: &[Spanned<ParsedUnit>]) -> Option<UtilityBody> {
+        if let Some(body) = self.utility_body_with_sign(utility, false) {
+            return Some(body);
+        }
+        let mut positive = utility.to_vec();
+        match positive.first_mut() {
+            Some((ParsedUnit::String(first), _)) if first.len() > 1 && first.starts_with('-') => {
+                first.remove(0);
+            }
+            _ => return None,
+        }
+        self.utility_body_with_sign(&positive, true)
+    }
 
+    fn utility_body_with_sign(
+        &mut self,
+        utility: &[Spanned<ParsedUnit>],
+        negative: bool,
+    ) -> Option<UtilityBody> {
         let mut body_to_set = None;
+        // config of the utility the body comes from, a utility of an earlier config doesn't
+        // override it
+        let mut body_config = None;
 
-        if parsed.0.utility.len() == 1
-            && let Some((ParsedUnit::Raw(raw_css), _)) = parsed.0.utility.first()
+        // `bg-[#ff0000]/50`, the modifier of a raw value is a segment of its own
+        let (utility, alpha) = match utility {
+            [
+                ..,
+                (ParsedUnit::Raw(_), _),
+                (ParsedUnit::String(modifier), _),
+            ] if modifier.starts_with('/') => (
+                &utility[..utility.len() - 1],
+                Some(modifier_alpha(&modifier[1..])?),
+            ),
+            _ => (utility, None),
+        };
+
+        if utility.len() == 1
+            && alpha.is_none()
+            && let Some((ParsedUnit::Raw(raw_css), _)) = utility.first()
         {
-            body_to_set = Some(raw_css.to_owned());
+            body_to_set = Some((raw_css.to_owned(), None, None));
         } else {
-            let mut pre = parsed.0.utility[..parsed.0.utility.len() - 1]
+            let mut pre = utility[..utility.len() - 1]
                 .iter()
                 .map(|x| {
                     let ParsedUnit::String(s) = x.0.clone() else {
@@ -508,7 +2835,7 @@ impl EmitEnv {
                 })
                 .collect::<Vec<_>>();
             let pre_len = pre.len();
-            let last = parsed.0.utility.last().cloned().unwrap();
+            let last = utility.last().cloned().unwrap();
             let pre_str = pre.join("-");
 
             match last.0 {
@@ -516,7 +2843,7 @@ impl EmitEnv {
                     let mut special_param = None;
                     if let Some((pre, special_param_val)) = last_str.split_once("/") {
                         if pre_str.starts_with("text")
-                            && self.theme.vars.contains_key(&format!(
+                            && self.theme.has_var(&format!(
                                 "text{}{}",
                                 if pre_len > 1 { "" } else { "-" },
                                 [&pre_str[4..], pre]
@@ -534,22 +2861,16 @@ impl EmitEnv {
                                     special_param_val[1..special_param_val.len() - 1].to_string(),
                                 ));
                             } else {
-                                special_param = Some(SpecialParam::LineHeight(format!(
-                                    "calc(var(--spacing) * {})",
-                                    special_param_val
-                                )));
+                                special_param = Some(SpecialParam::LineHeight(
+                                    self.theme.spacing(special_param_val, false),
+                                ));
                             }
                         }
 
                         if special_param.is_none() {
                             let idx = pre_str.find("-").unwrap_or(pre_str.len());
                             let after_idx = &pre_str[idx..];
-                            if self.theme.vars.contains_key(&format!(
-                                "color{}{}{}",
-                                after_idx,
-                                if after_idx.is_empty() { "" } else { "-" },
-                                pre
-                            )) {
+                            if self.theme.has_var(&format!("color{after_idx}-{pre}")) {
                                 special_param = Some(SpecialParam::Transparency(format!(
                                     "{special_param_val}%"
                                 )));
@@ -557,10 +2878,9 @@ impl EmitEnv {
                                 let css_literal = data_type_parser().parse(pre).into_output();
                                 if let Some(css_literal) = css_literal {
                                     if matches!(css_literal, CssLiteral::Number(..)) {
-                                        special_param = Some(SpecialParam::LineHeight(format!(
-                                            "calc(var(--spacing) * {})",
-                                            special_param_val
-                                        )));
+                                        special_param = Some(SpecialParam::LineHeight(
+                                            self.theme.spacing(special_param_val, false),
+                                        ));
                                     } else if matches!(css_literal, CssLiteral::Color(..)) {
                                         special_param = Some(SpecialParam::Transparency(format!(
                                             "{}%",
@@ -578,139 +2898,333 @@ impl EmitEnv {
                     pre.push(last_str.clone());
                     let full = pre.join("-");
 
-                    for utility in self.utilities.iter() {
-                        if utility.name.as_str() == full.as_str()
-                            && !utility.has_value
-                            && let Ok(res) = utility.instantiate(
-                                &self.theme,
-                                None,
-                                special_param.as_ref(),
-                                false,
-                            )
-                        {
-                            self.custom_properties
-                                .extend_from_slice(&utility.properties);
-                            body_to_set = Some(res);
+                    // the precomputed body if it's what resolving the utility would give,
+                    // otherwise it's resolved like any other
+                    let cached = self
+                        .static_utilities
+                        .get(full.as_str())
+                        .filter(|(index, _)| {
+                            special_param.is_none()
+                                && self.options.utility_enabled(&self.utilities[*index])
+                        });
+                    if let Some((index, body)) = cached
+                        && !negative
+                    {
+                        let utility = &self.utilities[*index];
+                        self.custom_properties
+                            .extend_from_slice(&utility.properties);
+                        self.theme
+                            .keyframes
+                            .extend(utility.keyframes.iter().cloned());
+                        body_to_set =
+                            Some((body.clone(), utility.group.clone(), utility.layer.clone()));
+                        body_config = Some(utility.config);
+                    } else if !negative {
+                        for utility in self.utilities.iter() {
+                            if utility.name == full
+                                && !utility.has_value
+                                && self.options.utility_enabled(utility)
+                                && let Ok(res) = utility.instantiate(
+                                    &self.theme,
+                                    None,
+                                    special_param.as_ref(),
+                                    false,
+                                    false,
+                                )
+                            {
+                                self.custom_properties
+                                    .extend_from_slice(&utility.properties);
+                                self.theme
+                                    .keyframes
+                                    .extend(utility.keyframes.iter().cloned());
+                                body_to_set =
+                                    Some((res, utility.group.clone(), utility.layer.clone()));
+                                body_config = Some(utility.config);
+                            }
                         }
                     }
                     for utility in self.utilities.iter() {
                         if utility.has_value
                             && full.starts_with(utility.name.as_str())
+                            && self.options.utility_enabled(utility)
                             && full.len() > utility.name.len()
+                            && body_config.is_none_or(|config| utility.config >= config)
                             && let Ok(res) = utility.instantiate(
                                 &self.theme,
                                 Some(&full[&utility.name.len() + 1..]),
                                 special_param.as_ref(),
                                 false,
+                                negative,
                             )
                         {
                             self.custom_properties
                                 .extend_from_slice(&utility.properties);
-                            body_to_set = Some(res);
+                            self.theme
+                                .keyframes
+                                .extend(utility.keyframes.iter().cloned());
+                            body_to_set = Some((res, utility.group.clone(), utility.layer.clone()));
+                            body_config = Some(utility.config);
                         }
                     }
 
                     for utility in self.utilities.iter() {
                         if utility.name.as_str() == pre_str.as_str()
                             && utility.has_value
+                            && self.options.utility_enabled(utility)
+                            && body_config.is_none_or(|config| utility.config >= config)
                             && let Ok(res) = utility.instantiate(
                                 &self.theme,
                                 Some(last_str.as_str()),
                                 special_param.as_ref(),
                                 false,
+                                negative,
                             )
                         {
                             self.custom_properties
                                 .extend_from_slice(&utility.properties);
-                            body_to_set = Some(res);
+                            self.theme
+                                .keyframes
+                                .extend(utility.keyframes.iter().cloned());
+                            body_to_set = Some((res, utility.group.clone(), utility.layer.clone()));
+                            body_config = Some(utility.config);
                         }
                     }
 
                     if let Some(SpecialParam::LineHeight(after)) = special_param.as_ref()
-                        && let Some(res) = body_to_set.as_mut()
+                        && let Some((res, _, _)) = body_to_set.as_mut()
                     {
                         res.push_str(&format!("\nline-height: {after};"));
                     }
                 }
                 ParsedUnit::Raw(raw_value) => {
-                    for utility in self.utilities.iter() {
-                        if utility.name.as_str() == pre_str.as_str()
-                            && utility.has_value
-                            && let Ok(res) = utility.instantiate(
-                                &self.theme,
-                                Some(raw_value.as_str()),
-                                None,
-                                true,
-                            )
-                        {
-                            self.custom_properties
-                                .extend_from_slice(&utility.properties);
-                            body_to_set = Some(res);
+                    // resolved first, so the type of the value is known when matching
+                    let raw_value = self.theme.resolve_theme_functions(&raw_value);
+                    // `color:var(--brand)` goes to the utilities that take a color, the ones
+                    // that take anything only get it if there are none
+                    let mut attempts = vec![(raw_value.clone(), None)];
+                    if let Some((hint, value)) = raw_value.split_once(':')
+                        && let Some(hint) = parse_css_data_type().parse(hint).into_output()
+                    {
+                        attempts = vec![
+                            (value.to_string(), Some(SpecialParam::DataType(hint))),
+                            (value.to_string(), None),
+                        ];
+                    }
+                    // only a color takes an alpha modifier, `var(--brand)/50` is mixed with
+                    // transparent. `bg-[1px]/50` isn't a class
+                    if let Some(alpha) = &alpha {
+                        let (value, data_type) = attempts.swap_remove(0);
+                        let color = match data_type {
+                            Some(data_type) => {
+                                matches!(data_type, SpecialParam::DataType(ValueType::Color))
+                            }
+                            None => is_color(&value),
+                        };
+                        if !color {
+                            return None;
+                        }
+                        let with_alpha = insert_alpha(&value, alpha).unwrap_or_else(|| {
+                            format!("color-mix(in oklab, {value} {alpha}, transparent)")
+                        });
+                        attempts =
+                            vec![(with_alpha, Some(SpecialParam::DataType(ValueType::Color)))];
+                    }
+                    for (value, data_type) in attempts {
+                        let value = value.as_str();
+                        for utility in self.utilities.iter() {
+                            if utility.name.as_str() == pre_str.as_str()
+                                && utility.has_value
+                                && self.options.utility_enabled(utility)
+                                && body_config.is_none_or(|config| utility.config >= config)
+                                && let Ok(res) = utility.instantiate(
+                                    &self.theme,
+                                    Some(value),
+                                    data_type.as_ref(),
+                                    true,
+                                    negative,
+                                )
+                            {
+                                self.custom_properties
+                                    .extend_from_slice(&utility.properties);
+                                self.theme
+                                    .keyframes
+                                    .extend(utility.keyframes.iter().cloned());
+                                body_to_set =
+                                    Some((res, utility.group.clone(), utility.layer.clone()));
+                                body_config = Some(utility.config);
+                            }
+                        }
+                        if body_to_set.is_some() {
+                            break;
                         }
                     }
                 }
             }
         }
 
-        css_def.body = body_to_set?;
+        body_to_set
+            .map(|(body, group, layer)| (self.theme.resolve_theme_functions(&body), group, layer))
+    }
+
+    /// the body of `css_def` in the negation of the variant `v`: `not-md:` ->
+    /// `@media not all and (width >= 48rem)`, `not-focus:` -> `&:not(:focus)`. a variant that nests
+    /// two blocks like `hover` is true if both are, so its negation is a rule for each of them
+    fn negated_variant(
+        &self,
+        css_def: &CssDef,
+        v: &[Spanned<ParsedUnit>],
+        where_selectors: bool,
+    ) -> Option<String> {
+        const SLOT: &str = "--dw-not-slot: 0";
+        let mut probe = css_def.clone();
+        probe.body = SLOT.to_string();
+        self.apply_variants(&mut probe, &[v.to_vec()], false)?;
+        if probe.pseudo_elements != css_def.pseudo_elements {
+            return None;
+        }
+
+        let mut negated = Vec::new();
+        let mut items = parse_items(&probe.body);
+        loop {
+            match items.as_slice() {
+                [Item::Declaration(SLOT)] if !negated.is_empty() => break,
+                [Item::Block { prelude, .. }] => negated.push(negate_prelude(prelude)?),
+                _ => return None,
+            }
+            let Some(Item::Block { items: inner, .. }) = items.pop() else {
+                unreachable!("matched a block");
+            };
+            items = inner;
+        }
+        Some(
+            negated
+                .into_iter()
+                .map(|prelude| {
+                    let prelude = if where_selectors && !prelude.starts_with('@') {
+                        where_variant_selector(&prelude)
+                    } else {
+                        prelude
+                    };
+                    format!("{prelude} {{\n{}\n}}", css_def.body)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        )
+    }
 
-        for v in parsed.0.variants.iter()
+    /// wraps the body of a rule in its variants, pseudo-element variants are added to the
+    /// selector instead. with `where_selectors` the selectors the variants add are in `:where()`
+    /// so `hover:` and `group-*` don't outweigh the utilities after them, see
+    /// `EmitOptions::no_where_variants`. selectors of the utility itself are left alone
+    fn apply_variants(
+        &self,
+        css_def: &mut CssDef,
+        variants: &[Vec<Spanned<ParsedUnit>>],
+        where_selectors: bool,
+    ) -> Option<()> {
+        for v in variants.iter()
         // .rev()
         {
             match &v[0].0 {
                 ParsedUnit::String(v_str) => {
-                    if v_str == "before" {
-                        css_def.pseudo_elements.push("before".to_string());
-                    } else if v_str == "after" {
-                        css_def.pseudo_elements.push("after".to_string());
-                    } else if v_str == "placeholder" {
-                        css_def.pseudo_elements.push("placeholder".to_string());
-                    } else if v_str == "file" {
-                        css_def.pseudo_elements.push("file".to_string());
-                    } else if v_str == "selection" {
-                        css_def.pseudo_elements.push("selection".to_string());
-                    } else if v_str == "first-letter" {
-                        css_def.pseudo_elements.push("first-letter".to_string());
-                    } else if v_str == "first-line" {
-                        css_def.pseudo_elements.push("first-line".to_string());
-                    } else if v_str == "backdrop" {
-                        css_def.pseudo_elements.push("backdrop".to_string());
+                    if let Some(pseudo_element) =
+                        self.pseudo_element_variants.get(&params_joined(v))
+                    {
+                        css_def.pseudo_elements.push(pseudo_element.clone());
                     } else {
                         match v_str.as_str() {
                             "*" => css_def.body = format!("& > * {{\n{}\n}}", css_def.body),
                             "**" => css_def.body = format!("& * {{\n{}\n}}", css_def.body),
                             "min" => {
-                                if let ParsedUnit::Raw(r) = &v[1].0 {
-                                    css_def.body =
-                                        format!("@media (width >= {r}) {{\n{}\n}}", css_def.body);
+                                if let Some((ParsedUnit::Raw(r), _)) = v.get(1) {
+                                    css_def.breakpoints.push(Breakpoint::Min(width_in_px(r)));
+                                    css_def.body = self.width_media_block(
+                                        &format!("(width >= {r})"),
+                                        &css_def.body,
+                                    );
                                 }
                             }
                             "max" => {
-                                if let ParsedUnit::Raw(r) = &v[1].0 {
-                                    css_def.body =
-                                        format!("@media (width < {r}) {{\n{}\n}}", css_def.body);
+                                if let Some((ParsedUnit::Raw(r), _)) = v.get(1) {
+                                    css_def.breakpoints.push(Breakpoint::Max(width_in_px(r)));
+                                    css_def.body = self.width_media_block(
+                                        &format!("(width < {r})"),
+                                        &css_def.body,
+                                    );
+                                } else {
+                                    let breakpoint =
+                                        self.get_breakpoint_var(&params_joined(&v[1..]))?;
+                                    css_def
+                                        .breakpoints
+                                        .push(Breakpoint::Max(width_in_px(&breakpoint)));
+                                    css_def.body = self.width_media_block(
+                                        &format!("(width < {breakpoint})"),
+                                        &css_def.body,
+                                    );
                                 }
                             }
                             "@min" => {
-                                if let ParsedUnit::Raw(r) = &v[1].0 {
+                                if let Some((ParsedUnit::Raw(r), _)) = v.get(1) {
+                                    css_def.breakpoints.push(Breakpoint::Min(width_in_px(r)));
                                     css_def.body = format!(
-                                        "@container (width >= {r}) {{\n{}\n}}",
+                                        "{} {{\n{}\n}}",
+                                        container_query(
+                                            container_name(v),
+                                            &format!("(width >= {r})")
+                                        ),
                                         css_def.body
                                     );
                                 }
                             }
                             "@max" => {
-                                if let ParsedUnit::Raw(r) = &v[1].0 {
+                                if let Some((ParsedUnit::Raw(r), _)) = v.get(1) {
+                                    css_def.breakpoints.push(Breakpoint::Max(width_in_px(r)));
                                     css_def.body = format!(
-                                        "@container (width < {r}) {{\n{}\n}}",
+                                        "{} {{\n{}\n}}",
+                                        container_query(
+                                            container_name(v),
+                                            &format!("(width < {r})")
+                                        ),
+                                        css_def.body
+                                    );
+                                } else {
+                                    let size = params_joined(&v[1..]);
+                                    let (size, name) = size
+                                        .split_once('/')
+                                        .map_or((size.as_str(), None), |(size, name)| {
+                                            (size, Some(name))
+                                        });
+                                    let breakpoint = self.get_container_breakpoint_var(size)?;
+                                    css_def
+                                        .breakpoints
+                                        .push(Breakpoint::Max(width_in_px(&breakpoint)));
+                                    css_def.body = format!(
+                                        "{} {{\n{}\n}}",
+                                        container_query(name, &format!("(width < {breakpoint})")),
                                         css_def.body
                                     );
                                 }
                             }
+                            "@style" => {
+                                let (ParsedUnit::Raw(r), _) = v.get(1)? else {
+                                    return None;
+                                };
+                                css_def.body = format!(
+                                    "{} {{\n{}\n}}",
+                                    container_query(
+                                        container_name(v),
+                                        &format!("style({})", style_query(r))
+                                    ),
+                                    css_def.body
+                                );
+                            }
                             "supports" => {
-                                if let ParsedUnit::Raw(r) = &v[1].0 {
-                                    css_def.body =
-                                        format!("@supports ({r}) {{\n{}\n}}", css_def.body);
+                                if let Some((ParsedUnit::Raw(r), _)) = v.get(1) {
+                                    css_def.body = format!(
+                                        "@supports {} {{\n{}\n}}",
+                                        supports_query(r),
+                                        css_def.body
+                                    );
                                 } else {
                                     let joined = v[1..]
                                         .iter()
@@ -727,12 +3241,19 @@ impl EmitEnv {
                                         format!("@supports ({joined}) {{\n{}\n}}", css_def.body);
                                 }
                             }
-                            "not" if v[1].0 == ParsedUnit::String("supports".to_string()) => {
-                                if let ParsedUnit::Raw(r) = &v[1].0 {
-                                    css_def.body =
-                                        format!("@supports (not {r}) {{\n{}\n}}", css_def.body);
+                            "not"
+                                if v.get(1).is_some_and(|x| {
+                                    x.0 == ParsedUnit::String("supports".to_string())
+                                }) =>
+                            {
+                                if let Some((ParsedUnit::Raw(r), _)) = v.get(2) {
+                                    css_def.body = format!(
+                                        "@supports not {} {{\n{}\n}}",
+                                        supports_query(r),
+                                        css_def.body
+                                    );
                                 } else {
-                                    let joined = v[1..]
+                                    let joined = v[2..]
                                         .iter()
                                         .map(|x| {
                                             if let ParsedUnit::String(s) = &x.0 {
@@ -744,7 +3265,7 @@ impl EmitEnv {
                                         .collect::<Vec<_>>()
                                         .join("-");
                                     css_def.body = format!(
-                                        "@supports (not {joined}) {{\n{}\n}}",
+                                        "@supports not ({joined}) {{\n{}\n}}",
                                         css_def.body
                                     );
                                 }
@@ -763,45 +3284,1619 @@ impl EmitEnv {
                                     .join("-");
 
                                 if let Some(breakpoint) = self.get_breakpoint_var(joined.as_str()) {
-                                    css_def.body = format!(
-                                        "@media (width >= {breakpoint}) {{\n{}\n}}",
-                                        css_def.body
+                                    css_def
+                                        .breakpoints
+                                        .push(Breakpoint::Min(width_in_px(&breakpoint)));
+                                    css_def.body = self.width_media_block(
+                                        &format!("(width >= {breakpoint})"),
+                                        &css_def.body,
                                     );
-                                } else if joined.starts_with("@")
-                                    && joined.len() > 1
+                                } else if let Some(size) = joined.strip_prefix('@')
+                                    && !size.is_empty()
+                                    && let (size, name) = size
+                                        .split_once('/')
+                                        .map_or((size, None), |(size, name)| (size, Some(name)))
                                     && let Some(container_breakpoint) =
-                                        self.get_container_breakpoint_var(&joined[1..])
+                                        self.get_container_breakpoint_var(size)
                                 {
+                                    css_def
+                                        .breakpoints
+                                        .push(Breakpoint::Min(width_in_px(&container_breakpoint)));
                                     css_def.body = format!(
-                                        "@container (width >= {container_breakpoint}) {{\n{}\n}}",
+                                        "{} {{\n{}\n}}",
+                                        container_query(
+                                            name,
+                                            &format!("(width >= {container_breakpoint})")
+                                        ),
                                         css_def.body
                                     );
-                                } else if let Some(variant) = self
-                                    .variants
-                                    .iter()
-                                    .find(|x| x.name.as_str() == joined.as_str())
+                                } else if let Some(body) = self.instantiate_variant(
+                                    &joined,
+                                    &css_def.body,
+                                    where_selectors,
+                                ) {
+                                    css_def.body = body;
+                                } else if let Some(body) =
+                                    self.resolve_internal_variant(css_def.body.as_str(), v)
                                 {
-                                    css_def.body = variant.instantiate(&css_def.body);
-                                } else {
+                                    css_def.body = match body.split_once(" {\n") {
+                                        Some((selector, rest)) if where_selectors => format!(
+                                            "{} {{\n{rest}",
+                                            where_variant_selector(selector)
+                                        ),
+                                        _ => body,
+                                    };
+                                } else if v_str == "not" && v.len() > 1 {
                                     css_def.body =
-                                        self.resolve_internal_variant(css_def.body.as_str(), v)?;
+                                        self.negated_variant(css_def, &v[1..], where_selectors)?;
+                                } else {
+                                    return None;
                                 }
                             }
                         }
                     }
                 }
                 ParsedUnit::Raw(raw_str) => {
-                    if raw_str.starts_with("::") {
-                        css_def.pseudo_elements.push(raw_str[2..].to_string());
+                    if let Some(pseudo_element) = raw_str.strip_prefix("::") {
+                        css_def.pseudo_elements.push(pseudo_element.to_string());
+                    } else if raw_str.starts_with('@') {
+                        let prelude = arbitrary_at_rule(raw_str);
+                        if (prelude.starts_with("@media ") || prelude.starts_with("@container "))
+                            && let Some(width) = min_width_of_query(&prelude)
+                        {
+                            css_def
+                                .breakpoints
+                                .push(Breakpoint::Min(width_in_px(width)));
+                        }
+                        css_def.body = format!("{prelude} {{\n{}\n}}", css_def.body);
+                    } else if where_selectors {
+                        css_def.body = format!(
+                            "{} {{\n{}\n}}",
+                            where_variant_selector(raw_str),
+                            css_def.body
+                        );
                     } else {
                         css_def.body = format!("{raw_str} {{\n{}\n}}", css_def.body);
                     }
                 }
             }
         }
+        Some(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeSet;
+
+    use crate::{
+        Breakpoint, CompileOptions, CssDef, DarkMode, Diagnostic, EmitEnv, Location, RuleOrder,
+        UsageReport, Variant, attribute_selector, mark_important, minify_css,
+        where_variant_selector, wrap_nested_selectors,
+    };
+
+    #[test]
+    fn test_named_themes() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config(
+            "@theme { --color-surface: #fff; }
+            @theme name(dark) { --color-surface: #111; }",
+        );
+        env.parse_full_string(
+            &CompileOptions::default(),
+            "bg-surface theme-dark:p-4 theme-unknown:p-2",
+        );
+
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("[data-theme=\"dark\"] {\n--color-surface: #111;\n}"));
+        assert!(css.contains("background-color: var(--color-surface);"));
+        assert!(css.contains("&:where([data-theme=\"dark\"], [data-theme=\"dark\"] *) {"));
+        assert!(!css.contains("theme-unknown"));
+    }
+
+    #[test]
+    fn test_single_word_color_transparency() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config("@theme { --color-surface: #ffffff; }");
+        env.parse_full_string(&CompileOptions::default(), "bg-surface/50 bg-red-500/50");
+
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("background-color: #ffffff80;"), "{css}");
+        assert!(css.contains("background-color: oklch(63.7% 0.237 25.331 / 50%);"));
+    }
+
+    #[test]
+    fn test_variant_themes() {
+        let mut env = EmitEnv::new();
+        env.load_config(
+            "@custom-variant dark (&:where(.dark, .dark *));
+            @utility bg-* { background-color: --value(--color-*); }
+            @theme { --color-surface: #fff; }
+            @theme dark { --color-surface: #111; }
+            @theme print { --color-surface: white; }",
+        );
+        env.parse_full_string(&CompileOptions::default(), "bg-surface");
+
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(":root {\n--color-surface: #fff;\n}"));
+        assert!(css.contains(":root {\n&:where(.dark, .dark *) {\n--color-surface: #111;\n"));
+        assert!(css.contains("background-color: var(--color-surface);"));
+
+        // flattened the whole stylesheet is, the theme too. the targets flatten it as well
+        for flatten_nesting in [true, false] {
+            env.options.flatten_nesting = flatten_nesting;
+            env.options.targets = (!flatten_nesting).then(|| "safari 15".parse().unwrap());
+            let css = env.to_css_stylesheet(false);
+            assert!(css.contains(":root:where(.dark, .dark *) {\n--color-surface: #111;\n"));
+            assert!(!css.contains('&'), "{css}");
+        }
+        assert_eq!(
+            env.diagnostics,
+            vec![Diagnostic::UnknownThemeVariant("print".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_presets() {
+        let mut env = EmitEnv::new_with_default_config();
+        assert!(env.load_config("@preset unknown;"));
+        assert_eq!(
+            env.diagnostics,
+            vec![Diagnostic::UnknownPreset("unknown".to_string())]
+        );
+
+        #[cfg(feature = "typography")]
+        {
+            assert!(env.load_preset("typography"));
+            env.parse_full_string(&CompileOptions::default(), "prose lg:prose-lg");
+            let css = env.to_css_stylesheet(false);
+            assert!(css.contains("& :where(h1):not(:where(.not-prose, .not-prose *)) {"));
+            assert!(css.contains("--prose-body:"));
+            assert!(css.contains(".lg\\:prose-lg{"));
+        }
+        #[cfg(feature = "forms")]
+        {
+            assert!(env.load_preset("forms"));
+            env.parse_full_string(&CompileOptions::default(), "form-checkbox");
+            assert!(env.to_css_stylesheet(false).contains("&:checked {"));
+        }
+        #[cfg(not(feature = "typography"))]
+        assert!(!env.load_preset("typography"));
+        assert_eq!(env.diagnostics.len(), 1);
+    }
+
+    #[test]
+    fn test_missing_plugin() {
+        let mut env = EmitEnv::new();
+        assert!(env.load_config("@plugin \"./does-not-exist.so\";"));
+        assert!(matches!(
+            env.diagnostics.as_slice(),
+            [Diagnostic::PluginLoadFailed(path, _)] if path == "./does-not-exist.so"
+        ));
+    }
+
+    #[test]
+    fn test_multiple_prefixes() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.parse_full_string(&CompileOptions::with_prefix("a-"), "a-p-4");
+        env.parse_full_string(&CompileOptions::with_prefix("b-"), "b-p-4");
+        env.parse_full_string(&CompileOptions::with_prefix("a-"), "a-p-4");
+
+        let class_names = env
+            .defs
+            .iter()
+            .map(|def| def.class_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(class_names, vec!["a-p-4", "b-p-4"]);
+
+        let options = CompileOptions::with_prefix("tw:");
+        env.parse_full_string(&options, "<div class=\"tw:hover:flex flex tw:p-4\">");
+        env.parse_full_string(&options, "tw:p-4");
+        let class_names = env.defs[2..]
+            .iter()
+            .map(|def| def.class_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(class_names, ["tw\\:hover\\:flex", "tw\\:p-4"]);
+        assert!(env.to_css_stylesheet(false).contains("\n.tw\\:p-4{"));
+    }
+
+    #[test]
+    fn test_compile_options() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions {
+            important: true,
+            scope_selector: Some("#app".to_string()),
+            strict: true,
+            ..Default::default()
+        };
+        let generated = env.compile_classes(&options, ["flex", "hover:p-4", "not-a-class", "flex"]);
+        assert_eq!(generated.len(), 2);
+        assert_eq!(
+            env.diagnostics,
+            vec![Diagnostic::UnknownClass("not-a-class".to_string())]
+        );
+
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("#app .flex{"));
+        assert!(css.contains("display: flex !important;"));
+
+        // the same class without a scope is a different rule
+        assert_eq!(
+            env.compile_classes(&CompileOptions::default(), ["flex"])
+                .len(),
+            1
+        );
+
+        // scoping the whole stylesheet keeps the rules' own scope
+        env.compile_classes(&CompileOptions::default(), ["p-4"]);
+        env.options.scope = Some("#root".to_string());
+        let css = env.to_css_stylesheet(true);
+        assert!(css.contains("#app .flex{"));
+        assert!(css.contains("#root .p-4 {"));
+        assert!(css.contains("#root {\n--"));
+        assert!(css.contains("#root *,\n#root ::after"));
+        assert!(!css.contains(":root") && !css.contains("\nhtml"));
+    }
+
+    #[test]
+    fn test_compile_options_generated_once_per_rule() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        let plain = CompileOptions::default();
+        let important = CompileOptions {
+            important: true,
+            ..Default::default()
+        };
+        let low_specificity = CompileOptions {
+            low_specificity: true,
+            ..Default::default()
+        };
+        assert_eq!(env.compile_classes(&plain, ["p-4"]).len(), 1);
+        assert_eq!(
+            env.parse_source(&important, "a.html", "p-4")
+                .added_rules
+                .len(),
+            1
+        );
+        assert_eq!(env.compile_classes(&low_specificity, ["p-4"]).len(), 1);
+        assert_eq!(env.compile_classes(&important, ["p-4"]).len(), 0);
+
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("!important;"), "{css}");
+        assert!(css.contains(":where(.p-4)"), "{css}");
+
+        // retracting one keeps the others
+        env.retract_source("a.html");
+        assert_eq!(env.defs.len(), 2);
+        assert!(!env.to_css_stylesheet(false).contains("!important"));
+    }
+
+    #[test]
+    fn test_scope_at_rule_option() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.scope = Some("#widget-root".to_string());
+        env.options.scope_at_rule = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["p-4", "hover:flex", "animate-spin"],
+        );
+        let css = env.to_css_stylesheet(true);
+        assert!(css.contains("@scope (#widget-root) {\n"), "{css}");
+        assert!(css.contains(":scope {\n--"));
+        assert!(css.contains("\n.p-4 {"));
+        assert!(!css.contains("#widget-root .p-4"));
+        // keyframes can't be nested in @scope
+        assert!(css.contains("}\n@keyframes spin"));
+        assert_eq!(css.matches("@keyframes").count(), 1);
+    }
+
+    #[test]
+    fn test_important_option() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.important = true;
+        env.options.no_theme_vars = true;
+        env.compile_classes(&CompileOptions::default(), ["hover:flex", "!p-4"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("display: flex !important;"));
+        assert!(css.contains(".\\!p-4{\n    padding: calc(var(--spacing) * 4) !important;"));
+        assert!(!css.contains("!important !important"));
+        // the rules themselves aren't changed
+        assert!(!env.defs[0].body.contains("!important"));
+    }
+
+    #[test]
+    fn test_important_modifier() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.parse_full_string(
+            &CompileOptions::default(),
+            "<div class=\"bg-red-500! hover:!flex md:(p-4!,m-2) p-4\">",
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".bg-red-500\\!{"));
+        assert!(css.contains("background-color: oklch(63.7% 0.237 25.331) !important;"));
+        assert!(css.contains(".hover\\:\\!flex{"));
+        assert!(css.contains("display: flex !important;"));
+        // only the member with the `!`
+        assert!(css.contains("padding: calc(var(--spacing) * 4) !important;"));
+        assert!(!css.contains("margin: calc(var(--spacing) * 2) !important;"));
+        // the class without `!` is a rule of its own
+        assert!(css.contains(".p-4{\n    padding: calc(var(--spacing) * 4);\n"));
+    }
+
+    #[test]
+    fn test_mark_important() {
+        assert_eq!(
+            mark_important("color: red;\n&:hover {\nbackground: url(\"a;b\")\n}"),
+            "color: red !important;\n&:hover {\nbackground: url(\"a;b\") !important\n}"
+        );
+        assert_eq!(
+            mark_important("color: red !important;"),
+            "color: red !important;"
+        );
+    }
+
+    #[test]
+    fn test_low_specificity() {
+        assert_eq!(
+            wrap_nested_selectors("@media (hover: hover) {\n&:hover, &::after {\ncolor: red\n}\n}"),
+            "@media (hover: hover) {\n:where(&:hover), :where(&)::after {\ncolor: red\n}\n}"
+        );
+
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions {
+            low_specificity: true,
+            ..Default::default()
+        };
+        env.compile_classes(&options, ["hover:bg-red-500", "before:content-['a']"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(":where(.hover\\:bg-red-500){"));
+        assert!(css.contains(":where(&:where(:hover)) {"));
+        assert!(css.contains(":where(.before\\:content-\\[\\'a\\'\\])::before{"));
+    }
+
+    #[test]
+    fn test_where_variants() {
+        assert_eq!(where_variant_selector("&:hover"), "&:where(:hover)");
+        assert_eq!(where_variant_selector(".dark &"), ":where(.dark) &");
+        assert_eq!(where_variant_selector("& > *"), "& > *");
+        assert_eq!(where_variant_selector("&::backdrop"), "&::backdrop");
+        assert_eq!(
+            where_variant_selector("&:where(.dark, .dark *)"),
+            "&:where(.dark, .dark *)"
+        );
+        assert_eq!(
+            where_variant_selector("&[open]::details-content, &:focus"),
+            "&:where([open])::details-content, &:where(:focus)"
+        );
+
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["hover:bg-red-500", "group-hover:flex", "space-x-2"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("&:where(:hover) {"), "{css}");
+        assert!(css.contains("&:where(:is(:where(.group):is(:hover) *)) {"));
+        // the selectors of the utility itself aren't the variant's
+        assert!(css.contains("& > :not(:last-child) {"));
+
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_where_variants = true;
+        env.compile_classes(&CompileOptions::default(), ["hover:bg-red-500"]);
+        assert!(env.to_css_stylesheet(false).contains("&:hover {"));
+    }
+
+    #[test]
+    fn test_utility_groups() {
+        let mut env = EmitEnv::new_with_default_config();
+        assert!(env.utility_groups().contains("spacing"));
+        env.try_load_config("@utility card {\ndisplay: block;\n}")
+            .unwrap();
+        env.options.utility_groups = Some(BTreeSet::from([
+            "spacing".to_string(),
+            "flexbox-grid".to_string(),
+        ]));
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            ["p-4", "hover:gap-2", "bg-red-500", "rounded", "card"],
+        );
+        let classes = generated
+            .iter()
+            .map(|css_def| css_def.class_name.as_str())
+            .collect::<Vec<_>>();
+        // utilities without a group, like the ones of user configs, stay enabled
+        assert_eq!(classes, ["p-4", "hover\\:gap-2", "card"]);
+    }
+
+    #[test]
+    fn test_variant_groups() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "md:(px-4,hover:bg-red-500)",
+                "hover:(p-4,before:p-2)",
+                "hover:(p-4,)",
+            ],
+        );
+        // a rule for every member, with the variants of the group in front
+        let class_names = generated
+            .iter()
+            .map(|def| (def.class_name.as_str(), def.variants.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            class_names,
+            [
+                (
+                    "md\\:\\(px-4\\,hover\\:bg-red-500\\)",
+                    vec!["md".to_string()]
+                ),
+                (
+                    "md\\:\\(px-4\\,hover\\:bg-red-500\\)",
+                    vec!["md".to_string(), "hover".to_string()]
+                ),
+                (
+                    "hover\\:\\(p-4\\,before\\:p-2\\)",
+                    vec!["hover".to_string()]
+                ),
+                (
+                    "hover\\:\\(p-4\\,before\\:p-2\\)",
+                    vec!["hover".to_string(), "before".to_string()]
+                ),
+            ]
+        );
+        assert_eq!(generated[0].properties, ["padding-inline"]);
+        assert_eq!(generated[1].properties, ["background-color"]);
+
+        // members can add pseudo-elements, and are ordered like the members on their own, so
+        // `md:px-4` overrides `hover:p-4`
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".hover\\:\\(p-4\\,before\\:p-2\\)::before{"));
+        assert!(
+            css.find(".hover\\:\\(p-4\\,before\\:p-2\\){").unwrap()
+                < css.find(".md\\:\\(px-4\\,hover\\:bg-red-500\\){").unwrap()
+        );
+
+        // every member knows where the group was found
+        let mut report = UsageReport::default();
+        env.collect_usage(
+            &CompileOptions::default(),
+            "a.html",
+            "<i class=\"focus:(p-1,flex)\">",
+            &mut report,
+        );
+        let members = env
+            .defs
+            .iter()
+            .filter(|def| def.class_name.starts_with("focus"))
+            .collect::<Vec<_>>();
+        assert_eq!(members.len(), 2);
+        assert!(
+            members
+                .iter()
+                .all(|def| def.origin.as_ref().is_some_and(|o| o.column == 11))
+        );
+    }
+
+    #[test]
+    fn test_negative_cache() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions::default();
+        env.parse_full_string(&options, "the frobnicate the frobnicate p-4");
+        assert_eq!(env.defs.len(), 1);
+
+        // loading a config invalidates earlier misses
+        env.load_config("@utility frobnicate {\n    color: red;\n}");
+        env.parse_full_string(&options, "the frobnicate");
+        assert!(env.defs.iter().any(|def| def.class_name == "frobnicate"));
+    }
+
+    #[test]
+    fn test_negative_cache_groups() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions::default();
+        env.parse_full_string(&options, "hover:(bg-red-500,text-white)");
+        env.parse_full_string(&options, "hover:(bg-red-500,nope-x)");
+        env.parse_full_string(&options, "hover:(bg-red-500,flex)");
+        let members = env
+            .defs
+            .iter()
+            .filter(|def| def.class_name.ends_with("flex\\)"))
+            .count();
+        assert_eq!(members, 2);
+    }
+
+    #[test]
+    fn test_media_feature_variants() {
+        let mut env = EmitEnv::new_with_default_config();
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "reduced-data:bg-none",
+                "scripting:flex",
+                "scripting-none:hidden",
+            ],
+        );
+        assert_eq!(generated.len(), 3);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("@media (prefers-reduced-data: reduce) {"));
+        assert!(css.contains("@media (scripting: enabled) {"));
+        assert!(css.contains("@media (scripting: none) {"));
+    }
+
+    #[test]
+    fn test_static_utilities() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config("@utility flex {\n    display: inline-flex;\n}");
+        let generated = env.compile_classes(&CompileOptions::default(), ["flex", "hidden"]);
+        assert!(generated[0].body.contains("display: inline-flex;"));
+        assert!(generated[1].body.contains("display: none;"));
+
+        // a theme loaded later is used by the static utilities of earlier configs
+        env.load_config("@utility ring-brand {\n    color: theme(--color-brand);\n}");
+        env.load_config("@theme {\n    --color-brand: #336699;\n}");
+        let generated = env.compile_classes(&CompileOptions::default(), ["ring-brand"]);
+        assert_eq!(generated.len(), 1);
+        assert!(generated[0].body.contains("color: #336699;"));
+
+        // a utility missing from the table still resolves
+        env.static_utilities.clear();
+        let generated = env.compile_classes(&CompileOptions::default(), ["block"]);
+        assert!(generated[0].body.contains("display: block;"));
+    }
+
+    #[test]
+    fn test_spacing_scale() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config("@theme {\n    --spacing-4: 1.125rem;\n    --spacing-gutter: 2rem;\n}");
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            ["p-4", "-m-4", "p-3", "gap-gutter", "m-[3px]"],
+        );
+        assert!(generated[0].body.contains("padding: 1.125rem;"));
+        assert!(generated[1].body.contains("margin: calc(1.125rem * -1);"));
+        assert!(
+            generated[2]
+                .body
+                .contains("padding: calc(var(--spacing) * 3);")
+        );
+        assert!(generated[3].body.contains("gap: 2rem;"));
+        assert!(generated[4].body.contains("margin: 3px;"));
+    }
+
+    #[test]
+    fn test_theme_function_alpha() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config(
+            "@utility ring-soft {\n    box-shadow: 0 0 0 2px theme(--color-black / 10%);\n}",
+        );
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            ["bg-[theme(--color-red-500/50%)]", "ring-soft"],
+        );
+        assert!(
+            generated[0]
+                .body
+                .contains("background-color: oklch(63.7% 0.237 25.331 / 50%);")
+        );
+        assert!(
+            generated[1]
+                .body
+                .contains("box-shadow: 0 0 0 2px #0000001A;")
+        );
+    }
+
+    #[test]
+    fn test_canonical_rule_order() {
+        let stylesheet = |classes: &[&str]| {
+            let mut env = EmitEnv::new_with_default_config();
+            env.options.rule_order = RuleOrder::Canonical;
+            // leave out `:root`, only the rules are compared
+            env.options.no_theme_vars = true;
+            env.compile_classes(&CompileOptions::default(), classes.iter().copied());
+            env.to_css_stylesheet(false)
+        };
+        let css = stylesheet(&["hover:p-2", "bg-red-500", "px-4", "flex", "p-4"]);
+        assert_eq!(
+            css,
+            stylesheet(&["p-4", "flex", "px-4", "bg-red-500", "hover:p-2"])
+        );
+
+        let positions = [".flex{", ".p-4{", ".px-4{", ".hover\\:p-2{", ".bg-red-500{"]
+            .map(|selector| css.find(selector).expect("rule should be emitted"));
+        assert!(positions.is_sorted(), "{css}");
+
+        // variants in config order, breakpoints by width and not by name
+        let css = stylesheet(&["lg:p-2", "focus:p-2", "md:p-2", "hover:p-2", "sm:p-2"]);
+        let positions = ["hover", "focus", "sm", "md", "lg"]
+            .map(|variant| css.find(&format!(".{variant}\\:p-2{{")).unwrap());
+        assert!(positions.is_sorted(), "{css}");
+    }
+
+    #[test]
+    fn test_source_rule_order() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.rule_order = RuleOrder::Source;
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["md:p-2", "pt-4", "hover:flex", "p-4"],
+        );
+        let css = env.to_css_stylesheet(false);
+        let positions = [".md\\:p-2{", ".pt-4{", ".hover\\:flex{", ".p-4{"]
+            .map(|selector| css.find(selector).expect("rule should be emitted"));
+        assert!(positions.is_sorted(), "{css}");
+    }
+
+    #[test]
+    fn test_breakpoints_ascending() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config("@theme { --breakpoint-tablet: 600px; }");
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["md:p-4", "@sm:px-2", "tablet:px-2", "sm:p-2"],
+        );
+        assert_eq!(env.defs[1].breakpoints, [Breakpoint::Min(384)]);
+        let css = env.to_css_stylesheet(false);
+        let positions = [
+            ".\\@sm\\:px-2{",
+            ".tablet\\:px-2{",
+            ".sm\\:p-2{",
+            ".md\\:p-4{",
+        ]
+        .map(|selector| css.find(selector).unwrap());
+        assert!(positions.is_sorted(), "{css}");
+    }
+
+    #[test]
+    fn test_max_breakpoints_order() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "sm:max-lg:p-7",
+                "md:p-4",
+                "max-sm:p-1",
+                "min-[30rem]:p-5",
+                "max-lg:p-2",
+                "max-[50rem]:p-6",
+                "max-md:p-3",
+                "sm:p-8",
+            ],
+        );
+        let css = env.to_css_stylesheet(false);
+        let positions = [
+            ".max-lg\\:p-2{",
+            ".max-\\[50rem\\]\\:p-6{",
+            ".max-md\\:p-3{",
+            ".max-sm\\:p-1{",
+            ".min-\\[30rem\\]\\:p-5{",
+            ".sm\\:p-8{",
+            ".md\\:p-4{",
+            ".sm\\:max-lg\\:p-7{",
+        ]
+        .map(|selector| css.find(selector).unwrap());
+        assert!(positions.is_sorted(), "{css}");
+    }
+
+    #[test]
+    fn test_negative_values() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.load_config("@utility tab-* { tab-size: --value(integer, [*]); }");
+        let options = CompileOptions::default();
+        env.compile_classes(
+            &options,
+            ["-mt-4", "-mt-[5px]", "-top-(--offset)", "-tab-2", "-mx-px"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("margin-top: calc(var(--spacing) * -4);"));
+        assert!(css.contains("margin-top: calc(5px * -1);"));
+        assert!(css.contains("top: calc(var(--offset) * -1);"));
+        assert!(css.contains("tab-size: calc(2 * -1);"));
+        assert!(css.contains("margin-inline: -1px;"), "{css}");
+        // colors and keywords can't be negated
+        assert!(
+            env.compile_classes(&options, ["-bg-red-500", "-flex"])
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_dark_mode() {
+        let stylesheet = |dark_mode: &str, config: &str| {
+            let mut env = EmitEnv::new_with_default_config();
+            env.options.no_theme_vars = true;
+            env.options.dark_mode = dark_mode.parse().unwrap();
+            env.load_config(config);
+            env.compile_classes(&CompileOptions::default(), ["dark:flex", "not-dark:grid"]);
+            env.to_css_stylesheet(false)
+        };
+        let css = stylesheet("media", "");
+        assert!(css.contains("@media (prefers-color-scheme: dark) {"));
+        let css = stylesheet("class", "");
+        assert!(css.contains("&:where(.dark, .dark *) {"), "{css}");
+        assert!(css.contains("&:where(:not(:where(.dark, .dark *))) {"));
+        assert!(!css.contains("prefers-color-scheme"));
+        let css = stylesheet("data-attribute", "");
+        assert!(css.contains("&:where([data-theme=\"dark\"], [data-theme=\"dark\"] *) {"));
+        assert!(stylesheet(".night", "").contains("&:where(.night, .night *) {"));
+        // a dark variant of the user wins
+        let css = stylesheet("class", "@custom-variant dark (&:is(.dim *));");
+        assert!(css.contains("&:where(:is(.dim *))") && !css.contains("&:where(.dark, .dark *)"));
+        let css = stylesheet("media-and-class", "");
+        assert!(css.contains(
+            "@media (prefers-color-scheme: dark) {\n&:where(:not(:where(.light, .light *))) {"
+        ));
+        assert!(css.contains("}\n}\n&:where(.dark, .dark *) {"), "{css}");
+        assert!(css.contains(
+            "&:where(:not(:where(.dark, .dark *))) {\n@media not (prefers-color-scheme: dark) {"
+        ));
+        assert!(css.contains("}\n&:where(.light, .light *) {"));
+
+        // a variant that isn't from a loaded config counts as the user's
+        let mut env = EmitEnv::new();
+        env.options.dark_mode = DarkMode::Class;
+        env.load_config("@utility flex { display: flex; }");
+        env.variants.push(Variant {
+            name: "dark".to_string(),
+            body: "&:is(.dim *) {\n\n}".to_string(),
+            target: "&:is(.dim *) {\n".len(),
+            is_short: false,
+            doc: None,
+            config: 7,
+            span: 0..0,
+        });
+        env.compile_classes(&CompileOptions::default(), ["dark:flex"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("&:where(:is(.dim *))") && !css.contains(".dark, .dark *"));
+        assert_eq!(env.variant_origin("dark"), None);
+        assert_eq!(
+            "".parse::<DarkMode>(),
+            Err("the dark mode can't be empty".to_string())
+        );
+    }
+
+    #[test]
+    fn test_var_shorthand() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "bg-(--brand)",
+                "bg-(color:--brand)",
+                "bg-[color:var(--brand)]",
+                "p-(length:--gap)",
+            ],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".bg-\\(--brand\\){\n    background-image: var(--brand);"));
+        assert!(css.contains(".bg-\\(color\\:--brand\\){\n    background-color: var(--brand);"));
+        assert!(
+            css.contains(
+                ".bg-\\[color\\:var\\(--brand\\)\\]{\n    background-color: var(--brand);"
+            )
+        );
+        assert!(css.contains("padding: var(--gap);"), "{css}");
+
+        // only a value after the name of a utility
+        let options = CompileOptions::default();
+        assert!(
+            env.compile_classes(&options, ["(--x)", "hover:(--x)", "-(--fg)"])
+                .is_empty()
+        );
+        let defs = env.defs.len();
+        env.parse_full_string(&options, "<p class=\"p-(--fg)\">");
+        let class_names = env.defs[defs..]
+            .iter()
+            .map(|def| def.class_name.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(class_names, ["p-\\(--fg\\)"]);
+    }
+
+    #[test]
+    fn test_variants() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        let options = CompileOptions::default();
+        for (candidate, expected) in [
+            ("rtl:ml-4", "&:where([dir=\"rtl\"], [dir=\"rtl\"] *) {"),
+            (
+                "ltr:hover:flex",
+                "&:where(:hover) {\n            &:where([dir=\"ltr\"], [dir=\"ltr\"] *) {",
+            ),
+            ("print:hidden", "@media print {"),
+            ("screen:md:flex", "@media screen {"),
+            ("portrait:hidden", "@media (orientation: portrait) {"),
+            ("landscape:flex", "@media (orientation: landscape) {"),
+            ("pointer-coarse:p-4", "@media (pointer: coarse) {"),
+            ("pointer-none:hidden", "@media (pointer: none) {"),
+            ("any-pointer-fine:p-2", "@media (any-pointer: fine) {"),
+            ("noscript:hidden", "@media (scripting: none) {"),
+            (
+                "scripting-initial:flex",
+                "@media (scripting: initial-only) {",
+            ),
+            ("open:flex", "&:where(:is([open], :popover-open, :open)) {"),
+            ("inert:opacity-50", "&:where(:is([inert], [inert] *)) {"),
+            ("odd:bg-gray-50", "&:where(:nth-child(odd)) {"),
+            ("even:flex", "&:where(:nth-child(even)) {"),
+            ("nth-even:grid", "&:where(:nth-child(even)) {"),
+            ("nth-last-[2]:block", "&:where(:nth-last-child(2)) {"),
+            ("nth-of-type-odd:hidden", "&:where(:nth-of-type(odd)) {"),
+            (
+                "nth-last-of-type-[3n+1]:inline",
+                "&:where(:nth-last-of-type(3n+1)) {",
+            ),
+            ("data-[state=open]:flex", "&:where([data-state=\"open\"]) {"),
+            ("data-[size*=lg]:grid", "&:where([data-size*=\"lg\"]) {"),
+            ("data-loading:block", "&:where([data-loading]) {"),
+            ("aria-busy:flex", "&:where([aria-busy=\"true\"]) {"),
+            (
+                "aria-[sort=ascending]:grid",
+                "&:where([aria-sort=\"ascending\"]) {",
+            ),
+            (
+                "group-aria-expanded:block",
+                "&:where(:is(:where(.group)[aria-expanded=\"true\"] *)) {",
+            ),
+            (
+                "peer-aria-[sort=descending]:hidden",
+                "&:where(:is(:where(.peer)[aria-sort=\"descending\"] ~ *)) {",
+            ),
+            (
+                "group-[.is-active]:flex",
+                "&:where(:is(:where(.group):is(.is-active) *)) {",
+            ),
+            (
+                "group-[.is-active]/sidebar:grid",
+                "&:where(:is(:where(.group\\/sidebar):is(.is-active) *)) {",
+            ),
+            (
+                "peer-[:checked]/name:block",
+                "&:where(:is(:where(.peer\\/name):is(:checked) ~ *)) {",
+            ),
+            (
+                "group-has-peer-checked:flex",
+                "&:where(:is(:where(.group):has(:is(:where(.peer):is(:checked) ~ *)) *)) {",
+            ),
+            (
+                "peer-group-hover:grid",
+                "&:where(:is(:where(.peer):is(:where(.group):is(:hover) *) ~ *)) {",
+            ),
+            (
+                "group-data-[state=open]:block",
+                "&:where(:is(:where(.group)[data-state=\"open\"] *)) {",
+            ),
+            (
+                "group-hover/menu:hidden",
+                "&:where(:is(:where(.group\\/menu):is(:hover) *)) {",
+            ),
+            ("@sm/sidebar:flex", "@container sidebar (width >= 24rem) {"),
+            (
+                "@min-[600px]/main:grid",
+                "@container main (width >= 600px) {",
+            ),
+            ("@max-[30rem]:block", "@container (width < 30rem) {"),
+            ("@md:hidden", "@container (width >= 28rem) {"),
+            ("max-sm:flex", "@media (width < 40rem) {"),
+            ("@max-md:grid", "@container (width < 28rem) {"),
+            ("@max-lg/side:block", "@container side (width < 32rem) {"),
+            (
+                "sm:max-lg:hidden",
+                "@media (width >= 40rem) and (width < 64rem) {",
+            ),
+            (
+                "min-[30rem]:max-[50rem]:block",
+                "@media (width >= 30rem) and (width < 50rem) {",
+            ),
+            ("not-md:flex", "@media not all and (width >= 48rem) {"),
+            ("not-print:grid", "@media not print {"),
+            (
+                "not-rtl:block",
+                "&:where(:not(:where([dir=\"rtl\"], [dir=\"rtl\"] *))) {",
+            ),
+            ("not-hover:hidden", "@media not all and (hover: hover) {"),
+            ("not-focus:hidden", "&:where(:not(:focus)) {"),
+            (
+                "not-@sm/side:inline",
+                "@container side not (width >= 24rem) {",
+            ),
+            (
+                "not-has-checked:contents",
+                "&:where(:not(:has(:checked))) {",
+            ),
+            ("not-[.active]:table", "&:where(:not(.active)) {"),
+        ] {
+            let css = env
+                .compile_classes(&options, [candidate])
+                .iter()
+                .map(CssDef::to_css)
+                .collect::<String>();
+            assert!(css.contains(expected), "{candidate}: {css}");
+        }
+        for candidate in ["max-nope:flex", "not-*:flex"] {
+            assert!(env.compile_classes(&options, [candidate]).is_empty());
+        }
+    }
+
+    #[test]
+    fn test_stacked_breakpoint_ranges() {
+        // the range query of stacked breakpoints can still be lowered for older browsers
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.options.targets = Some("safari 15".parse().unwrap());
+        env.compile_classes(&CompileOptions::default(), ["sm:max-lg:hidden"]);
+        let css = env.to_css_stylesheet(false);
+        assert!(
+            css.contains("@media not all and (min-width: 64rem) {"),
+            "{css}"
+        );
+        assert!(css.contains("@media (min-width: 40rem) {"));
+    }
+
+    #[test]
+    fn test_arbitrary_at_rule_variants() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "[@media(min-width:900px)]:flex",
+                "md:flex",
+                "[@supports_(display:grid)]:grid",
+            ],
+        );
+        assert_eq!(env.defs[0].breakpoints, [Breakpoint::Min(900)]);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("@media (min-width:900px) {\n"), "{css}");
+        assert!(css.contains("@supports (display:grid) {\n"));
+        assert!(css.find(".md\\:flex{") < css.find(".\\[\\@media\\(min-width\\:900px\\)\\]"));
+    }
+
+    #[test]
+    fn test_debug_comments() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.debug_comments = true;
+        env.options.no_theme_vars = true;
+        let options = CompileOptions::default();
+        env.parse_source(
+            &options,
+            "src/index.html",
+            "<div>\n  <a class=\"p-4 hover:bg-red-500\">",
+        );
+        env.compile_classes(&options, ["flex"]);
+
+        let css = env.to_css_stylesheet(false);
+        assert!(
+            css.contains("/* from: src/index.html:2 — hover:bg-red-500 */\n.hover\\:bg-red-500")
+        );
+        assert!(css.contains("/* from: src/index.html:2 — p-4 */\n.p-4{"));
+        assert!(css.contains("/* flex */\n.flex{"));
+        assert!(!minify_css(&css).contains("from:"));
+    }
+
+    #[test]
+    fn test_minify_option() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.debug_comments = true;
+        env.compile_classes(&CompileOptions::default(), ["p-4", "hover:flex"]);
+        let pretty = env.to_css_stylesheet(false);
+
+        env.options.minify = true;
+        let css = env.to_css_stylesheet(false);
+        assert_eq!(css, minify_css(&pretty));
+        assert!(css.len() < pretty.len() && !css.contains("/*") && !css.contains('\n'));
+        assert!(css.ends_with(".p-4{padding:calc(var(--spacing) * 4)}.hover\\:flex{@media (hover:hover){&:where(:hover){display:flex}}}"));
+        assert!(!env.defs_to_css_since(0).contains('\n'));
+    }
+
+    #[test]
+    fn test_layers() {
+        let mut env = EmitEnv::new_with_default_config();
+        assert!(env.load_config(
+            "@layer components;
+            @utility btn {
+                padding: 1rem;
+            }"
+        ));
+        env.options.layers = true;
+        env.compile_classes(&CompileOptions::default(), ["p-4", "btn", "flex"]);
+        let css = env.to_css_stylesheet(true);
+
+        assert!(css.starts_with("@layer theme, base, components, utilities;\n@layer base {\n"));
+        assert!(css.contains("@layer theme {\n:root {\n"));
+        let components = css.find("@layer components {\n.btn{").unwrap();
+        let utilities = css.find("@layer utilities {\n").unwrap();
+        assert!(components < utilities);
+        assert!(css[utilities..].contains(".p-4{") && css[utilities..].contains(".flex{"));
+        // the rules sent for updates have to end up in the same layers
+        assert_eq!(env.defs_to_css_since(0).matches("@layer ").count(), 2);
+    }
+
+    #[test]
+    fn test_docs() {
+        let mut env = EmitEnv::new_with_default_config();
+        assert!(env.load_config(
+            "/**
+              * Stacks the children with a gap.
+              * @example stack-4
+              */
+            @utility stack-* {
+                gap: --value(spacing);
+            }
+            /** Only on devices with a fine pointer. */
+            @custom-variant pointer-fine (@media (pointer: fine));
+            @utility plain {
+                color: red;
+            }"
+        ));
+
+        let doc = env
+            .utility_doc("md:stack-4")
+            .expect("stack should be documented");
+        assert_eq!(doc.description, "Stacks the children with a gap.");
+        assert_eq!(doc.example.as_deref(), Some("stack-4"));
+        assert_eq!(
+            env.variant_doc("pointer-fine")
+                .map(|doc| doc.description.as_str()),
+            Some("Only on devices with a fine pointer.")
+        );
+        assert_eq!(env.utility_doc("plain"), None);
+        assert!(
+            env.compile_classes(&CompileOptions::default(), ["pointer-fine:stack-2"])[0]
+                .body
+                .contains("gap: calc(var(--spacing) * 2);")
+        );
+    }
+
+    #[test]
+    fn test_empty_value_namespaces() {
+        let mut env = EmitEnv::new_with_default_config();
+        let empty = |env: &EmitEnv| {
+            env.diagnostics
+                .iter()
+                .filter(|diagnostic| matches!(diagnostic, Diagnostic::EmptyValueNamespace(..)))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(empty(&env), []);
+
+        env.load_config("@utility icon-* {\n    mask-image: --value(--icon-*);\n}");
+        assert_eq!(
+            empty(&env),
+            [Diagnostic::EmptyValueNamespace(
+                "icon-*".to_string(),
+                "--icon-*".to_string()
+            )]
+        );
+        // the vars can come with a later config
+        env.load_config("@theme {\n    --icon-check: url(check.svg);\n}");
+        assert_eq!(empty(&env), []);
+    }
+
+    #[test]
+    fn test_config_precedence() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.try_load_named_config(
+            "base.css",
+            "@custom-variant pointer-fine (@media (pointer: fine));",
+        )
+        .expect("base should load");
+        env.try_load_named_config(
+            "brand.css",
+            "@utility p-4 {
+                padding: 3px;
+            }
+            @custom-variant pointer-fine (@media (any-pointer: fine));
+            @theme {
+                --color-red-500: #e11;
+            }",
+        )
+        .expect("brand should load");
+
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            ["p-4", "p-2", "pointer-fine:flex", "bg-red-500"],
+        );
+        assert!(generated[0].body.contains("padding: 3px;"));
+        assert!(generated[1].body.contains("calc(var(--spacing) * 2)"));
+        assert!(generated[2].body.contains("any-pointer"));
+        assert!(generated[3].body.contains("#e11"));
+
+        assert_eq!(env.utility_origin("p-4"), Some("brand.css"));
+        assert_eq!(env.utility_origin("p-*"), Some("default"));
+        assert_eq!(env.variant_origin("pointer-fine"), Some("brand.css"));
+        assert_eq!(env.theme_var_origin("color-red-500"), Some("brand.css"));
+        assert_eq!(env.theme_var_origin("spacing"), Some("default theme"));
+
+        env.options.error_on_conflict = true;
+        let errors = env
+            .try_load_named_config(
+                "strict.css",
+                "@theme {\n    --color-red-500: #f00;\n    --spacing: 0.25rem;\n}",
+            )
+            .expect_err("redefining a var should fail");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].message,
+            "theme var --color-red-500 is already defined by brand.css"
+        );
+        assert_eq!(errors[0].span, 13..35);
+        assert_eq!(env.theme.vars["color-red-500"], "#e11");
+        assert!(!env.config_names().contains(&"strict.css".to_string()));
+
+        // the same definition again isn't a conflict, for utilities and variants neither. the
+        // span is the one of the name, not the first text that starts like it
+        let config = "@utility p-40 {\n    padding: 1px;\n}\n@utility p-4 {\n    padding: 3px;\n}\n\
+                      @utility p-4 {\n    padding: 4px;\n}\n\
+                      @custom-variant pointer-fine (@media (any-pointer: fine));";
+        let errors = env
+            .try_load_named_config("strict.css", config)
+            .expect_err("redefining a utility should fail");
+        assert_eq!(errors.len(), 1, "{errors:?}");
+        assert_eq!(
+            errors[0].message,
+            "utility p-4 is already defined by brand.css"
+        );
+        assert_eq!(&config[errors[0].span.clone()], "p-4");
+        assert!(errors[0].span.start > config.find("padding: 3px").unwrap());
+    }
+
+    #[test]
+    fn test_utility_keyframes() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config(
+            "@utility animate-shake-* {
+                animation: shake --value([*]) ease-in-out infinite;
+                @keyframes shake {
+                    50% { translate: 2px; }
+                }
+            }
+            @utility wobble {
+                animation: wobble 1s;
+                @keyframes wobble {
+                    50% { rotate: 3deg; }
+                }
+            }",
+        );
+        env.compile_classes(&CompileOptions::default(), ["animate-shake-[2s]"]);
+
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("@keyframes shake {"));
+        assert!(!css.contains("@keyframes wobble"));
+        assert!(css.contains("animation: shake 2s ease-in-out infinite;"));
+        assert_eq!(env.defs[0].body.matches('{').count(), 0);
+    }
+
+    #[test]
+    fn test_used_keyframes() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.load_config(
+            "@theme {
+                --animate-jiggle: shake 1s;
+                @keyframes wiggle {
+                    50% { rotate: 3deg; }
+                }
+                @keyframes shake {
+                    50% { translate: 2px; }
+                }
+            }
+            @utility jiggle {
+                animation: var(--animate-jiggle);
+            }",
+        );
+        env.parse_full_string(
+            &CompileOptions::default(),
+            "animate-[wiggle_1s_ease-in-out_infinite] bounce",
+        );
+        assert_eq!(env.used_keyframes(), BTreeSet::from(["wiggle"]));
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("@keyframes wiggle {"));
+        assert!(!css.contains("@keyframes shake") && !css.contains("@keyframes spin"));
+        env.options.keep_keyframes = BTreeSet::from(["ping".to_string()]);
+        assert!(env.to_css_stylesheet(false).contains("@keyframes ping {"));
+        env.options.keep_keyframes.clear();
+
+        env.parse_full_string(&CompileOptions::default(), "jiggle animate-spin");
+        assert_eq!(
+            env.used_keyframes(),
+            BTreeSet::from(["shake", "spin", "wiggle"])
+        );
+    }
+
+    #[test]
+    fn test_metrics() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.inline_vars = true;
+        env.parse_full_string(
+            &CompileOptions::default(),
+            "p-4 hover:bg-red-500 animate-spin",
+        );
+
+        let metrics = env.metrics(false);
+        assert_eq!(metrics.rules, 3);
+        assert_eq!(metrics.declarations, 3);
+        assert_eq!(metrics.keyframes_used, 1);
+        assert!(metrics.keyframes_total > 1);
+        assert!(metrics.theme_vars_emitted < metrics.theme_vars_total);
+        assert_eq!(metrics.output_bytes, env.to_css_stylesheet(false).len());
+        assert!(metrics.estimated_gzip_bytes < metrics.output_bytes);
+        assert_eq!(metrics.preflight_bytes, 0);
+        assert!(env.metrics(true).preflight_bytes > 0);
+
+        // without inlining every var is emitted, those of variant themes too
+        env.options.inline_vars = false;
+        env.try_load_config("@theme dark {\n--color-ink: #eee;\n}")
+            .unwrap();
+        let metrics = env.metrics(false);
+        assert_eq!(metrics.theme_vars_emitted, metrics.theme_vars_total);
+    }
+
+    #[test]
+    fn test_theme_vars_split() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.compile_classes(&CompileOptions::default(), ["p-4"]);
+        env.options.no_theme_vars = true;
+        let css = env.to_css_stylesheet(false);
+        assert!(!css.contains(":root") && css.contains(".p-4{"));
+
+        let vars = env.to_theme_vars_stylesheet();
+        assert!(vars.starts_with(":root {\n") && vars.contains("--spacing: 0.25rem;"));
+        assert!(!vars.contains(".p-4{") && !vars.contains("@keyframes"));
+
+        // the same on every run, sorted by name
+        assert_eq!(
+            vars,
+            EmitEnv::new_with_default_config().to_theme_vars_stylesheet()
+        );
+        let names = vars
+            .lines()
+            .filter_map(|line| line.strip_prefix("--")?.split_once(':'))
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        assert!(names.is_sorted(), "{vars}");
+    }
+
+    #[test]
+    fn test_custom_preflight() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.preflight = Some("html {\n  color: red;\n}\n".to_string());
+        env.options.scope = Some("#app".to_string());
+        assert!(
+            env.to_css_stylesheet(true)
+                .starts_with("#app {\n  color: red;\n}\n")
+        );
+        assert_eq!(env.to_preflight_stylesheet(), "#app {\n  color: red;\n}\n");
+        assert_eq!(env.metrics(true).preflight_bytes, 23);
+    }
+
+    #[test]
+    fn test_usage_report() {
+        let mut env = EmitEnv::new_with_default_config();
+        let mut report = UsageReport::default();
+        let options = CompileOptions::default();
+        env.collect_usage(&options, "a.html", "<div class=\"p-4 flex\">", &mut report);
+        env.collect_usage(
+            &options,
+            "b.html",
+            "<p\n  class=\"p-4 not-a-class bg-red-510 hover:p-x\">",
+            &mut report,
+        );
+
+        assert_eq!(
+            report.classes["p-4"],
+            vec![
+                Location {
+                    file: "a.html".to_string(),
+                    line: 1,
+                    column: 13,
+                    offset: 12
+                },
+                Location {
+                    file: "b.html".to_string(),
+                    line: 2,
+                    column: 10,
+                    offset: 12
+                }
+            ]
+        );
+        assert_eq!(report.classes["flex"].len(), 1);
+        assert_eq!(
+            report.unresolved.keys().collect::<Vec<_>>(),
+            ["not-a-class"]
+        );
+        assert_eq!(
+            report.unknown_values.keys().collect::<Vec<_>>(),
+            ["bg-red-510", "hover:p-x"]
+        );
+        let json = serde_json::from_str::<serde_json::Value>(&report.to_json()).unwrap();
+        assert_eq!(
+            json["classes"]["p-4"][0],
+            serde_json::json!({"file": "a.html", "line": 1, "column": 13})
+        );
+
+        env.collect_usage(
+            &options,
+            "c.js",
+            "const a = \"hover:bg-\" + color;\nconst b = `text-${size}`;\nformat!(\"p-{}\", n); \"not-\" + x; \"foo-\" + y;",
+            &mut report,
+        );
+        assert_eq!(
+            report.dynamic.keys().collect::<Vec<_>>(),
+            ["hover:bg-", "p-", "text-"]
+        );
+        assert_eq!(report.dynamic["text-"][0].line, 2);
+    }
+
+    #[test]
+    fn test_unresolved_in_html_document() {
+        let mut env = EmitEnv::new_with_default_config();
+        let mut report = UsageReport::default();
+        env.collect_usage(
+            &CompileOptions::default(),
+            "index.html",
+            r#"<!DOCTYPE html>
+<html lang="en-US">
+<head>
+  <meta charset="utf-8">
+  <meta http-equiv="X-UA-Compatible" content="IE=edge">
+  <meta name="viewport" content="width=device-width, initial-scale=1">
+  <title>Pricing - Acme</title>
+</head>
+<body>
+  <button aria-label="close-dialog" data-foo="x" class="p-4 hover:bg-red-500 not-a-class">
+  <p>The well-known set-up is described on https://example.com/docs.</p>
+  <p className='bg-red-510 hover:p-x'>tw-like</p>
+</body>
+</html>"#,
+            &mut report,
+        );
+        assert_eq!(
+            report.unresolved.keys().collect::<Vec<_>>(),
+            ["not-a-class"]
+        );
+        assert_eq!(
+            report.unknown_values.keys().collect::<Vec<_>>(),
+            ["bg-red-510", "hover:p-x"]
+        );
+
+        // outside of class attributes only words written like classes are reported
+        let mut report = UsageReport::default();
+        env.collect_usage(
+            &CompileOptions::default(),
+            "app.js",
+            "const a = \"bg-red-510\"; const b = \"md:frobnicate\"; const c = \"e-mail\";",
+            &mut report,
+        );
+        assert_eq!(
+            report.unresolved.keys().collect::<Vec<_>>(),
+            ["md:frobnicate"]
+        );
+        assert_eq!(
+            report.unknown_values.keys().collect::<Vec<_>>(),
+            ["bg-red-510"]
+        );
+    }
+
+    #[test]
+    fn test_property_order() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.parse_full_string(
+            &CompileOptions::default(),
+            "hover:p-1 pt-2 px-3 p-4 top-0 inset-0",
+        );
+
+        let css = env.to_css_stylesheet(false);
+        let positions = [
+            "inset-0{",
+            "top-0{",
+            "p-4{",
+            "px-3{",
+            "pt-2{",
+            "hover\\:p-1{",
+        ]
+        .map(|selector| css.find(selector).unwrap());
+        assert!(positions.is_sorted(), "{positions:?}");
+    }
+
+    #[test]
+    fn test_config_errors_and_hints() {
+        let mut env = EmitEnv::new_with_default_config();
+        let errors = env
+            .try_load_config("@utility ok {\n    color: red;\n}\n@utilty broken {}")
+            .unwrap_err();
+        assert_eq!(errors[0].span, 37..38);
+
+        assert_eq!(
+            env.unresolved_hint("hover:flx").as_deref(),
+            Some("did you mean `flex`?")
+        );
+        assert_eq!(
+            env.unresolved_hint("bg-notacolor").as_deref(),
+            Some("`bg-*` exists, but `notacolor` isn't one of its values")
+        );
+    }
+
+    #[test]
+    fn test_retract_source() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions::default();
+        env.compile_classes(&options, ["grid"]);
+        env.parse_source(&options, "a.html", "<div class=\"p-4 flex grid\">");
+        env.parse_source(&options, "b.html", "<div class=\"p-4\">");
+
+        let removed = env.retract_source("a.html");
+        assert_eq!(removed.len(), 1);
+        assert_eq!(removed[0].class_name, "flex");
+        // p-4 is used by b.html, grid was there before
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".p-4{") && css.contains(".grid{") && !css.contains(".flex{"));
+
+        assert_eq!(env.retract_source("b.html").len(), 1);
+        assert!(env.retract_source("b.html").is_empty());
+        env.parse_full_string(&options, "p-4");
+        assert!(env.to_css_stylesheet(false).contains(".p-4{"));
+    }
+
+    #[test]
+    fn test_attribute_selector() {
+        assert_eq!(
+            attribute_selector("data-", "state=open"),
+            r#"[data-state="open"]"#
+        );
+        assert_eq!(
+            attribute_selector("data-", "size~='large' i"),
+            r#"[data-size~="large" i]"#
+        );
+        assert_eq!(
+            attribute_selector("data-", r#"label^="a b""#),
+            r#"[data-label^="a b"]"#
+        );
+        assert_eq!(
+            attribute_selector("data-", r#"quote$='say "hi"'"#),
+            r#"[data-quote$="say \"hi\""]"#
+        );
+        assert_eq!(attribute_selector("data-", "active"), "[data-active]");
+    }
+
+    #[test]
+    fn test_pseudo_element_variants() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        assert!(env.load_config("@pseudo-element target = target-text;"));
+        env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "file:p-4",
+                "first-letter:flex",
+                "details-content:grid",
+                "grammar-error:underline",
+                "marker:text-red-500",
+                "target:hidden",
+            ],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".file\\:p-4::file-selector-button{"), "{css}");
+        assert!(css.contains(".first-letter\\:flex::first-letter{"));
+        assert!(css.contains(".details-content\\:grid::details-content{"));
+        assert!(css.contains(".grammar-error\\:underline::grammar-error{"));
+        assert!(css.contains(".marker\\:text-red-500{\n&::marker, & *::marker {"));
+        assert!(css.contains(".target\\:hidden::target-text{"));
+
+        // built in, they work without the default config
+        let mut env = EmitEnv::new();
+        assert!(env.load_config("@utility flex { display: flex; }"));
+        let generated = env.compile_classes(
+            &CompileOptions::default(),
+            ["before:flex", "after:flex", "file:flex", "placeholder:flex"],
+        );
+        assert_eq!(generated.len(), 4);
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".before\\:flex::before{"), "{css}");
+        assert!(css.contains(".after\\:flex::after{"));
+        assert!(css.contains(".file\\:flex::file-selector-button{"));
+        assert!(css.contains(".placeholder\\:flex::placeholder{"));
+    }
+
+    #[test]
+    fn test_arbitrary_color_alpha() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "bg-[#ff0000]/50",
+                "text-[rgb(0_0_0)]/[0.25]",
+                "border-[var(--brand)]/25",
+                "bg-[color:red]/40",
+                "bg-[length:1px]/50",
+                "p-[1rem]/50",
+                "bg-[1px]/50",
+                "text-[2rem]/50",
+                "bg-[url(x.png)]/50",
+            ],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("background-color: #ff000080;"), "{css}");
+        assert!(css.contains("color: rgb(0 0 0 / 25%);"));
+        assert!(css.contains("color-mix(in oklab, var(--brand) 25%, transparent)"));
+        assert!(css.contains("background-color: color-mix(in oklab, red 40%, transparent);"));
+        assert!(!css.contains("bg-\\[length"));
+        assert!(!css.contains("p-\\["));
+        // only colors take the alpha
+        assert!(!css.contains("bg-\\[1px\\]"));
+        assert!(!css.contains("text-\\[2rem\\]"));
+        assert!(!css.contains("bg-\\[url"));
+        assert_eq!(css.matches("color-mix").count(), 2);
+    }
+
+    #[test]
+    fn test_variant_aliases() {
+        let mut env = EmitEnv::new_with_default_config();
+        assert!(env.load_config(
+            "@alias-variant parent-hover = group-hover;\n@alias-variant dark-hocus = dark:hover;\n@alias-variant tablet = md;\n@alias-variant loop-a = loop-b;\n@alias-variant loop-b = loop-a;"
+        ));
+        let options = CompileOptions::default();
+        env.compile_classes(
+            &options,
+            [
+                "parent-hover:flex",
+                "group-hover:flex",
+                "dark-hocus:p-4",
+                "tablet:grid",
+            ],
+        );
+
+        let css = env.to_css_stylesheet(false);
+        let group_hover = env.defs[1].body.clone();
+        assert_eq!(env.defs[0].body, group_hover);
+        assert_eq!(env.defs[1].variant_count, 1);
+        assert_eq!(env.defs[2].variant_count, 2);
+        assert!(css.contains("prefers-color-scheme: dark"));
+        assert!(css.contains("@media (width >= 48rem)"));
+        assert!(env.parse_tailwind_str(&options, "loop-a:flex").is_none());
+    }
+
+    #[test]
+    fn test_style_query_variants() {
+        let mut env = EmitEnv::new_with_default_config();
+        let options = CompileOptions::default();
+        env.compile_classes(
+            &options,
+            ["@style-[--theme:dark]:bg-black", "@style-[--compact]:p-2"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains(".\\@style-\\[--theme\\:dark\\]\\:bg-black{"));
+        assert!(css.contains("@container style(--theme: dark) {"));
+        assert!(css.contains("@container style(--compact) {"));
+        assert!(env.parse_tailwind_str(&options, "@style:flex").is_none());
+    }
 
-        self.defs.push(css_def.clone());
-        self.defs_generated.push(css_def.class_name.clone());
-        Some((css_def, end))
+    #[test]
+    fn test_supports_queries() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.compile_classes(
+            &CompileOptions::default(),
+            [
+                "supports-[selector(&::details-content)]:block",
+                "supports-[display:grid]:grid",
+                "supports-[font-tech(color-COLRv1)]:font-sans",
+                "not-supports-[display:grid]:flex",
+                "not-supports-grid:block",
+            ],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(css.contains("@supports selector(::details-content) {"));
+        assert!(css.contains("@supports (display: grid) {"));
+        assert!(css.contains("@supports font-tech(color-COLRv1) {"));
+        assert!(css.contains("@supports not (display: grid) {"));
+        assert!(css.contains("@supports not (grid) {"));
     }
 }
@@ -1,19 +1,408 @@
-use clap::Parser;
-use duckwind::EmitEnv;
+use ariadne::{Color, Config, IndexType, Label, Report, Source};
+use clap::{Parser, Subcommand};
+use duckwind::{
+    CompileOptions, EmitEnv, HashedClassNames, NormalizedStylesheet, RuleOrder, SourceMap,
+    UsageReport, extract_markdown, inject_stylesheet, rust_const,
+};
 
-use notify::{Event, EventKind, RecursiveMode, Result, Watcher, event::DataChange};
-use std::{path::Path, sync::mpsc, time::Instant};
+use serde::{Deserialize, Serialize};
 
-#[derive(Parser, Debug)]
-struct Args {
+use globset::{Glob, GlobBuilder, GlobSet, GlobSetBuilder};
+use notify::{
+    Event, EventKind, RecursiveMode, Result, Watcher,
+    event::{MetadataKind, ModifyKind},
+};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashSet},
+    io::IsTerminal,
+    ops::Range,
+    path::{Component, Path, PathBuf},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+/// file with defaults for the cli flags, looked up in the working directory
+const SETTINGS_FILE: &str = "duckwind.toml";
+
+/// directories that are never scanned by content globs or watched
+const DEFAULT_IGNORES: &[&str] = &["**/target", "**/node_modules", "**/.git"];
+
+/// the stylesheet `--out-dir` writes the theme vars and preflight to
+const SHARED_STYLESHEET: &str = "shared.css";
+
+#[derive(clap::Args, Debug, Clone)]
+struct CompileArgs {
     #[arg(name = "input", help = "load the input from this file")]
     in_file: Vec<String>,
     #[arg(
         long = "str",
         short = 's',
-        help = "interpret the input as a string and not as a file name"
+        value_name = "TEXT",
+        help = "also scan this text for classes, can be given more than once"
+    )]
+    strings: Vec<String>,
+    #[arg(
+        long,
+        short = 'd',
+        help = "do not include preflight styles",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    no_preflight: Option<bool>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "use this css as preflight instead of the bundled one"
+    )]
+    preflight: Option<String>,
+    #[arg(
+        long,
+        help = "leave out the :root theme vars, for pages that already define the tokens",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    no_theme_vars: Option<bool>,
+    #[arg(
+        long,
+        conflicts_with = "no_theme_vars",
+        help = "only output the :root theme vars, to ship the tokens separately",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    only_theme_vars: Option<bool>,
+    #[arg(
+        long,
+        help = "keep the specificity of variant selectors instead of wrapping them in :where()",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    no_where_variants: Option<bool>,
+    #[arg(
+        long,
+        short = 'n',
+        help = "do not load default config",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    no_default_config: Option<bool>,
+    #[arg(
+        long,
+        help = "fail instead of overriding when a config redefines a utility, variant or theme var of an earlier one differently",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    error_on_conflict: Option<bool>,
+    #[arg(long, short = 'c', help = "load this config")]
+    config: Vec<String>,
+    #[arg(
+        long,
+        short = 'p',
+        help = "only classes starting with this prefix are generated, e.g. tw: for tw:flex"
+    )]
+    prefix: Option<String>,
+    #[arg(
+        long,
+        help = "mark every generated declaration as !important",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    important: Option<bool>,
+    #[arg(
+        long,
+        help = "nest the stylesheet under this root selector, e.g. #app, preflight and theme vars included"
+    )]
+    scope: Option<String>,
+    #[arg(
+        long,
+        help = "scope the stylesheet with an @scope (...) block instead of prefixing every selector",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    scope_at_rule: Option<bool>,
+    #[arg(
+        long,
+        help = "wrap the generated selectors in :where() so component css can override them",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    low_specificity: Option<bool>,
+    #[arg(
+        long,
+        help = "inline theme vars into the generated rules instead of referencing them",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    inline_vars: Option<bool>,
+    #[arg(
+        long,
+        help = "evaluate constant calc() expressions, e.g. calc(var(--spacing) * 4) -> 1rem",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    fold_calc: Option<bool>,
+    #[arg(
+        long,
+        help = "order the rules by utility group, variants and name instead of the order the classes were found in, so reordering the sources doesn't change the output",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    canonical_order: Option<bool>,
+    #[arg(
+        long,
+        value_name = "MODE",
+        help = "what dark: checks for: media (prefers-color-scheme, the default), media-and-class (prefers-color-scheme unless .light, or .dark), class (.dark), data-attribute ([data-theme=\"dark\"]) or any selector"
     )]
-    from_string: bool,
+    dark_mode: Option<String>,
+    #[arg(
+        long,
+        help = "prefix every rule with a comment naming its class and where it was found",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    debug_comments: Option<bool>,
+    #[arg(
+        long,
+        help = "minify the output, without the whitespace and comments of the pretty-printed css",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    minify: Option<bool>,
+    #[arg(
+        long,
+        help = "write a source map from the rules to the classes they were generated for next to the output file, as <out>.map",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    source_map: Option<bool>,
+    #[arg(
+        long,
+        help = "emit the theme vars, preflight and rules in @layer theme, base, components and utilities",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    layers: Option<bool>,
+    #[arg(
+        long,
+        help = "emit rules with the same declarations as one rule with a selector list where that doesn't change the cascade",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    merge_rules: Option<bool>,
+    #[arg(
+        long,
+        help = "emit plain rules instead of nested ones, for browsers without css nesting",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    flatten_nesting: Option<bool>,
+    #[arg(
+        long,
+        help = "merge neighbouring rules in the same media or container query into one block",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    merge_media_queries: Option<bool>,
+    #[arg(
+        long,
+        help = "minify the output and merge rules and media queries where the cascade allows it",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    optimize: Option<bool>,
+    #[arg(
+        long,
+        value_name = "BROWSERS",
+        help = "the oldest browsers to support, like \"chrome 100, firefox 115, safari 15.4\". nesting, media ranges and modern colors are lowered for them"
+    )]
+    targets: Option<String>,
+    #[arg(
+        long,
+        help = "add an srgb fallback in front of declarations with oklch(), lab() and the like",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    color_fallbacks: Option<bool>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "NAME",
+        help = "emit these @keyframes of the theme even if no class animates with them"
+    )]
+    keep_keyframes: Vec<String>,
+    #[arg(
+        long,
+        help = "emit the stylesheet for a shadow root, with :host instead of the document selectors",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    shadow_dom: Option<bool>,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        value_name = "GROUP",
+        help = "only generate the utilities of these groups, e.g. spacing,flexbox-grid,backgrounds"
+    )]
+    groups: Vec<String>,
+    #[arg(
+        long,
+        help = "report every class-like word that doesn't resolve to a rule as an error",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true"
+    )]
+    strict: Option<bool>,
+    #[arg(
+        long,
+        value_name = "MANIFEST",
+        help = "compile the classes listed in this json manifest ({\"candidates\": [...], \"sources\": {\"file\": [...]}}) without scanning any text"
+    )]
+    candidates: Vec<String>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(about = "compare the generated stylesheet against a reference stylesheet")]
+    Diff {
+        #[arg(help = "the reference stylesheet")]
+        expected: String,
+        #[command(flatten)]
+        compile: CompileArgs,
+    },
+    #[command(
+        about = "read candidates line by line from stdin and write the css for them to stdout, every response ends with a /* duckwind:end */ line"
+    )]
+    Pipe {
+        #[arg(
+            long,
+            help = "respond with the full stylesheet instead of only the new rules"
+        )]
+        full: bool,
+        #[command(flatten)]
+        compile: CompileArgs,
+    },
+    #[command(
+        about = "scan a single html document and add the css it needs to a <style> element in its <head>"
+    )]
+    Inline {
+        #[arg(
+            long,
+            short = 'o',
+            help = "write the document to this file instead of stdout"
+        )]
+        out: Option<String>,
+        #[command(flatten)]
+        compile: CompileArgs,
+    },
+    #[command(
+        about = "check the project setup (configs, content globs, output, watch) and print how to fix what's wrong"
+    )]
+    Doctor {
+        #[command(flatten)]
+        compile: CompileArgs,
+    },
+    #[command(about = "report on how the classes of a build are used")]
+    Report {
+        #[command(subcommand)]
+        kind: ReportKind,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum ReportKind {
+    #[command(about = "map each generated class to every place it was found")]
+    Usage {
+        #[arg(long, value_enum, default_value_t = ReportFormat::Json)]
+        format: ReportFormat,
+        #[arg(long, short = 'o', help = "write the report to this file")]
+        out: Option<String>,
+        #[command(flatten)]
+        compile: CompileArgs,
+    },
+    #[command(
+        about = "warn about classes that are likely built at runtime, like \"bg-\" + color, and can't be generated"
+    )]
+    Dynamic {
+        #[command(flatten)]
+        compile: CompileArgs,
+    },
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy)]
+enum ReportFormat {
+    Json,
+    Html,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum OutputFormat {
+    #[default]
+    Css,
+    /// class -> selector suffix, at-rules and declarations, for css-in-js runtimes
+    Json,
+    /// an es module exporting the stylesheet as a string and as a constructed stylesheet
+    /// for `adoptedStyleSheets`
+    Js,
+    /// a rust source file with the stylesheet as `pub const STYLES: &str`, to `include!` in
+    /// embedded and wasm apps
+    Rust,
+}
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum WatchBackend {
+    #[default]
+    Auto,
+    /// inotify, FSEvents or ReadDirectoryChangesW, fails instead of falling back
+    Native,
+    Poll,
+}
+
+/// interval for `--watch-poll` when only `--watch-backend poll` is given
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// `500ms`, `2s` or a number of milliseconds
+fn parse_duration(s: &str) -> std::result::Result<Duration, String> {
+    let (number, unit_ms) = if let Some(ms) = s.strip_suffix("ms") {
+        (ms, 1.0)
+    } else if let Some(secs) = s.strip_suffix('s') {
+        (secs, 1000.0)
+    } else {
+        (s, 1.0)
+    };
+    match number.trim().parse::<f64>() {
+        Ok(n) if n > 0.0 => Ok(Duration::from_millis((n * unit_ms).round() as u64)),
+        _ => Err(format!("{s} isn't a duration like 500ms or 2s")),
+    }
+}
+
+#[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Args {
+    #[command(flatten)]
+    compile: CompileArgs,
     #[arg(
         long,
         short = 'o',
@@ -21,64 +410,1332 @@ struct Args {
         help = "write the output to this file"
     )]
     out: Option<String>,
-    #[arg(long, short = 'd', help = "do not include preflight styles")]
-    no_preflight: bool,
-    #[arg(long, short = 'n', help = "do not load default config")]
-    no_default_config: bool,
-    #[arg(long, short = 'c', help = "load this config")]
-    config: Vec<String>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        conflicts_with_all = ["output file", "watch", "dry_run"],
+        help = "write a stylesheet per input with only the classes found in it to this directory, and shared.css with the theme vars and preflight"
+    )]
+    out_dir: Option<String>,
+    #[arg(long, value_enum, help = "what to write [default: css]")]
+    format: Option<OutputFormat>,
+    #[arg(
+        long,
+        value_name = "MANIFEST",
+        help = "rename the generated classes to short hashes like dw-x7f3a and write the original -> hashed names to this json file"
+    )]
+    hash_classes: Option<String>,
     #[arg(
         long,
         short = 'w',
-        help = "watch a file, relaunching with the same parameters if it changes (requires out file)"
+        help = "watch a file, directory or glob (like src/**/*.html), relaunching with the same parameters if a matching file changes (requires out file)"
     )]
-    watch: Option<String>,
+    watch: Vec<String>,
     #[arg(
         long,
-        short = 'p',
-        help = "configure a prefix to be used for the tailwind classes"
+        help = "don't relaunch for changes to paths matching this glob, target, node_modules and .git are always ignored"
+    )]
+    watch_ignore: Vec<String>,
+    #[arg(
+        long,
+        value_enum,
+        help = "how to watch for changes, auto uses the native backend and falls back to polling if it fails [default: auto]"
+    )]
+    watch_backend: Option<WatchBackend>,
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = parse_duration,
+        help = "poll for changes at this interval (like 500ms or 2s) instead of using the native backend, for network mounts, docker volumes and WSL"
+    )]
+    watch_poll: Option<Duration>,
+    #[arg(
+        long,
+        value_name = "MANIFEST",
+        conflicts_with = "watch",
+        help = "write the classes that were found, and the files they were found in, to this json manifest"
+    )]
+    write_candidates: Option<String>,
+    #[arg(long, help = "print size statistics about the output to stderr")]
+    stats: bool,
+    #[arg(
+        long,
+        conflicts_with = "watch",
+        help = "compile but don't write anything, print what would be written instead. exits with 1 if the out file would change"
     )]
+    dry_run: bool,
+    #[arg(
+        long,
+        short = 'v',
+        action = clap::ArgAction::Count,
+        global = true,
+        help = "log more details, -vv logs every generated class"
+    )]
+    verbose: u8,
+    #[arg(
+        long,
+        short = 'q',
+        global = true,
+        conflicts_with = "verbose",
+        help = "only log errors"
+    )]
+    quiet: bool,
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// defaults for the cli flags from `duckwind.toml` or `[package.metadata.duckwind]` in
+/// `Cargo.toml`, flags given on the command line take precedence. `--minify=false` turns off a
+/// flag the settings turn on
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+struct Settings {
+    /// files or globs to scan for classes
+    content: Vec<String>,
+    candidates: Vec<String>,
+    config: Vec<String>,
+    out: Option<String>,
+    format: Option<OutputFormat>,
+    hash_classes: Option<String>,
     prefix: Option<String>,
+    scope: Option<String>,
+    scope_at_rule: Option<bool>,
+    preflight: Option<String>,
+    no_preflight: Option<bool>,
+    no_theme_vars: Option<bool>,
+    only_theme_vars: Option<bool>,
+    no_where_variants: Option<bool>,
+    no_default_config: Option<bool>,
+    error_on_conflict: Option<bool>,
+    important: Option<bool>,
+    low_specificity: Option<bool>,
+    inline_vars: Option<bool>,
+    fold_calc: Option<bool>,
+    canonical_order: Option<bool>,
+    dark_mode: Option<String>,
+    debug_comments: Option<bool>,
+    minify: Option<bool>,
+    source_map: Option<bool>,
+    layers: Option<bool>,
+    merge_rules: Option<bool>,
+    flatten_nesting: Option<bool>,
+    merge_media_queries: Option<bool>,
+    optimize: Option<bool>,
+    targets: Option<String>,
+    color_fallbacks: Option<bool>,
+    keep_keyframes: Vec<String>,
+    shadow_dom: Option<bool>,
+    groups: Vec<String>,
+    strict: Option<bool>,
+    watch: Vec<String>,
+    watch_ignore: Vec<String>,
+    watch_backend: Option<WatchBackend>,
+    watch_poll: Option<String>,
 }
 
-fn main() -> Result<()> {
-    let cli = Args::parse();
+#[derive(Deserialize)]
+struct CargoManifest {
+    package: Option<CargoPackage>,
+}
 
-    let run = || {
-        let mut emit_env = if cli.no_default_config {
-            EmitEnv::new()
-        } else {
-            EmitEnv::new_with_default_config()
+#[derive(Deserialize)]
+struct CargoPackage {
+    metadata: Option<CargoMetadata>,
+}
+
+#[derive(Deserialize)]
+struct CargoMetadata {
+    duckwind: Option<Settings>,
+}
+
+impl Settings {
+    fn load() -> std::result::Result<Option<Self>, String> {
+        if let Ok(src) = std::fs::read_to_string(SETTINGS_FILE) {
+            return toml::from_str(&src)
+                .map(Some)
+                .map_err(|e| format!("couldn't parse {SETTINGS_FILE}: {e}"));
+        }
+        if let Ok(src) = std::fs::read_to_string("Cargo.toml") {
+            let manifest = toml::from_str::<CargoManifest>(&src)
+                .map_err(|e| format!("couldn't parse [package.metadata.duckwind]: {e}"))?;
+            return Ok(manifest
+                .package
+                .and_then(|package| package.metadata)
+                .and_then(|metadata| metadata.duckwind));
+        }
+        Ok(None)
+    }
+
+    fn apply(&self, cli: &mut CompileArgs) {
+        if cli.in_file.is_empty() && cli.strings.is_empty() {
+            cli.in_file = self.content.clone();
+        }
+        if cli.config.is_empty() {
+            cli.config = self.config.clone();
+        }
+        if cli.candidates.is_empty() {
+            cli.candidates = self.candidates.clone();
+        }
+        if cli.groups.is_empty() {
+            cli.groups = self.groups.clone();
+        }
+        if cli.keep_keyframes.is_empty() {
+            cli.keep_keyframes = self.keep_keyframes.clone();
+        }
+        cli.prefix = cli.prefix.take().or_else(|| self.prefix.clone());
+        cli.scope = cli.scope.take().or_else(|| self.scope.clone());
+        cli.preflight = cli.preflight.take().or_else(|| self.preflight.clone());
+        cli.no_preflight = cli.no_preflight.or(self.no_preflight);
+        cli.no_theme_vars = cli.no_theme_vars.or(self.no_theme_vars);
+        cli.only_theme_vars = cli.only_theme_vars.or(self.only_theme_vars);
+        cli.no_where_variants = cli.no_where_variants.or(self.no_where_variants);
+        cli.no_default_config = cli.no_default_config.or(self.no_default_config);
+        cli.error_on_conflict = cli.error_on_conflict.or(self.error_on_conflict);
+        cli.important = cli.important.or(self.important);
+        cli.low_specificity = cli.low_specificity.or(self.low_specificity);
+        cli.inline_vars = cli.inline_vars.or(self.inline_vars);
+        cli.fold_calc = cli.fold_calc.or(self.fold_calc);
+        cli.canonical_order = cli.canonical_order.or(self.canonical_order);
+        cli.dark_mode = cli.dark_mode.take().or_else(|| self.dark_mode.clone());
+        cli.debug_comments = cli.debug_comments.or(self.debug_comments);
+        cli.minify = cli.minify.or(self.minify);
+        cli.source_map = cli.source_map.or(self.source_map);
+        cli.layers = cli.layers.or(self.layers);
+        cli.merge_rules = cli.merge_rules.or(self.merge_rules);
+        cli.flatten_nesting = cli.flatten_nesting.or(self.flatten_nesting);
+        cli.merge_media_queries = cli.merge_media_queries.or(self.merge_media_queries);
+        cli.optimize = cli.optimize.or(self.optimize);
+        cli.targets = cli.targets.take().or_else(|| self.targets.clone());
+        cli.color_fallbacks = cli.color_fallbacks.or(self.color_fallbacks);
+        cli.shadow_dom = cli.shadow_dom.or(self.shadow_dom);
+        cli.scope_at_rule = cli.scope_at_rule.or(self.scope_at_rule);
+        cli.strict = cli.strict.or(self.strict);
+    }
+
+    fn apply_to_args(&self, cli: &mut Args) {
+        self.apply(&mut cli.compile);
+        cli.out = cli.out.take().or_else(|| self.out.clone());
+        cli.format = cli.format.or(self.format);
+        cli.hash_classes = cli
+            .hash_classes
+            .take()
+            .or_else(|| self.hash_classes.clone());
+        if cli.watch.is_empty() {
+            cli.watch = self.watch.clone();
+        }
+        if cli.watch_ignore.is_empty() {
+            cli.watch_ignore = self.watch_ignore.clone();
+        }
+        cli.watch_backend = cli.watch_backend.or(self.watch_backend);
+        if cli.watch_poll.is_none()
+            && let Some(interval) = &self.watch_poll
+        {
+            match parse_duration(interval) {
+                Ok(interval) => cli.watch_poll = Some(interval),
+                Err(e) => tracing::warn!("ignoring watch-poll in {SETTINGS_FILE}: {e}"),
+            }
+        }
+        match &mut cli.command {
+            Some(Command::Diff { compile, .. })
+            | Some(Command::Pipe { compile, .. })
+            | Some(Command::Doctor { compile })
+            | Some(Command::Inline { compile, .. })
+            | Some(Command::Report {
+                kind: ReportKind::Usage { compile, .. } | ReportKind::Dynamic { compile },
+            }) => self.apply(compile),
+            None => {}
+        }
+    }
+}
+
+/// prints an error with the offending part of `src` underlined
+fn render_error(
+    file: &str,
+    src: &str,
+    span: Range<usize>,
+    message: &str,
+    label: &str,
+    help: Option<&str>,
+) {
+    render_report(
+        ariadne::ReportKind::Error,
+        file,
+        src,
+        span,
+        message,
+        label,
+        help,
+    );
+}
+
+/// like `render_error` for something that doesn't fail the build
+fn render_warning(
+    file: &str,
+    src: &str,
+    span: Range<usize>,
+    message: &str,
+    label: &str,
+    help: Option<&str>,
+) {
+    render_report(
+        ariadne::ReportKind::Warning,
+        file,
+        src,
+        span,
+        message,
+        label,
+        help,
+    );
+}
+
+fn render_report(
+    kind: ariadne::ReportKind,
+    file: &str,
+    src: &str,
+    span: Range<usize>,
+    message: &str,
+    label: &str,
+    help: Option<&str>,
+) {
+    let config = Config::default()
+        .with_index_type(IndexType::Byte)
+        .with_color(std::io::stderr().is_terminal());
+    let color = match kind {
+        ariadne::ReportKind::Warning => Color::Yellow,
+        _ => Color::Red,
+    };
+    let mut report = Report::build(kind, (file, span.clone()))
+        .with_config(config)
+        .with_message(message)
+        .with_label(
+            Label::new((file, span))
+                .with_message(label)
+                .with_color(color),
+        );
+    if let Some(help) = help {
+        report = report.with_help(help);
+    }
+    // failing to print to stderr leaves nowhere to report it
+    let _ = report.finish().eprint((file, Source::from(src)));
+}
+
+/// the env with every config loaded and the number of configs that failed to parse
+fn load_env(cli: &CompileArgs) -> (EmitEnv, usize) {
+    let mut emit_env = if cli.no_default_config.unwrap_or_default() {
+        EmitEnv::new()
+    } else {
+        EmitEnv::new_with_default_config()
+    };
+    emit_env.options.inline_vars = cli.inline_vars.unwrap_or_default();
+    emit_env.options.fold_calc = cli.fold_calc.unwrap_or_default();
+    if cli.canonical_order.unwrap_or_default() {
+        emit_env.options.rule_order = RuleOrder::Canonical;
+    }
+    emit_env.options.debug_comments = cli.debug_comments.unwrap_or_default();
+    emit_env.options.minify = cli.minify.unwrap_or_default();
+    emit_env.options.layers = cli.layers.unwrap_or_default();
+    emit_env.options.merge_rules = cli.merge_rules.unwrap_or_default();
+    emit_env.options.flatten_nesting = cli.flatten_nesting.unwrap_or_default();
+    emit_env.options.merge_media_queries = cli.merge_media_queries.unwrap_or_default();
+    if cli.optimize.unwrap_or_default() {
+        emit_env.options.minify = true;
+        emit_env.options.merge_rules = true;
+        emit_env.options.merge_media_queries = true;
+    }
+    emit_env.options.keep_keyframes = cli.keep_keyframes.iter().cloned().collect();
+    emit_env.options.scope = cli.scope.clone();
+    emit_env.options.scope_at_rule = cli.scope_at_rule.unwrap_or_default();
+    emit_env.options.important = cli.important.unwrap_or_default();
+    emit_env.options.shadow_dom = cli.shadow_dom.unwrap_or_default();
+    emit_env.options.no_theme_vars = cli.no_theme_vars.unwrap_or_default();
+    emit_env.options.no_where_variants = cli.no_where_variants.unwrap_or_default();
+    if let Some(preflight) = &cli.preflight {
+        let preflight_src = std::fs::read_to_string(preflight)
+            .unwrap_or_else(|_| panic!("couldn't load preflight {preflight}"));
+        emit_env.options.preflight = Some(preflight_src);
+    }
+    emit_env.options.error_on_conflict = cli.error_on_conflict.unwrap_or_default();
+    emit_env.options.color_fallbacks = cli.color_fallbacks.unwrap_or_default();
+    let mut errors = 0;
+    if let Some(dark_mode) = &cli.dark_mode {
+        match dark_mode.parse() {
+            Ok(dark_mode) => emit_env.options.dark_mode = dark_mode,
+            Err(e) => {
+                tracing::error!("invalid dark mode: {e}");
+                errors += 1;
+            }
+        }
+    }
+    if let Some(targets) = &cli.targets {
+        match targets.parse() {
+            Ok(targets) => emit_env.options.targets = Some(targets),
+            Err(e) => {
+                tracing::error!("invalid targets: {e}");
+                errors += 1;
+            }
+        }
+    }
+
+    // // dbg!(emit_env.parse_tailwind_str("mask--100"));
+    // // dbg!(emit_env.parse_tailwind_str("text-red-200/30"));
+    // dbg!(emit_env.parse_tailwind_str(Some("tw-"), "tw-mask-y-from-100"));
+    // // dbg!(emit_env.to_css_stylesheet(false));
+    // return;
+
+    for config_to_load in &cli.config {
+        let config_src = std::fs::read_to_string(config_to_load.as_str())
+            .unwrap_or_else(|_| panic!("couldn't load config {config_to_load}"));
+        if let Err(config_errors) = emit_env.try_load_named_config(config_to_load, &config_src) {
+            for error in config_errors {
+                render_error(
+                    config_to_load,
+                    &config_src,
+                    error.span,
+                    "couldn't load the config",
+                    &error.message,
+                    Some("the config wasn't loaded"),
+                );
+            }
+            errors += 1;
+        }
+    }
+
+    for diagnostic in &emit_env.diagnostics {
+        tracing::warn!("{diagnostic}");
+    }
+
+    if !cli.groups.is_empty() {
+        let known = emit_env.utility_groups();
+        for group in cli
+            .groups
+            .iter()
+            .filter(|group| !known.contains(group.as_str()))
+        {
+            tracing::warn!(
+                "unknown utility group {group}, the groups are {}",
+                known.iter().copied().collect::<Vec<_>>().join(", ")
+            );
+        }
+        emit_env.options.utility_groups = Some(cli.groups.iter().cloned().collect());
+    }
+
+    (emit_env, errors)
+}
+
+fn has_glob_chars(s: &str) -> bool {
+    s.contains(['*', '?', '[', '{'])
+}
+
+fn build_glob(pattern: &str) -> Glob {
+    GlobBuilder::new(pattern)
+        .literal_separator(true)
+        .build()
+        .unwrap_or_else(|e| panic!("invalid glob {pattern}: {e}"))
+}
+
+fn build_glob_set<'a>(patterns: impl IntoIterator<Item = &'a str>) -> GlobSet {
+    let mut builder = GlobSetBuilder::new();
+    for pattern in patterns {
+        builder.add(build_glob(pattern));
+    }
+    builder.build().expect("globs are valid")
+}
+
+/// a path is ignored if it or one of its parent directories matches
+fn is_ignored(ignore: &GlobSet, path: &Path) -> bool {
+    path.ancestors().any(|ancestor| ignore.is_match(ancestor))
+}
+
+/// the directory a glob is rooted in, `src/**/*.html` -> `src`
+fn glob_base(pattern: &str) -> PathBuf {
+    let base = Path::new(pattern)
+        .components()
+        .take_while(|component| !has_glob_chars(&component.as_os_str().to_string_lossy()))
+        .collect::<PathBuf>();
+    if base.as_os_str().is_empty() {
+        PathBuf::from(".")
+    } else {
+        base
+    }
+}
+
+/// all files matching a content glob like `src/**/*.html`, sorted
+fn expand_glob(pattern: &str) -> Vec<String> {
+    let glob = build_glob(pattern).compile_matcher();
+    let ignore = build_glob_set(DEFAULT_IGNORES.iter().copied());
+    let mut res = Vec::new();
+    let mut to_visit = vec![glob_base(pattern)];
+    while let Some(dir) = to_visit.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
         };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let path = path.strip_prefix(".").unwrap_or(&path);
+            if is_ignored(&ignore, path) {
+                continue;
+            }
+            if path.is_dir() {
+                to_visit.push(path.to_path_buf());
+            } else if glob.is_match(path) {
+                res.push(path.to_string_lossy().into_owned());
+            }
+        }
+    }
+    res.sort();
+    res
+}
 
-        // // dbg!(emit_env.parse_tailwind_str("mask--100"));
-        // // dbg!(emit_env.parse_tailwind_str("text-red-200/30"));
-        // dbg!(emit_env.parse_tailwind_str(Some("tw-"), "tw-mask-y-from-100"));
-        // // dbg!(emit_env.to_css_stylesheet(false));
-        // return;
+fn compile_options(cli: &CompileArgs) -> CompileOptions {
+    CompileOptions {
+        prefix: cli.prefix.clone(),
+        // with @scope the env scopes the whole stylesheet, see `EmitOptions::scope_at_rule`
+        scope_selector: cli
+            .scope
+            .clone()
+            .filter(|_| !cli.scope_at_rule.unwrap_or_default()),
+        strict: cli.strict.unwrap_or_default(),
+        low_specificity: cli.low_specificity.unwrap_or_default(),
+        ..Default::default()
+    }
+}
+
+struct Input {
+    name: String,
+    src: String,
+    /// the part of `src` that gets scanned, blanked out where it can't contain classes but with
+    /// the same byte offsets
+    text: String,
+}
 
-        for config_to_load in &cli.config {
-            let config_src = std::fs::read_to_string(config_to_load.as_str())
-                .expect(&format!("couldn't load config {config_to_load}"));
-            emit_env.load_config(&config_src);
+/// the files `read_inputs` reads, with the content globs expanded
+fn input_names(cli: &CompileArgs) -> impl Iterator<Item = String> + '_ {
+    cli.in_file.iter().flat_map(|file_name| {
+        if has_glob_chars(file_name) {
+            expand_glob(file_name)
+        } else {
+            vec![file_name.clone()]
         }
+    })
+}
 
-        let txt = if cli.from_string {
-            cli.in_file.clone()
+fn read_inputs(cli: &CompileArgs) -> Vec<Input> {
+    let mut inputs = input_names(cli)
+        .map(|file_name| {
+            let src = std::fs::read_to_string(&file_name).expect("Could not read input file");
+            read_input(file_name, src)
+        })
+        .collect::<Vec<_>>();
+    // every string needs its own name, sources are tracked by it
+    inputs.extend(cli.strings.iter().enumerate().map(|(i, txt)| Input {
+        name: if cli.strings.len() == 1 {
+            "<string>".to_string()
         } else {
-            cli.in_file
+            format!("<string {}>", i + 1)
+        },
+        src: txt.clone(),
+        text: txt.clone(),
+    }));
+    inputs
+}
+
+fn read_input(name: String, src: String) -> Input {
+    let text = if name.ends_with(".md") || name.ends_with(".mdx") {
+        extract_markdown(&src)
+    } else {
+        src.clone()
+    };
+    Input { name, src, text }
+}
+
+/// the id a file is tracked under in watch mode, the same for the paths notify reports
+fn source_id(path: &Path) -> String {
+    path.canonicalize()
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// the watcher for `--watch-backend`, polling if `--watch-poll` is given. with the auto backend
+/// a native watcher that can't be created or can't watch one of the paths falls back to polling.
+/// also returns whether it polls
+fn start_watcher(
+    cli: &Args,
+    tx: &mpsc::Sender<Result<Event>>,
+    paths: &[PathBuf],
+) -> Result<(Box<dyn Watcher>, bool)> {
+    let backend = match (cli.watch_backend, cli.watch_poll) {
+        (None, Some(_)) => WatchBackend::Poll,
+        (backend, _) => backend.unwrap_or_default(),
+    };
+    let poll_interval = cli.watch_poll.unwrap_or(DEFAULT_POLL_INTERVAL);
+    if backend != WatchBackend::Poll {
+        let native = notify::recommended_watcher(tx.clone()).and_then(|mut watcher| {
+            for path in paths {
+                watcher.watch(path, RecursiveMode::Recursive)?;
+            }
+            Ok(watcher)
+        });
+        match native {
+            Ok(watcher) => return Ok((Box::new(watcher), false)),
+            Err(e) if backend == WatchBackend::Native => return Err(e),
+            Err(e) => tracing::warn!(
+                "native file watching failed ({e}), polling every {}ms instead",
+                poll_interval.as_millis()
+            ),
+        }
+    }
+    Ok((poll_watcher(tx, paths, poll_interval)?, true))
+}
+
+fn poll_watcher(
+    tx: &mpsc::Sender<Result<Event>>,
+    paths: &[PathBuf],
+    interval: Duration,
+) -> Result<Box<dyn Watcher>> {
+    let mut watcher = notify::PollWatcher::new(
+        tx.clone(),
+        notify::Config::default().with_poll_interval(interval),
+    )?;
+    for path in paths {
+        watcher.watch(path, RecursiveMode::Recursive)?;
+    }
+    Ok(Box::new(watcher))
+}
+
+/// a class set handed over by an external scanner (`--candidates`), or the classes a build found
+/// (`--write-candidates`)
+#[derive(Serialize, Deserialize, Debug, Default)]
+struct CandidateManifest {
+    #[serde(default)]
+    candidates: BTreeSet<String>,
+    /// file -> the candidates in it
+    #[serde(default)]
+    sources: BTreeMap<String, BTreeSet<String>>,
+}
+
+fn compile_manifests(
+    cli: &CompileArgs,
+    emit_env: &mut EmitEnv,
+    mut found: Option<&mut CandidateManifest>,
+) -> usize {
+    let options = compile_options(cli);
+    let mut errors = 0;
+    for manifest_file in &cli.candidates {
+        let src = match std::fs::read_to_string(manifest_file) {
+            Ok(src) => src,
+            Err(e) => {
+                tracing::error!("couldn't load candidates {manifest_file}: {e}");
+                errors += 1;
+                continue;
+            }
+        };
+        let manifest = match serde_json::from_str::<CandidateManifest>(&src) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                tracing::error!("couldn't parse {manifest_file}: {e}");
+                errors += 1;
+                continue;
+            }
+        };
+
+        let sources = manifest.sources.iter().map(|(file, c)| (Some(file), c));
+        for (file, candidates) in std::iter::once((None, &manifest.candidates)).chain(sources) {
+            for candidate in candidates {
+                match emit_env.resolve_candidate(&options, candidate) {
+                    Some((_, consumed)) if consumed == candidate.len() => {
+                        if let Some(found) = found.as_deref_mut() {
+                            found.candidates.insert(candidate.clone());
+                            if let Some(file) = file {
+                                let in_file = found.sources.entry(file.clone()).or_default();
+                                in_file.insert(candidate.clone());
+                            }
+                        }
+                    }
+                    _ if cli.strict.unwrap_or_default() => {
+                        let hint = emit_env
+                            .unresolved_hint(candidate)
+                            .map(|hint| format!(", {hint}"))
+                            .unwrap_or_default();
+                        tracing::error!("unknown class `{candidate}` in {manifest_file}{hint}");
+                        errors += 1;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    errors
+}
+
+/// the env after scanning every input and the number of errors that were reported, with
+/// `track_sources` the inputs are scanned with `EmitEnv::parse_source` so they can be rescanned
+/// one by one. the classes that were found are added to `found`
+fn compile(
+    cli: &CompileArgs,
+    track_sources: bool,
+    mut found: Option<&mut CandidateManifest>,
+) -> (EmitEnv, usize) {
+    let (mut emit_env, mut errors) = load_env(cli);
+    let options = compile_options(cli);
+    for input in read_inputs(cli) {
+        let inst = Instant::now();
+        if cli.strict.unwrap_or_default() || found.is_some() {
+            let mut report = UsageReport::default();
+            emit_env.collect_usage(&options, &input.name, &input.text, &mut report);
+            if let Some(found) = found.as_deref_mut() {
+                found.candidates.extend(report.classes.keys().cloned());
+                let in_file = found.sources.entry(input.name.clone()).or_default();
+                in_file.extend(report.classes.keys().cloned());
+            }
+            let mut unresolved = report
+                .unresolved
                 .iter()
-                .map(|file_name| {
-                    std::fs::read_to_string(file_name).expect("Could not read input file")
-                })
-                .collect()
+                .chain(&report.unknown_values)
+                .filter(|_| cli.strict.unwrap_or_default())
+                .flat_map(|(word, locations)| locations.iter().map(move |l| (l.offset, word)))
+                .collect::<Vec<_>>();
+            unresolved.sort();
+            for (offset, word) in unresolved {
+                render_error(
+                    &input.name,
+                    &input.src,
+                    offset..offset + word.len(),
+                    &format!("unknown class `{word}`"),
+                    "doesn't resolve to a rule",
+                    emit_env.unresolved_hint(word).as_deref(),
+                );
+                errors += 1;
+            }
+        } else if track_sources {
+            emit_env.parse_source(&options, &source_id(Path::new(&input.name)), &input.text);
+        } else if cli.debug_comments.unwrap_or_default() || cli.source_map.unwrap_or_default() {
+            // only sources scanned by name know where their classes are
+            emit_env.parse_source(&options, &input.name, &input.text);
+        } else {
+            emit_env.parse_full_string(&options, &input.text);
+        }
+        tracing::debug!(
+            file = input.name,
+            elapsed_ms = inst.elapsed().as_millis(),
+            "scanned"
+        );
+    }
+    errors += compile_manifests(cli, &mut emit_env, found);
+    (emit_env, errors)
+}
+
+fn report_usage(format: ReportFormat, out: Option<&str>, cli: &CompileArgs) {
+    let (mut emit_env, _) = load_env(cli);
+    let options = compile_options(cli);
+    let mut report = UsageReport::default();
+    for input in read_inputs(cli) {
+        emit_env.collect_usage(&options, &input.name, &input.text, &mut report);
+    }
+
+    let rendered = match format {
+        ReportFormat::Json => report.to_json(),
+        ReportFormat::Html => report.to_html(),
+    };
+    if let Some(out) = out {
+        std::fs::write(out, rendered).expect("Could not write report file");
+    } else {
+        print!("{rendered}");
+    }
+}
+
+fn report_dynamic(cli: &CompileArgs) {
+    let (mut emit_env, _) = load_env(cli);
+    let options = compile_options(cli);
+    let mut found = 0;
+    for input in read_inputs(cli) {
+        let mut report = UsageReport::default();
+        emit_env.collect_usage(&options, &input.name, &input.text, &mut report);
+        let mut fragments = report
+            .dynamic
+            .iter()
+            .flat_map(|(fragment, locations)| locations.iter().map(move |l| (l.offset, fragment)))
+            .collect::<Vec<_>>();
+        fragments.sort();
+        for (offset, fragment) in fragments {
+            render_warning(
+                &input.name,
+                &input.src,
+                offset..offset + fragment.len(),
+                &format!("class starting with `{fragment}` is built at runtime"),
+                "cannot be statically generated",
+                Some("list the classes it can become in a --candidates manifest"),
+            );
+            found += 1;
+        }
+    }
+    tracing::info!(found, "dynamic classes");
+}
+
+/// collects the results of `duckwind doctor`
+#[derive(Default)]
+struct Checkup {
+    problems: usize,
+}
+
+impl Checkup {
+    fn ok(&self, what: &str) {
+        println!("ok       {what}");
+    }
+
+    fn problem(&mut self, what: &str, fix: &str) {
+        println!("problem  {what}\n         fix: {fix}");
+        self.problems += 1;
+    }
+}
+
+/// whether `out` can be written without changing it: the file opens for appending, or its
+/// directory exists and isn't read only
+fn is_writable(out: &Path) -> bool {
+    if out.exists() {
+        return std::fs::OpenOptions::new().append(true).open(out).is_ok();
+    }
+    let dir = match out.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    std::fs::metadata(dir)
+        .is_ok_and(|metadata| metadata.is_dir() && !metadata.permissions().readonly())
+}
+
+/// checks the settings, configs, inputs, output and watch setup, returns the number of problems
+fn doctor(cli: &Args, compile: &CompileArgs, settings_error: Option<&str>) -> usize {
+    let mut checkup = Checkup::default();
+
+    match settings_error {
+        Some(e) => checkup.problem(e, "fix the settings, unknown keys are rejected"),
+        None if Path::new(SETTINGS_FILE).exists() => checkup.ok(&format!("{SETTINGS_FILE} parses")),
+        None => checkup.ok(&format!(
+            "no {SETTINGS_FILE}, using the flags and Cargo.toml"
+        )),
+    }
+
+    let mut env = EmitEnv::new();
+    env.options.error_on_conflict = compile.error_on_conflict.unwrap_or_default();
+    for config in &compile.config {
+        match std::fs::read_to_string(config) {
+            Err(e) => checkup.problem(
+                &format!("config {config} can't be read: {e}"),
+                "check the path, it's relative to the working directory",
+            ),
+            Ok(src) => match env.try_load_named_config(config, &src) {
+                Ok(()) => checkup.ok(&format!("config {config} parses")),
+                Err(errors) => checkup.problem(
+                    &format!("config {config} can't be loaded: {}", errors[0]),
+                    &format!("run `duckwind -c {config}` to see every error with its location"),
+                ),
+            },
+        }
+    }
+    for diagnostic in &env.diagnostics {
+        checkup.problem(&diagnostic.to_string(), "fix or remove it in the config");
+    }
+    if let Some(preflight) = &compile.preflight
+        && let Err(e) = std::fs::metadata(preflight)
+    {
+        checkup.problem(
+            &format!("preflight {preflight} can't be read: {e}"),
+            "check the path or drop preflight to use the bundled one",
+        );
+    }
+    if compile.no_default_config.unwrap_or_default() && compile.config.is_empty() {
+        checkup.problem(
+            "no-default-config is set but no config is loaded, nothing can be generated",
+            "load a config with -c or drop no-default-config",
+        );
+    }
+
+    if compile.in_file.is_empty() && compile.strings.is_empty() && compile.candidates.is_empty() {
+        checkup.problem(
+            "no inputs to scan",
+            &format!(
+                "pass files or globs, or set content = [\"src/**/*.html\"] in {SETTINGS_FILE}"
+            ),
+        );
+    }
+    for input in &compile.in_file {
+        if has_glob_chars(input) {
+            match expand_glob(input).len() {
+                0 => checkup.problem(
+                    &format!("content glob {input} doesn't match any file"),
+                    "check the pattern, it's relative to the working directory and target, node_modules and .git are skipped",
+                ),
+                matches => checkup.ok(&format!("content glob {input} matches {matches} file(s)")),
+            }
+        } else if !Path::new(input).is_file() {
+            checkup.problem(&format!("input {input} doesn't exist"), "check the path");
+        }
+    }
+    for manifest in &compile.candidates {
+        let parsed = std::fs::read_to_string(manifest)
+            .map_err(|e| e.to_string())
+            .and_then(|src| {
+                serde_json::from_str::<CandidateManifest>(&src).map_err(|e| e.to_string())
+            });
+        match parsed {
+            Ok(_) => checkup.ok(&format!("candidates {manifest} parse")),
+            Err(e) => checkup.problem(
+                &format!("candidates {manifest} can't be loaded: {e}"),
+                "it has to be json like {\"candidates\": [...], \"sources\": {\"file\": [...]}}",
+            ),
+        }
+    }
+
+    if let Some(out) = &cli.out {
+        if is_writable(Path::new(out)) {
+            checkup.ok(&format!("output {out} is writable"));
+        } else {
+            checkup.problem(
+                &format!("output {out} can't be written"),
+                "create its directory or fix the permissions",
+            );
+        }
+        let out_id = source_id(Path::new(out));
+        if input_names(compile).any(|input| source_id(Path::new(&input)) == out_id) {
+            checkup.problem(
+                &format!("output {out} is also an input"),
+                "exclude the generated stylesheet from the content globs",
+            );
+        }
+    }
+
+    if !cli.watch.is_empty() {
+        if cli.out.is_none() {
+            checkup.problem(
+                "watch is set but there's no output file",
+                "pass -o or set out in the settings",
+            );
+        }
+        for watch in &cli.watch {
+            let path = if has_glob_chars(watch) {
+                glob_base(watch)
+            } else {
+                PathBuf::from(watch)
+            };
+            if !path.exists() {
+                checkup.problem(
+                    &format!("watched path {} doesn't exist", path.display()),
+                    "check the path",
+                );
+            }
+        }
+        let polls = cli.watch_backend == Some(WatchBackend::Poll)
+            || (cli.watch_backend.is_none() && cli.watch_poll.is_some());
+        if polls {
+            let interval = cli.watch_poll.unwrap_or(DEFAULT_POLL_INTERVAL);
+            checkup.ok(&format!(
+                "polling for changes every {}ms",
+                interval.as_millis()
+            ));
+        } else {
+            match notify::recommended_watcher(|_: Result<Event>| {}) {
+                Ok(mut watcher) => {
+                    match watcher.watch(Path::new("."), RecursiveMode::NonRecursive) {
+                        Ok(()) => checkup.ok("file watching is available"),
+                        Err(e) => checkup.problem(
+                            &format!("file watching doesn't work here: {e}"),
+                            "raise the inotify watch limit (fs.inotify.max_user_watches) or poll with --watch-poll 1s",
+                        ),
+                    }
+                }
+                Err(e) => checkup.problem(
+                    &format!("no file watching backend: {e}"),
+                    "poll with --watch-poll 1s or rebuild on changes yourself",
+                ),
+            }
+        }
+    }
+
+    if compile.scope.is_some() && compile.shadow_dom.unwrap_or_default() {
+        checkup.problem(
+            "scope and shadow-dom are both set, the scope replaces the :host selectors",
+            "a shadow root is already scoped, drop scope",
+        );
+    }
+    if checkup.problems == 0 {
+        println!("\neverything looks fine");
+    } else {
+        println!("\n{} problem(s) found", checkup.problems);
+    }
+    checkup.problems
+}
+
+fn diff(expected: &str, cli: &CompileArgs) {
+    let expected_src = std::fs::read_to_string(expected).expect("Could not read expected file");
+    let Some(expected_sheet) = NormalizedStylesheet::parse(&expected_src) else {
+        tracing::error!("couldn't parse {expected}");
+        std::process::exit(2);
+    };
+
+    let as_css = compile(cli, false, None)
+        .0
+        .to_css_stylesheet(!cli.no_preflight.unwrap_or_default());
+    let actual_sheet =
+        NormalizedStylesheet::parse(&as_css).expect("generated stylesheet should be parsable");
+
+    let differences = expected_sheet.diff(&actual_sheet);
+    for difference in differences.iter() {
+        println!("{difference}");
+    }
+
+    if differences.is_empty() {
+        println!("no differences");
+    } else {
+        println!(
+            "{} differences (- only in {expected}, + only in generated, ~ changed)",
+            differences.len()
+        );
+        std::process::exit(1);
+    }
+}
+
+fn inline(out: Option<&str>, cli: &CompileArgs) {
+    let [input] = read_inputs(cli)
+        .try_into()
+        .unwrap_or_else(|inputs: Vec<_>| {
+            tracing::error!("inline takes exactly one document, got {}", inputs.len());
+            std::process::exit(2);
+        });
+
+    let (mut emit_env, _) = load_env(cli);
+    // only the theme vars the document needs
+    emit_env.options.inline_vars = true;
+    emit_env.parse_full_string(&compile_options(cli), &input.text);
+
+    let css = emit_env.to_css_stylesheet(!cli.no_preflight.unwrap_or_default());
+    let html = inject_stylesheet(&input.src, &css);
+    if let Some(out) = out {
+        std::fs::write(out, html).expect("Could not write output file");
+    } else {
+        print!("{html}");
+    }
+}
+
+/// marks the end of a response in pipe mode
+const PIPE_END_MARKER: &str = "/* duckwind:end */";
+
+/// `{"candidates": ["p-4"]}` compiles the given classes, `{"content": "..."}` scans the text
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+struct PipeMessage {
+    #[serde(default)]
+    candidates: Vec<String>,
+    #[serde(default)]
+    content: Option<String>,
+}
+
+fn pipe(full: bool, cli: &CompileArgs) {
+    use std::io::{BufRead, Write};
+
+    let (mut emit_env, _) = compile(cli, false, None);
+    let options = compile_options(cli);
+    let mut stdout = std::io::stdout().lock();
+    let mut respond = |css: &str| {
+        writeln!(stdout, "{css}\n{PIPE_END_MARKER}")
+            .and_then(|_| stdout.flush())
+            .is_ok()
+    };
+
+    // the base stylesheet first, the responses only contain rules afterwards
+    if !respond(&emit_env.to_css_stylesheet(!cli.no_preflight.unwrap_or_default())) {
+        return;
+    }
+
+    for line in std::io::stdin().lock().lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        let start = emit_env.defs.len();
+
+        if line.trim_start().starts_with('{') {
+            match serde_json::from_str::<PipeMessage>(&line) {
+                Ok(message) => {
+                    emit_env
+                        .compile_classes(&options, message.candidates.iter().map(String::as_str));
+                    if let Some(content) = message.content {
+                        emit_env.parse_full_string(&options, &content);
+                    }
+                }
+                Err(e) => tracing::error!("invalid message: {e}"),
+            }
+        } else {
+            emit_env.compile_classes(&options, line.split_whitespace());
+        }
+
+        let css = if full {
+            emit_env.to_css_stylesheet(!cli.no_preflight.unwrap_or_default())
+        } else {
+            emit_env.defs_to_css_since(start)
         };
+        if !respond(&css) {
+            break;
+        }
+    }
+}
+
+/// `--format js`, the constructed stylesheet is `null` where there is no `CSSStyleSheet`, e.g.
+/// during server side rendering
+fn js_module(css: &str) -> String {
+    let css = serde_json::to_string(css).expect("strings serialize");
+    format!(
+        "export const css = {css};\n\
+         export const sheet = typeof CSSStyleSheet === \"function\" ? new CSSStyleSheet() : null;\n\
+         sheet?.replaceSync(css);\n\
+         export default sheet;\n"
+    )
+}
+
+/// the output, the hashed class names with `--hash-classes` and the source map of a stylesheet
+/// written to a file with `--source-map`
+fn render_output(
+    cli: &Args,
+    emit_env: &EmitEnv,
+) -> (String, Option<HashedClassNames>, Option<SourceMap>) {
+    let mut hashed_env;
+    let mut emit_env = emit_env;
+    let mut hashed = None;
+    if cli.hash_classes.is_some() {
+        hashed_env = emit_env.clone();
+        hashed = Some(hashed_env.hash_class_names());
+        emit_env = &hashed_env;
+    }
+
+    let stylesheet = || {
+        if cli.compile.only_theme_vars.unwrap_or_default() {
+            emit_env.to_theme_vars_stylesheet()
+        } else {
+            emit_env.to_css_stylesheet(!cli.compile.no_preflight.unwrap_or_default())
+        }
+    };
+    let mut source_map = None;
+    let rendered = match cli.format.unwrap_or_default() {
+        OutputFormat::Css => match &cli.out {
+            Some(out)
+                if cli.compile.source_map.unwrap_or_default()
+                    && !cli.compile.only_theme_vars.unwrap_or_default() =>
+            {
+                let file = Path::new(out)
+                    .file_name()
+                    .map_or_else(|| out.clone(), |name| name.to_string_lossy().into_owned());
+                let (mut css, map) = emit_env.to_css_stylesheet_with_source_map(
+                    !cli.compile.no_preflight.unwrap_or_default(),
+                    &file,
+                );
+                source_map = Some(map);
+                css.push_str(&format!("/*# sourceMappingURL={file}.map */\n"));
+                css
+            }
+            _ => stylesheet(),
+        },
+        OutputFormat::Json => emit_env.defs_to_json(),
+        OutputFormat::Js => js_module(&stylesheet()),
+        OutputFormat::Rust => rust_const(&stylesheet(), "STYLES"),
+    };
+    (rendered, hashed, source_map)
+}
+
+/// where `--out-dir` writes the stylesheet of `input`, `src/pages/a.html` -> `src/pages/a.css`
+fn split_output_path(out_dir: &Path, input: &str) -> PathBuf {
+    // `..` and roots would leave the directory
+    let relative = Path::new(input)
+        .components()
+        .filter(|component| matches!(component, Component::Normal(_)))
+        .collect::<PathBuf>();
+    out_dir.join(relative).with_extension("css")
+}
+
+/// an input and where `--out-dir` writes its stylesheet
+type SplitOutput = (String, PathBuf);
+
+/// the inputs with where `--out-dir` writes their stylesheets, and the inputs that can't be
+/// written because their stylesheet would overwrite the shared one or the one of an earlier
+/// input, like `a.htm` after `a.html`
+fn split_output_paths(
+    out_dir: &Path,
+    inputs: impl IntoIterator<Item = String>,
+) -> (Vec<SplitOutput>, Vec<SplitOutput>) {
+    let mut written = Vec::new();
+    let mut clashing = Vec::new();
+    let mut taken = HashSet::from([out_dir.join(SHARED_STYLESHEET)]);
+    for input in inputs {
+        let path = split_output_path(out_dir, &input);
+        if taken.insert(path.clone()) {
+            written.push((input, path));
+        } else {
+            clashing.push((input, path));
+        }
+    }
+    (written, clashing)
+}
+
+/// `--out-dir`: a stylesheet per input and the shared one, returns the number of errors
+fn write_split_output(cli: &Args, out_dir: &str) -> usize {
+    if cli.compile.strict.unwrap_or_default() {
+        tracing::warn!("strict mode doesn't track which input a class is from, ignoring it");
+    }
+    let mut compile_args = cli.compile.clone();
+    compile_args.strict = Some(false);
+    let (emit_env, errors) = compile(&compile_args, true, None);
+
+    let out_dir = Path::new(out_dir);
+    let write = |path: PathBuf, css: String| {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent).expect("Could not create output directory");
+        }
+        std::fs::write(&path, css).expect("Could not write output file");
+    };
+    write(
+        out_dir.join(SHARED_STYLESHEET),
+        emit_env.to_shared_stylesheet(!cli.compile.no_preflight.unwrap_or_default()),
+    );
+    let (written, clashing) = split_output_paths(out_dir, input_names(&cli.compile));
+    for (input, path) in &clashing {
+        tracing::error!(
+            "not writing the stylesheet of {input}, {} is already written for another input",
+            path.display()
+        );
+    }
+    for (input, path) in written {
+        let css = emit_env.to_source_stylesheet(&source_id(Path::new(&input)));
+        write(path, css);
+    }
+    errors + clashing.len()
+}
+
+fn dry_run(cli: &Args) {
+    let (emit_env, errors) = compile(&cli.compile, false, None);
+    let (as_css, _, _) = render_output(cli, &emit_env);
+    let metrics = emit_env.metrics(!cli.compile.no_preflight.unwrap_or_default());
+    if cli.stats {
+        eprintln!("{metrics}");
+    }
 
-        for txt in txt {
-            emit_env.parse_full_string(cli.prefix.as_deref(), txt.as_str());
+    let target = cli.out.as_deref().unwrap_or("stdout");
+    let (status, would_change) = match cli.out.as_ref().map(std::fs::read_to_string) {
+        None => ("", false),
+        Some(Ok(existing)) if existing == as_css => (" (unchanged)", false),
+        Some(Ok(_)) => (" (changed)", true),
+        Some(Err(_)) => (" (new file)", true),
+    };
+    println!(
+        "would write {target}: {} rules, {} bytes{status}",
+        metrics.rules,
+        as_css.len()
+    );
+
+    if would_change || errors > 0 {
+        std::process::exit(1);
+    }
+}
+
+/// logs go to stderr so they don't mix with a stylesheet written to stdout
+fn init_logging(verbose: u8, quiet: bool) {
+    let level = match (quiet, verbose) {
+        (true, _) => tracing::Level::ERROR,
+        (false, 0) => tracing::Level::INFO,
+        (false, 1) => tracing::Level::DEBUG,
+        (false, _) => tracing::Level::TRACE,
+    };
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_ansi(std::io::stderr().is_terminal())
+        .with_max_level(level)
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+fn main() -> Result<()> {
+    let mut cli = Args::parse();
+    init_logging(cli.verbose, cli.quiet);
+    let settings_error = match Settings::load() {
+        Ok(Some(settings)) => {
+            settings.apply_to_args(&mut cli);
+            None
+        }
+        Ok(None) => None,
+        // the doctor reports it with the rest
+        Err(e) if matches!(cli.command, Some(Command::Doctor { .. })) => Some(e),
+        Err(e) => {
+            tracing::error!("{e}");
+            std::process::exit(2);
         }
+    };
 
-        let as_css = emit_env.to_css_stylesheet(!cli.no_preflight);
+    match cli.command.as_ref() {
+        Some(Command::Diff { expected, compile }) => {
+            diff(expected, compile);
+            return Ok(());
+        }
+        Some(Command::Pipe { full, compile }) => {
+            pipe(*full, compile);
+            return Ok(());
+        }
+        Some(Command::Inline { out, compile }) => {
+            inline(out.as_deref(), compile);
+            return Ok(());
+        }
+        Some(Command::Report {
+            kind:
+                ReportKind::Usage {
+                    format,
+                    out,
+                    compile,
+                },
+        }) => {
+            report_usage(*format, out.as_deref(), compile);
+            return Ok(());
+        }
+        Some(Command::Report {
+            kind: ReportKind::Dynamic { compile },
+        }) => {
+            report_dynamic(compile);
+            return Ok(());
+        }
+        Some(Command::Doctor { compile }) => {
+            if doctor(&cli, compile, settings_error.as_deref()) > 0 {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    if cli.dry_run {
+        dry_run(&cli);
+        return Ok(());
+    }
+    if let Some(out_dir) = &cli.out_dir {
+        if write_split_output(&cli, out_dir) > 0 {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    let write = |emit_env: &EmitEnv| {
+        let (as_css, hashed, source_map) = render_output(&cli, emit_env);
+        if let (Some(manifest), Some(hashed)) = (&cli.hash_classes, hashed) {
+            std::fs::write(manifest, hashed.to_json()).expect("Could not write manifest file");
+        }
+        if let (Some(out), Some(source_map)) = (&cli.out, source_map) {
+            std::fs::write(format!("{out}.map"), source_map.to_json())
+                .expect("Could not write source map");
+        } else if cli.compile.source_map.unwrap_or_default()
+            && !cli.compile.only_theme_vars.unwrap_or_default()
+        {
+            tracing::warn!("source maps are only written next to a css output file");
+        }
+        if cli.stats {
+            eprintln!(
+                "{}",
+                emit_env.metrics(!cli.compile.no_preflight.unwrap_or_default())
+            );
+        }
 
         if let Some(out) = cli.out.as_ref() {
             std::fs::write(out, as_css).expect("Could not write output file");
@@ -87,36 +1744,208 @@ fn main() -> Result<()> {
         }
     };
 
-    if let Some(watch) = cli.watch.as_ref() {
-        if cli.out.is_none() {
-            println!("error: watch requires out file");
+    if !cli.watch.is_empty() {
+        let Some(out) = cli.out.as_ref() else {
+            tracing::error!("watch requires out file");
             return Ok(());
+        };
+        // strict mode reports every input again, so it always rebuilds
+        let incremental = !cli.compile.strict.unwrap_or_default();
+        let (mut emit_env, _) = compile(&cli.compile, incremental, None);
+        write(&emit_env);
+
+        let cwd = std::env::current_dir()?.canonicalize()?;
+        let out = Path::new(out).canonicalize()?;
+        let ignore = build_glob_set(
+            DEFAULT_IGNORES
+                .iter()
+                .copied()
+                .chain(cli.watch_ignore.iter().map(String::as_str)),
+        );
+        let mut watch_roots = Vec::new();
+        let mut watched_paths = Vec::new();
+        let mut watched_globs = GlobSetBuilder::new();
+        for watch in &cli.watch {
+            if has_glob_chars(watch) {
+                watch_roots.push(glob_base(watch));
+                watched_globs.add(build_glob(watch));
+            } else {
+                let path = Path::new(watch).canonicalize()?;
+                watch_roots.push(path.clone());
+                watched_paths.push(path);
+            }
         }
+        let watched_globs = watched_globs.build().expect("globs are valid");
         let (tx, rx) = mpsc::channel::<Result<Event>>();
-        let mut watcher = notify::recommended_watcher(tx)?;
-        watcher.watch(Path::new(watch.as_str()), RecursiveMode::Recursive)?;
-        run();
-        println!("Watching... (Ctrl+C to exit)");
+        // only held so it keeps watching
+        let (mut _watcher, mut polling) = start_watcher(&cli, &tx, &watch_roots)?;
+
+        // changes to inputs are rescanned on their own, anything else (configs) rebuilds
+        let input_paths = cli
+            .compile
+            .in_file
+            .iter()
+            .filter(|input| !has_glob_chars(input))
+            .map(|input| source_id(Path::new(input)))
+            .collect::<HashSet<_>>();
+        let input_globs = build_glob_set(
+            cli.compile
+                .in_file
+                .iter()
+                .map(String::as_str)
+                .filter(|input| has_glob_chars(input)),
+        );
+        let default_ignores = build_glob_set(DEFAULT_IGNORES.iter().copied());
+        let is_input = |path: &Path| {
+            let relative = path.strip_prefix(&cwd).unwrap_or(path);
+            input_paths.contains(&source_id(path))
+                || (input_globs.is_match(relative) && !is_ignored(&default_ignores, relative))
+        };
+
+        // notify reports absolute paths, globs are relative to the working directory
+        let is_relevant = |path: &Path| {
+            let relative = path.strip_prefix(&cwd).unwrap_or(path);
+            path != out
+                && !is_ignored(&ignore, relative)
+                && (watched_paths
+                    .iter()
+                    .any(|watched| path.starts_with(watched))
+                    || watched_globs.is_match(relative))
+        };
+
+        tracing::info!("Watching... (Ctrl+C to exit)");
         for evt in rx {
             match evt {
                 Ok(evt) => {
-                    if let EventKind::Modify(notify::event::ModifyKind::Data(DataChange::Content)) =
-                        evt.kind
-                    {
+                    let is_change = matches!(
+                        evt.kind,
+                        EventKind::Create(_)
+                            | EventKind::Remove(_)
+                            | EventKind::Modify(
+                                ModifyKind::Data(_)
+                                    | ModifyKind::Name(_)
+                                    // what the poll watcher reports for changed files
+                                    | ModifyKind::Metadata(MetadataKind::WriteTime)
+                            )
+                    );
+                    let changed = evt
+                        .paths
+                        .iter()
+                        .filter(|path| is_relevant(path))
+                        .collect::<Vec<_>>();
+                    if is_change && !changed.is_empty() {
                         let inst = Instant::now();
-                        run();
-                        println!("Recompiled in {}ms.", inst.elapsed().as_millis());
+                        if incremental && changed.iter().all(|path| is_input(path)) {
+                            let options = compile_options(&cli.compile);
+                            for path in changed {
+                                let file_id = source_id(path);
+                                if let Ok(src) = std::fs::read_to_string(path) {
+                                    let input = read_input(file_id.clone(), src);
+                                    emit_env.parse_source(&options, &file_id, &input.text);
+                                } else {
+                                    emit_env.retract_source(&file_id);
+                                }
+                            }
+                        } else {
+                            emit_env = compile(&cli.compile, incremental, None).0;
+                        }
+                        write(&emit_env);
+                        tracing::info!("Recompiled in {}ms.", inst.elapsed().as_millis());
                     }
                 }
+                Err(e) if polling || cli.watch_backend == Some(WatchBackend::Native) => {
+                    tracing::warn!("file watching error: {e}");
+                }
                 Err(e) => {
-                    eprintln!("error: {e:?}, exiting...");
-                    return Err(e);
+                    let interval = cli.watch_poll.unwrap_or(DEFAULT_POLL_INTERVAL);
+                    tracing::warn!(
+                        "file watching error ({e}), polling every {}ms from now on",
+                        interval.as_millis()
+                    );
+                    match poll_watcher(&tx, &watch_roots, interval) {
+                        Ok(poll) => {
+                            _watcher = poll;
+                            polling = true;
+                        }
+                        Err(e) => tracing::warn!("couldn't start polling either: {e}"),
+                    }
                 }
             }
         }
     } else {
-        run();
+        let mut found = cli
+            .write_candidates
+            .as_ref()
+            .map(|_| CandidateManifest::default());
+        let (emit_env, errors) = compile(&cli.compile, false, found.as_mut());
+        write(&emit_env);
+        if let (Some(manifest_file), Some(found)) = (&cli.write_candidates, found) {
+            let json = serde_json::to_string_pretty(&found).expect("manifests are serializable");
+            std::fs::write(manifest_file, json + "\n").expect("Could not write manifest file");
+        }
+        if errors > 0 {
+            std::process::exit(1);
+        }
     }
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use clap::Parser;
+    use duckwind::EmitEnv;
+
+    use std::path::Path;
+
+    use crate::{Args, CandidateManifest, compile_manifests, split_output_paths};
+
+    #[test]
+    fn test_broken_manifests() {
+        let dir = std::env::temp_dir().join(format!("duckwind-manifests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let invalid = dir.join("invalid.json");
+        std::fs::write(&invalid, "{\"candidates\": [").unwrap();
+        let valid = dir.join("valid.json");
+        std::fs::write(&valid, "{\"candidates\": [\"p-4\"]}").unwrap();
+
+        let args = Args::parse_from([
+            "duckwind",
+            "--candidates",
+            dir.join("missing.json").to_str().unwrap(),
+            "--candidates",
+            invalid.to_str().unwrap(),
+            "--candidates",
+            valid.to_str().unwrap(),
+        ]);
+        let mut env = EmitEnv::new_with_default_config();
+        let mut found = CandidateManifest::default();
+        // both broken manifests are reported, the valid one is still compiled
+        let errors = compile_manifests(&args.compile, &mut env, Some(&mut found));
+        std::fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(errors, 2);
+        assert!(found.candidates.contains("p-4"));
+    }
+
+    #[test]
+    fn test_split_output_paths() {
+        let inputs = ["pages/a.html", "shared.html", "pages/a.htm", "b.rs"];
+        let (written, clashing) = split_output_paths(
+            Path::new("out"),
+            inputs.iter().map(|input| input.to_string()),
+        );
+        let written = written
+            .iter()
+            .map(|(input, path)| (input.as_str(), path.to_str().unwrap()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            written,
+            [("pages/a.html", "out/pages/a.css"), ("b.rs", "out/b.css")]
+        );
+        let clashing = clashing
+            .iter()
+            .map(|(input, _)| input.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(clashing, ["shared.html", "pages/a.htm"]);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::flatten::{Item, parse_items};
+
+/// the offset of `part` in `css`, `part` has to be a slice of it
+fn offset_in(css: &str, part: &str) -> usize {
+    part.as_ptr() as usize - css.as_ptr() as usize
+}
+
+/// what's between the braces of a block, `source` starts with `prelude`
+fn block_body<'a>(source: &'a str, prelude: &str) -> &'a str {
+    let open = prelude.len()
+        + source[prelude.len()..]
+            .find('{')
+            .expect("blocks have a brace");
+    let body = &source[open + 1..];
+    body.strip_suffix('}').unwrap_or(body)
+}
+
+/// `@media x` or `@container x` with its whitespace collapsed, `None` for other preludes
+fn query(prelude: &str) -> Option<String> {
+    (prelude.starts_with("@media") || prelude.starts_with("@container"))
+        .then(|| prelude.split_whitespace().collect::<Vec<_>>().join(" "))
+}
+
+/// the query a top-level block is in and the block without it: `@media x { .a{} }` -> `.a{}`,
+/// `.a{ @media x { color: red } }` -> `.a{color: red}`
+fn split_query(item: &Item) -> Option<(String, String)> {
+    let Item::Block {
+        prelude,
+        items,
+        source,
+    } = item
+    else {
+        return None;
+    };
+    if let Some(query) = query(prelude) {
+        return Some((query, block_body(source, prelude).trim().to_string()));
+    }
+    match items.as_slice() {
+        [
+            Item::Block {
+                prelude: inner,
+                source: inner_source,
+                ..
+            },
+        ] if !prelude.starts_with('@') => Some((
+            query(inner)?,
+            format!(
+                "{prelude}{{\n{}\n}}",
+                block_body(inner_source, inner).trim()
+            ),
+        )),
+        _ => None,
+    }
+}
+
+/// a top-level item with the text in front of it
+struct Chunk<'a> {
+    /// whitespace and comments
+    gap: &'a str,
+    text: String,
+    query: Option<(String, String)>,
+}
+
+/// merges neighbouring rules in the same `@media` or `@container` query into one block of that
+/// query, `.a{ @media x { color: red } } .b{ @media x { top: 0 } }` ->
+/// `@media x { .a{color: red} .b{top: 0} }`. only neighbours are merged, so the order of the
+/// rules and with it the cascade stays the same. works inside `@layer` blocks too
+pub fn merge_media_queries(css: &str) -> String {
+    let mut chunks = Vec::new();
+    let mut previous_end = 0;
+    for item in parse_items(css) {
+        let whole = match &item {
+            Item::Declaration(declaration) => *declaration,
+            Item::Block { source, .. } => *source,
+        };
+        let start = offset_in(css, whole);
+        let text = match &item {
+            Item::Block {
+                prelude, source, ..
+            } if prelude.starts_with("@layer") => {
+                format!(
+                    "{prelude} {{\n{}}}",
+                    merge_media_queries(block_body(source, prelude))
+                )
+            }
+            _ => whole.to_string(),
+        };
+        chunks.push(Chunk {
+            gap: &css[previous_end..start],
+            text,
+            query: split_query(&item),
+        });
+        previous_end = start + whole.len();
+    }
+
+    let mut res = String::with_capacity(css.len());
+    for run in chunks.chunk_by(|a, b| {
+        a.query.is_some() && a.query.as_ref().map(|q| &q.0) == b.query.as_ref().map(|q| &q.0)
+    }) {
+        if run.len() == 1 {
+            res.push_str(run[0].gap);
+            res.push_str(&run[0].text);
+            continue;
+        }
+        let query = &run[0].query.as_ref().expect("only queries are merged").0;
+        res.push_str(if res.is_empty() { "" } else { "\n" });
+        res.push_str(&format!("{query} {{\n"));
+        for chunk in run {
+            // debug comments stay in front of their rule
+            let gap = chunk.gap.trim();
+            if !gap.is_empty() {
+                res.push_str(gap);
+                res.push('\n');
+            }
+            res.push_str(&chunk.query.as_ref().expect("only queries are merged").1);
+            res.push('\n');
+        }
+        res.push('}');
+    }
+    res.push_str(&css[previous_end..]);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv, media::merge_media_queries};
+
+    #[test]
+    fn test_merge_media_queries() {
+        assert_eq!(
+            merge_media_queries(
+                ".a{\n@media (width >= 48rem) {\ncolor: red;\n}\n}\n/* b */\n.b{\n@media (width >=  48rem) {\ntop: 0;\n}\n}\n.c{\ntop: 1px;\n}\n@media (width >= 48rem) {\n.d{ left: 0 }\n}\n"
+            ),
+            "@media (width >= 48rem) {\n.a{\ncolor: red;\n}\n/* b */\n.b{\ntop: 0;\n}\n}\n.c{\ntop: 1px;\n}\n@media (width >= 48rem) {\n.d{ left: 0 }\n}\n"
+        );
+        let layered = "@layer utilities {\n@container (width >= 24rem) {\n.a{}\n}\n@container (width >= 24rem) {\n.b{}\n}\n}\n";
+        assert_eq!(
+            merge_media_queries(layered),
+            "@layer utilities {\n@container (width >= 24rem) {\n.a{}\n.b{}\n}\n}\n"
+        );
+    }
+
+    #[test]
+    fn test_merged_media_stylesheet() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.merge_media_queries = true;
+        env.options.no_theme_vars = true;
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["md:p-4", "md:flex", "lg:grid", "md:hover:flex"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert_eq!(css.matches("@media (width >= 48rem)").count(), 2, "{css}");
+        assert!(css.contains("@media (width >= 48rem) {\n.md\\:flex{\n"));
+    }
+}
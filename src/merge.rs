@@ -0,0 +1,91 @@
+use std::collections::HashSet;
+
+use crate::{CssDef, EmitEnv, declaration_ranges, minify_css};
+
+/// the names of all declarations in a rule body, custom properties and nested blocks included
+fn declaration_names(body: &str) -> HashSet<&str> {
+    declaration_ranges(body)
+        .into_iter()
+        .filter_map(|range| Some(body[range].split_once(':')?.0.trim()))
+        .collect()
+}
+
+/// rules whose bodies only differ in whitespace, ordered like their first rule
+struct Group<'a> {
+    defs: Vec<&'a CssDef>,
+    body: String,
+    properties: HashSet<&'a str>,
+}
+
+/// `defs` in groups of rules with the same body, in the order of their first rule. a rule only
+/// joins an earlier group if none of the rules in between declare one of its properties, so
+/// moving it up doesn't change which rule wins
+pub(crate) fn merge_identical_rules<'a>(defs: &[&'a CssDef]) -> Vec<Vec<&'a CssDef>> {
+    let mut res: Vec<Group> = Vec::new();
+    for def in defs {
+        // in a block, so the last `;` is dropped like in nested blocks
+        let body = minify_css(&format!("x{{{}}}", def.body));
+        let properties = declaration_names(&def.body);
+        let mut target = None;
+        for (i, group) in res.iter().enumerate().rev() {
+            // scoped rules are scoped on their own, see `EmitEnv::rule_css`
+            if group.body == body
+                && group.defs[0].scope_selector.is_some() == def.scope_selector.is_some()
+            {
+                target = Some(i);
+                break;
+            }
+            if !group.properties.is_disjoint(&properties) {
+                break;
+            }
+        }
+        match target {
+            Some(i) => res[i].defs.push(def),
+            None => res.push(Group {
+                defs: vec![def],
+                body,
+                properties,
+            }),
+        }
+    }
+    res.into_iter().map(|group| group.defs).collect()
+}
+
+impl EmitEnv {
+    /// the rules of `defs` in order, merged with `EmitOptions::merge_rules`
+    pub(crate) fn defs_css(&self, defs: &[&CssDef]) -> String {
+        if self.options.merge_rules {
+            merge_identical_rules(defs)
+                .iter()
+                .map(|group| self.rule_css(group))
+                .collect()
+        } else {
+            defs.iter().map(|def| self.def_to_css(def)).collect()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{CompileOptions, EmitEnv};
+
+    #[test]
+    fn test_merge_rules() {
+        let stylesheet = |classes: &[&str]| {
+            let mut env = EmitEnv::new_with_default_config();
+            env.options.merge_rules = true;
+            env.options.no_theme_vars = true;
+            env.compile_classes(&CompileOptions::default(), classes.iter().copied());
+            env.to_css_stylesheet(false)
+        };
+        let css = stylesheet(&["flex", "p-4", "[display:flex]", "hover:flex"]);
+        assert!(css.contains(".flex,\n.\\[display\\:flex\\]{\n"), "{css}");
+        assert!(css.contains(".p-4{"));
+        // the body of `hover:flex` is nested, so it's different
+        assert!(css.contains("\n.hover\\:flex{"));
+
+        // `inline-flex` sets display too, `[display:flex]` can't be moved in front of it
+        let css = stylesheet(&["flex", "inline-flex", "[display:flex]"]);
+        assert!(css.contains(".flex{") && css.contains("\n.\\[display\\:flex\\]{"));
+    }
+}
@@ -0,0 +1,51 @@
+use std::fmt::Display;
+
+/// size numbers about a generated stylesheet, see `EmitEnv::metrics`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Metrics {
+    /// generated utility rules
+    pub rules: usize,
+    /// declarations in the generated utility rules, after resolving nesting
+    pub declarations: usize,
+    /// theme vars in `:root` and the named theme blocks
+    pub theme_vars_emitted: usize,
+    pub theme_vars_total: usize,
+    /// keyframes referenced by the generated rules
+    pub keyframes_used: usize,
+    pub keyframes_total: usize,
+    /// size of the preflight before scoping, 0 if it isn't included
+    pub preflight_bytes: usize,
+    pub output_bytes: usize,
+    /// size of the output after gzip at the default compression level
+    pub estimated_gzip_bytes: usize,
+}
+
+/// gzip header and trailer around the deflate stream
+const GZIP_OVERHEAD: usize = 18;
+
+pub fn estimate_gzip_size(src: &str) -> usize {
+    miniz_oxide::deflate::compress_to_vec(src.as_bytes(), 6).len() + GZIP_OVERHEAD
+}
+
+impl Display for Metrics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "rules: {}", self.rules)?;
+        writeln!(f, "declarations: {}", self.declarations)?;
+        writeln!(
+            f,
+            "theme vars: {} of {}",
+            self.theme_vars_emitted, self.theme_vars_total
+        )?;
+        writeln!(
+            f,
+            "keyframes: {} of {} used",
+            self.keyframes_used, self.keyframes_total
+        )?;
+        writeln!(f, "preflight: {} bytes", self.preflight_bytes)?;
+        write!(
+            f,
+            "size: {} bytes (~{} bytes gzipped)",
+            self.output_bytes, self.estimated_gzip_bytes
+        )
+    }
+}
@@ -0,0 +1,91 @@
+/// characters whitespace around can be dropped without changing what the css means. `+`, `-`
+/// and `(` are missing on purpose: `calc(a + b)` and `and (...)` need their spaces
+const TIGHT_CHARS: &[char] = &['{', '}', ';', ',', '>'];
+
+/// whether a space after the end of `res` can be dropped, `: ` in declarations and `>= ` in
+/// media queries included
+fn is_tight_end(res: &str) -> bool {
+    res.ends_with(TIGHT_CHARS) || res.ends_with([':', '=']) && !res.ends_with(" =")
+}
+
+/// strips comments and whitespace that isn't needed, and the last `;` in every block
+pub fn minify_css(css: &str) -> String {
+    let mut res = String::with_capacity(css.len());
+    let mut chars = css.chars().peekable();
+    let mut pending_space = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '"' | '\'' => {
+                if std::mem::take(&mut pending_space) && !is_tight_end(&res) {
+                    res.push(' ');
+                }
+                res.push(c);
+                while let Some(quoted) = chars.next() {
+                    res.push(quoted);
+                    if quoted == '\\' {
+                        res.extend(chars.next());
+                    } else if quoted == c {
+                        break;
+                    }
+                }
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut previous = ' ';
+                for comment in chars.by_ref() {
+                    if previous == '*' && comment == '/' {
+                        break;
+                    }
+                    previous = comment;
+                }
+                pending_space = !res.is_empty();
+            }
+            c if c.is_whitespace() => pending_space = !res.is_empty(),
+            c => {
+                if std::mem::take(&mut pending_space)
+                    && !is_tight_end(&res)
+                    && !TIGHT_CHARS.contains(&c)
+                {
+                    res.push(' ');
+                }
+                if c == '}' && res.ends_with(';') {
+                    res.pop();
+                }
+                res.push(c);
+            }
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::minify::minify_css;
+
+    #[test]
+    fn test_minify_css() {
+        let css = r#"/* theme */
+:root {
+--spacing: 0.25rem;
+}
+.p-4 {
+    padding: calc(var(--spacing) * 4);
+}
+.sm\:flex {
+@media (width >= 40rem) and (hover: hover) {
+    display: flex;
+}
+}
+.a > .b, .c :where(h1) {
+    content: "  keep  ;  this  ";
+    font-family: ui-sans-serif, system-ui;
+}
+"#;
+        assert_eq!(
+            minify_css(css),
+            ":root{--spacing:0.25rem}.p-4{padding:calc(var(--spacing) * 4)}\
+             .sm\\:flex{@media (width>=40rem) and (hover:hover){display:flex}}\
+             .a>.b,.c :where(h1){content:\"  keep  ;  this  \";font-family:ui-sans-serif,system-ui}"
+        );
+    }
+}
@@ -18,7 +18,12 @@ pub enum ParsedUnit {
 #[derive(Debug, Clone, PartialEq)]
 pub struct Parsed {
     pub variants: Vec<Vec<Spanned<ParsedUnit>>>,
+    /// empty for variant groups
     pub utility: Vec<Spanned<ParsedUnit>>,
+    /// the members of a variant group like `hover:(bg-red-500,text-white)`
+    pub group: Option<Vec<String>>,
+    /// written with `!` in front of the utility or at the end, `hover:!p-4` / `hover:p-4!`
+    pub important: bool,
 }
 
 pub fn make_eoi(file_name: &'static str, file_contents: &'static str) -> DWS {
@@ -47,47 +52,96 @@ where
     I: BorrowInput<'a, Token = Token, Span = DWS>,
     M: Fn(DWS, &'a [Spanned<Token>]) -> I + Clone + 'static,
 {
-    (just(Token::Ctrl('-')).or_not().then(choice((
-        select_ref! { Token::Unit(i) => i.to_string() }.map(ParsedUnit::String),
-        select_ref! { Token::Raw(i) => i.to_string() }.map(ParsedUnit::Raw),
+    // the flag is set for values written like `(--brand)`
+    let segment = (just(Token::Ctrl('-')).or_not().then(choice((
+        select_ref! { Token::Unit(i) => i.to_string() }.map(|s| (ParsedUnit::String(s), false)),
+        select_ref! { Token::Raw(i) => i.to_string() }.map(|s| (ParsedUnit::Raw(s), false)),
+        select_ref! { Token::Var(i) => i.to_string() }.map(|s| (ParsedUnit::Raw(s), true)),
     ))))
-    .map_with(|x, e| {
-        (
-            match &x.1 {
-                ParsedUnit::String(s) => {
-                    ParsedUnit::String(format!("{}{s}", x.0.map(|_| "-").unwrap_or_default()))
-                }
-                ParsedUnit::Raw(s) => {
-                    ParsedUnit::Raw(format!("{}{s}", x.0.map(|_| "-").unwrap_or_default()))
-                }
-            },
-            e.span(),
-        )
+    .try_map(|(dash, (unit, is_var)), span| {
+        // `-(--x)` would be a negated var without a utility
+        if is_var && dash.is_some() {
+            return Err(Rich::custom(
+                span,
+                "a var shorthand needs a utility in front",
+            ));
+        }
+        let dash = dash.map(|_| "-").unwrap_or_default();
+        let unit = match unit {
+            ParsedUnit::String(s) => ParsedUnit::String(format!("{dash}{s}")),
+            ParsedUnit::Raw(s) => ParsedUnit::Raw(format!("{dash}{s}")),
+        };
+        Ok(((unit, span), is_var))
     })
+    // the name of `group-[.x]/sidebar` follows the raw value, it's a segment of its own
+    .then(
+        select_ref! { Token::Unit(i) if i.starts_with('/') => i.to_string() }
+            .map_with(|name, e| ((ParsedUnit::String(name), e.span()), false))
+            .or_not(),
+    )
+    .map(|(segment, name)| [Some(segment), name])
     .separated_by(just(Token::Ctrl('-')))
     .at_least(1)
     .collect::<Vec<_>>()
-    .separated_by(just(Token::Ctrl(':')))
-    .at_least(1)
-    .collect::<Vec<_>>()
-    .map(|x| Parsed {
-        variants: x[..x.len() - 1]
-            .iter()
-            .map(|x| {
-                // assert!(x.len() == 1, "variant may only consist of 1");
-                x.to_owned()
-            })
-            .collect(),
-        utility: {
-            let res = x.last().cloned().unwrap();
-            assert!(
-                res[..res.len() - 1]
-                    .iter()
-                    .all(|f| matches!(f.0, ParsedUnit::String(..))),
-                "only last may be raw"
-            );
-            res
-        },
-    })
-    .map_with(|x, e| (x, e.span()))
+    .try_map(|segments, span| {
+        let segments = segments.into_iter().flatten().flatten().collect::<Vec<_>>();
+        // `(--x)` on its own isn't a utility, only a value of one like `bg-(--x)`
+        if segments.first().is_some_and(|(_, is_var)| *is_var) {
+            return Err(Rich::custom(
+                span,
+                "a var shorthand needs a utility in front",
+            ));
+        }
+        Ok(segments
+            .into_iter()
+            .map(|(segment, _)| segment)
+            .collect::<Vec<_>>())
+    });
+
+    let bang = just(Token::Ctrl('!')).or_not().map(|bang| bang.is_some());
+
+    let group = segment
+        .clone()
+        .then_ignore(just(Token::Ctrl(':')))
+        .repeated()
+        .at_least(1)
+        .collect::<Vec<_>>()
+        .then(select_ref! { Token::Group(members) => members.clone() })
+        .then(bang.clone())
+        .map(|((variants, members), important)| Parsed {
+            variants,
+            utility: Vec::new(),
+            group: Some(members),
+            important,
+        });
+
+    let single = segment
+        .clone()
+        .then_ignore(just(Token::Ctrl(':')))
+        .repeated()
+        .collect::<Vec<_>>()
+        .then(bang.clone())
+        .then(segment.filter(|utility: &Vec<Spanned<ParsedUnit>>| {
+            // only last may be raw, or the one before a modifier like `bg-[#f00]/50`
+            let end = match utility.last() {
+                Some((ParsedUnit::String(modifier), _))
+                    if modifier.starts_with('/') && utility.len() > 1 =>
+                {
+                    utility.len() - 2
+                }
+                _ => utility.len() - 1,
+            };
+            utility[..end]
+                .iter()
+                .all(|f| matches!(f.0, ParsedUnit::String(..)))
+        }))
+        .then(bang)
+        .map(|(((variants, leading), utility), trailing)| Parsed {
+            variants,
+            utility,
+            group: None,
+            important: leading || trailing,
+        });
+
+    choice((group, single)).map_with(|x, e| (x, e.span()))
 }
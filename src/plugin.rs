@@ -0,0 +1,49 @@
+//! loading of `@plugin "path";` directives
+//!
+//! a plugin is a dynamic library exporting
+//!
+//! ```c
+//! const char *duckwind_plugin_config(void);
+//! ```
+//!
+//! which returns a nul terminated utf-8 string of duckwind config (`@utility`, `@custom-variant`,
+//! `@theme`, ...). the string is copied right away, so it only has to live until the call returns.
+//! requires the `plugins` cargo feature
+
+/// name of the symbol every plugin has to export
+pub const PLUGIN_ENTRY_POINT: &str = "duckwind_plugin_config";
+
+#[cfg(feature = "plugins")]
+pub fn load_plugin_config(path: &str) -> Result<String, String> {
+    use std::ffi::{CStr, c_char};
+
+    if path.ends_with(".wasm") {
+        return Err("wasm plugins are not supported, build the plugin as a dynamic library".into());
+    }
+
+    // SAFETY: loading a library runs its initializers, the user opted into this by
+    // referencing it with @plugin
+    let library = unsafe { libloading::Library::new(path) }.map_err(|e| e.to_string())?;
+    // SAFETY: the signature is the documented plugin abi
+    let entry_point = unsafe {
+        library.get::<unsafe extern "C" fn() -> *const c_char>(PLUGIN_ENTRY_POINT.as_bytes())
+    }
+    .map_err(|e| e.to_string())?;
+
+    // SAFETY: the plugin returns a nul terminated string that lives at least until we copied it
+    let config = unsafe {
+        let ptr = entry_point();
+        if ptr.is_null() {
+            return Err(format!("{PLUGIN_ENTRY_POINT} returned null"));
+        }
+        CStr::from_ptr(ptr).to_str().map(str::to_owned)
+    }
+    .map_err(|e| e.to_string())?;
+
+    Ok(config)
+}
+
+#[cfg(not(feature = "plugins"))]
+pub fn load_plugin_config(_path: &str) -> Result<String, String> {
+    Err("duckwind was built without the plugins feature".into())
+}
@@ -0,0 +1,239 @@
+/// emit order of properties, shorthands come before their longhands so the longhand wins when
+/// both are used, follows tailwind's property order
+const PROPERTY_ORDER: &[&str] = &[
+    "container-type",
+    "pointer-events",
+    "visibility",
+    "position",
+    "inset",
+    "inset-inline",
+    "inset-block",
+    "inset-inline-start",
+    "inset-inline-end",
+    "top",
+    "right",
+    "bottom",
+    "left",
+    "isolation",
+    "z-index",
+    "order",
+    "grid-column",
+    "grid-column-start",
+    "grid-column-end",
+    "grid-row",
+    "grid-row-start",
+    "grid-row-end",
+    "float",
+    "clear",
+    "margin",
+    "margin-inline",
+    "margin-block",
+    "margin-inline-start",
+    "margin-inline-end",
+    "margin-top",
+    "margin-right",
+    "margin-bottom",
+    "margin-left",
+    "box-sizing",
+    "display",
+    "field-sizing",
+    "aspect-ratio",
+    "height",
+    "max-height",
+    "min-height",
+    "width",
+    "max-width",
+    "min-width",
+    "flex",
+    "flex-shrink",
+    "flex-grow",
+    "flex-basis",
+    "table-layout",
+    "caption-side",
+    "border-collapse",
+    "border-spacing",
+    "transform-origin",
+    "translate",
+    "scale",
+    "rotate",
+    "transform",
+    "animation",
+    "cursor",
+    "touch-action",
+    "resize",
+    "scroll-snap-type",
+    "scroll-snap-align",
+    "scroll-snap-stop",
+    "scroll-margin",
+    "scroll-margin-inline",
+    "scroll-margin-block",
+    "scroll-margin-inline-start",
+    "scroll-margin-inline-end",
+    "scroll-margin-top",
+    "scroll-margin-right",
+    "scroll-margin-bottom",
+    "scroll-margin-left",
+    "scroll-padding",
+    "scroll-padding-inline",
+    "scroll-padding-block",
+    "scroll-padding-inline-start",
+    "scroll-padding-inline-end",
+    "scroll-padding-top",
+    "scroll-padding-right",
+    "scroll-padding-bottom",
+    "scroll-padding-left",
+    "list-style-position",
+    "list-style-type",
+    "list-style-image",
+    "appearance",
+    "columns",
+    "break-before",
+    "break-inside",
+    "break-after",
+    "grid-auto-columns",
+    "grid-auto-flow",
+    "grid-auto-rows",
+    "grid-template-columns",
+    "grid-template-rows",
+    "flex-direction",
+    "flex-wrap",
+    "place-content",
+    "place-items",
+    "align-content",
+    "align-items",
+    "justify-content",
+    "justify-items",
+    "gap",
+    "column-gap",
+    "row-gap",
+    "place-self",
+    "align-self",
+    "justify-self",
+    "overflow",
+    "overflow-x",
+    "overflow-y",
+    "overscroll-behavior",
+    "overscroll-behavior-x",
+    "overscroll-behavior-y",
+    "scroll-behavior",
+    "border-radius",
+    "border-start-start-radius",
+    "border-start-end-radius",
+    "border-end-end-radius",
+    "border-end-start-radius",
+    "border-top-left-radius",
+    "border-top-right-radius",
+    "border-bottom-right-radius",
+    "border-bottom-left-radius",
+    "border-width",
+    "border-inline-width",
+    "border-block-width",
+    "border-inline-start-width",
+    "border-inline-end-width",
+    "border-top-width",
+    "border-right-width",
+    "border-bottom-width",
+    "border-left-width",
+    "border-style",
+    "border-inline-style",
+    "border-block-style",
+    "border-top-style",
+    "border-right-style",
+    "border-bottom-style",
+    "border-left-style",
+    "border-color",
+    "border-inline-color",
+    "border-block-color",
+    "border-inline-start-color",
+    "border-inline-end-color",
+    "border-top-color",
+    "border-right-color",
+    "border-bottom-color",
+    "border-left-color",
+    "background-color",
+    "background-image",
+    "background-size",
+    "background-attachment",
+    "background-clip",
+    "background-position",
+    "background-repeat",
+    "background-origin",
+    "mask-image",
+    "mask-composite",
+    "mask-mode",
+    "mask-origin",
+    "mask-position",
+    "mask-repeat",
+    "mask-size",
+    "mask-type",
+    "fill",
+    "stroke",
+    "stroke-width",
+    "object-fit",
+    "object-position",
+    "padding",
+    "padding-inline",
+    "padding-block",
+    "padding-inline-start",
+    "padding-inline-end",
+    "padding-top",
+    "padding-right",
+    "padding-bottom",
+    "padding-left",
+    "text-align",
+    "text-indent",
+    "vertical-align",
+    "font-family",
+    "font-size",
+    "line-height",
+    "font-weight",
+    "letter-spacing",
+    "text-wrap",
+    "overflow-wrap",
+    "word-break",
+    "text-overflow",
+    "hyphens",
+    "white-space",
+    "color",
+    "text-transform",
+    "font-style",
+    "font-stretch",
+    "font-variant-numeric",
+    "text-decoration-line",
+    "text-decoration-color",
+    "text-decoration-style",
+    "text-decoration-thickness",
+    "text-underline-offset",
+    "-webkit-font-smoothing",
+    "caret-color",
+    "accent-color",
+    "color-scheme",
+    "opacity",
+    "background-blend-mode",
+    "mix-blend-mode",
+    "box-shadow",
+    "outline",
+    "outline-style",
+    "outline-width",
+    "outline-offset",
+    "outline-color",
+    "filter",
+    "backdrop-filter",
+    "transition-property",
+    "transition-behavior",
+    "transition-delay",
+    "transition-duration",
+    "transition-timing-function",
+    "will-change",
+    "contain",
+    "content",
+    "forced-color-adjust",
+];
+
+/// position of the property in the emit order, unknown properties go last
+pub fn property_rank(property: &str) -> usize {
+    PROPERTY_ORDER
+        .iter()
+        .position(|known| *known == property)
+        .unwrap_or(PROPERTY_ORDER.len())
+}
@@ -0,0 +1,177 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use serde::Serialize;
+
+/// 1-based position of a candidate in an input file
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+pub struct Location {
+    pub file: String,
+    pub line: usize,
+    pub column: usize,
+    /// byte offset into the input
+    #[serde(skip)]
+    pub offset: usize,
+}
+
+/// byte offset -> line/column lookup for one input
+pub struct LineIndex<'a> {
+    src: &'a str,
+    line_starts: Vec<usize>,
+}
+
+impl<'a> LineIndex<'a> {
+    pub fn new(src: &'a str) -> Self {
+        let line_starts = std::iter::once(0)
+            .chain(src.match_indices('\n').map(|(i, _)| i + 1))
+            .collect();
+        LineIndex { src, line_starts }
+    }
+
+    pub fn location(&self, file: &str, offset: usize) -> Location {
+        let line = self.line_starts.partition_point(|start| *start <= offset);
+        let line_start = self.line_starts[line - 1];
+        Location {
+            file: file.to_string(),
+            line,
+            column: self.src[line_start..offset].chars().count() + 1,
+            offset,
+        }
+    }
+}
+
+/// where the classes of a build were found, see `EmitEnv::collect_usage`
+#[derive(Debug, Clone, PartialEq, Default, Serialize)]
+pub struct UsageReport {
+    /// class as written in the source -> every place it was found
+    pub classes: BTreeMap<String, Vec<Location>>,
+    /// words that look like classes (contain `-` or `:`) but didn't resolve to a rule, in class
+    /// attributes or written like a class elsewhere
+    pub unresolved: BTreeMap<String, Vec<Location>>,
+    /// words for a utility that exists but not with that value, likely typos like `bg-red-510`.
+    /// they aren't in `unresolved`
+    pub unknown_values: BTreeMap<String, Vec<Location>>,
+    /// utility prefixes like `bg-` that are completed at runtime (`"bg-" + color`,
+    /// `` `bg-${color}` ``), the classes they end up as can't be generated
+    pub dynamic: BTreeMap<String, Vec<Location>>,
+}
+
+/// characters a class can't contain, a fragment starts after one of them
+fn is_fragment_boundary(c: char) -> bool {
+    c.is_whitespace() || "\"'`<>{}=,;(".contains(c)
+}
+
+/// offsets and text of the words ending in `-` that are followed by an interpolation or a
+/// concatenation: `${`, `{`, `#{`, `%s` or the end of a string and `+`
+pub(crate) fn dynamic_fragments(txt: &str) -> Vec<(usize, &str)> {
+    let mut res = Vec::new();
+    for (dash, _) in txt.match_indices('-') {
+        let after = &txt[dash + 1..];
+        let completed_later = ["${", "{", "#{", "%s"]
+            .iter()
+            .any(|marker| after.starts_with(marker))
+            || after.starts_with(['"', '\'', '`']) && after[1..].trim_start().starts_with('+');
+        if !completed_later {
+            continue;
+        }
+
+        let start = txt[..dash]
+            .char_indices()
+            .rev()
+            .find(|(_, c)| is_fragment_boundary(*c))
+            .map_or(0, |(i, c)| i + c.len_utf8());
+        let fragment = &txt[start..=dash];
+        if fragment.len() > 1 && !fragment.contains("--") {
+            res.push((start, fragment));
+        }
+    }
+    res
+}
+
+/// byte ranges of the quoted values of `class`, `className`, `:class` and `class:list`
+/// attributes, the words in them are meant as classes
+pub(crate) fn class_attribute_values(txt: &str) -> Vec<Range<usize>> {
+    let mut res = Vec::new();
+    for (start, _) in txt.match_indices("class") {
+        if !txt[..start]
+            .chars()
+            .next_back()
+            .is_none_or(|c| c.is_whitespace() || c == ':')
+        {
+            continue;
+        }
+        let after = &txt[start + "class".len()..];
+        let after = after
+            .strip_prefix("Name")
+            .or_else(|| after.strip_prefix(":list"))
+            .unwrap_or(after);
+        let Some(value) = after.trim_start().strip_prefix('=') else {
+            continue;
+        };
+        let value = value.trim_start();
+        let Some(quote) = value
+            .chars()
+            .next()
+            .filter(|c| matches!(c, '"' | '\'' | '`'))
+        else {
+            continue;
+        };
+        let value_start = txt.len() - value.len() + 1;
+        let value_end = txt[value_start..]
+            .find(quote)
+            .map_or(txt.len(), |len| value_start + len);
+        res.push(value_start..value_end);
+    }
+    res
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+impl UsageReport {
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports are serializable") + "\n"
+    }
+
+    fn usages_to_html(title: &str, usages: &BTreeMap<String, Vec<Location>>) -> String {
+        let mut res = format!(
+            "<h2>{title} ({})</h2>\n<table>\n<tr><th>class</th><th>count</th><th>locations</th></tr>\n",
+            usages.len()
+        );
+        for (class, locations) in usages {
+            let count = locations.len();
+            let locations = locations
+                .iter()
+                .map(|location| {
+                    html_escape(&format!(
+                        "{}:{}:{}",
+                        location.file, location.line, location.column
+                    ))
+                })
+                .collect::<Vec<_>>()
+                .join("<br>");
+            res.push_str(&format!(
+                "<tr><td><code>{}</code></td><td>{}</td><td>{locations}</td></tr>\n",
+                html_escape(class),
+                count
+            ));
+        }
+        res.push_str("</table>\n");
+        res
+    }
+
+    pub fn to_html(&self) -> String {
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>duckwind usage report</title>\n\
+             <style>body {{ font-family: sans-serif; }} td, th {{ text-align: left; vertical-align: top; padding: 0.25rem 1rem 0.25rem 0; }}</style>\n\
+             </head>\n<body>\n{}{}{}{}</body>\n</html>\n",
+            Self::usages_to_html("classes", &self.classes),
+            Self::usages_to_html("unresolved", &self.unresolved),
+            Self::usages_to_html("unknown values", &self.unknown_values),
+            Self::usages_to_html("built at runtime", &self.dynamic)
+        )
+    }
+}
@@ -0,0 +1,328 @@
+use crate::flatten::{Item, parse_items};
+
+/// at-rules whose blocks contain rules, the selectors in them get scoped too
+const GROUPING_AT_RULES: &[&str] = &["@media", "@supports", "@container", "@layer", "@scope"];
+
+/// selectors for the document root, they stand for the scope element itself
+const ROOT_SELECTORS: &[&str] = &["html", ":root", ":host"];
+
+/// the selectors of a selector list, split on top level commas and trimmed
+pub(crate) fn split_selector_list(selectors: &str) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut part_start = 0;
+    let mut paren_depth = 0usize;
+    for (i, c) in selectors.char_indices() {
+        match c {
+            '(' | '[' => paren_depth += 1,
+            ')' | ']' => paren_depth = paren_depth.saturating_sub(1),
+            ',' if paren_depth == 0 => {
+                parts.push(selectors[part_start..i].trim());
+                part_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    parts.push(selectors[part_start..].trim());
+    parts
+}
+
+/// maps every selector of a selector list, selectors that end up the same are only kept once
+fn map_selector_list(selectors: &str, f: impl Fn(&str) -> Vec<String>) -> String {
+    let mut res = Vec::new();
+    for mapped in split_selector_list(selectors).into_iter().flat_map(f) {
+        // `html, :host` would be the scope twice
+        if !res.contains(&mapped) {
+            res.push(mapped);
+        }
+    }
+    res.join(",\n")
+}
+
+/// calls `f` with the selector list of every rule in a stylesheet and replaces it with the
+/// result. rules in `@media` and the like are included, `@keyframes`, `@property` etc. and
+/// nested rules stay as they are
+fn map_rule_selectors(css: &str, f: impl Fn(&str) -> String) -> String {
+    let mut res = String::with_capacity(css.len() * 2);
+    // for every open block, whether it contains rules
+    let mut blocks = Vec::new();
+    let mut segment_start = 0;
+    let mut chars = css.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let in_rules = blocks.last().copied().unwrap_or(true);
+        match c {
+            '/' if css[i..].starts_with("/*") => {
+                let end = css[i + 2..].find("*/").map_or(css.len(), |end| i + end + 4);
+                while chars.peek().is_some_and(|(next, _)| *next < end) {
+                    chars.next();
+                }
+                // comments in front of a rule aren't part of its selector
+                if in_rules && css[segment_start..i].trim().is_empty() {
+                    res.push_str(&css[segment_start..end]);
+                    segment_start = end;
+                }
+            }
+            '"' | '\'' => {
+                let mut escaped = false;
+                for (_, quoted) in chars.by_ref() {
+                    match quoted {
+                        _ if escaped => escaped = false,
+                        '\\' => escaped = true,
+                        quoted if quoted == c => break,
+                        _ => {}
+                    }
+                }
+            }
+            '{' => {
+                let prelude = &css[segment_start..i];
+                let trimmed = prelude.trim();
+                if !in_rules {
+                    res.push_str(prelude);
+                    blocks.push(false);
+                } else if trimmed.starts_with('@') {
+                    res.push_str(prelude);
+                    let name = trimmed
+                        .split(|c: char| c.is_whitespace() || c == '(')
+                        .next()
+                        .unwrap_or_default();
+                    blocks.push(GROUPING_AT_RULES.contains(&name));
+                } else {
+                    let leading = &prelude[..prelude.len() - prelude.trim_start().len()];
+                    res.push_str(leading);
+                    res.push_str(&f(trimmed));
+                    res.push(' ');
+                    blocks.push(false);
+                }
+                res.push('{');
+                segment_start = i + 1;
+            }
+            '}' | ';' => {
+                res.push_str(&css[segment_start..=i]);
+                if c == '}' {
+                    blocks.pop();
+                }
+                segment_start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    res.push_str(&css[segment_start.min(css.len())..]);
+    res
+}
+
+/// nests every rule in a stylesheet under `scope`, so it only applies to the scope element
+/// and what's inside it, `html, .a > .b` -> `#app, #app .a > .b`
+pub fn scope_stylesheet(css: &str, scope: &str) -> String {
+    map_rule_selectors(css, |selectors| {
+        map_selector_list(selectors, |selector| {
+            if ROOT_SELECTORS.contains(&selector) {
+                vec![scope.to_string()]
+            } else {
+                vec![format!("{scope} {selector}")]
+            }
+        })
+    })
+}
+
+/// wraps the rules of a stylesheet in `@scope (scope) { ... }` instead of prefixing their
+/// selectors, the document root selectors become `:scope`. at-rules that can't be nested in
+/// it like `@keyframes` and `@property` stay outside
+pub fn scope_at_rule(css: &str, scope: &str) -> String {
+    let css = map_rule_selectors(css, |selectors| {
+        map_selector_list(selectors, |selector| {
+            if ROOT_SELECTORS.contains(&selector) {
+                vec![":scope".to_string()]
+            } else {
+                vec![selector.to_string()]
+            }
+        })
+    });
+
+    let mut res = String::with_capacity(css.len() + scope.len() + 12);
+    let mut scoped = String::new();
+    let flush = |res: &mut String, scoped: &mut String| {
+        let trimmed = scoped.trim_start();
+        if !trimmed.is_empty() {
+            res.push_str(&scoped[..scoped.len() - trimmed.len()]);
+            res.push_str(&format!("@scope ({scope}) {{\n{}\n}}", trimmed.trim_end()));
+        }
+        scoped.clear();
+    };
+    let mut previous_end = 0;
+    for item in parse_items(&css) {
+        let (whole, nestable) = match &item {
+            Item::Declaration(declaration) => (*declaration, false),
+            Item::Block {
+                prelude, source, ..
+            } => (
+                *source,
+                !prelude.starts_with('@')
+                    || GROUPING_AT_RULES
+                        .iter()
+                        .any(|at_rule| prelude.starts_with(at_rule)),
+            ),
+        };
+        let start = whole.as_ptr() as usize - css.as_ptr() as usize;
+        let mut end = start + whole.len();
+        // the `;` of statements like `@layer a, b;` isn't part of the declaration
+        if css[end..].starts_with(';') {
+            end += 1;
+        }
+        if nestable {
+            scoped.push_str(&css[previous_end..end]);
+        } else {
+            flush(&mut res, &mut scoped);
+            res.push_str(&css[previous_end..end]);
+        }
+        previous_end = end;
+    }
+    flush(&mut res, &mut scoped);
+    res.push_str(&css[previous_end..]);
+    res
+}
+
+/// `@property` rules are ignored in shadow roots, so the initial values they'd register are
+/// set on the host and everything in it instead
+fn property_fallbacks(css: &str) -> String {
+    let mut declarations = Vec::new();
+    let mut rest = css;
+    while let Some(start) = rest.find("@property ") {
+        rest = &rest[start + "@property ".len()..];
+        let Some((name, after_name)) = rest.split_once('{') else {
+            break;
+        };
+        let body = after_name.split('}').next().unwrap_or_default();
+        let initial_value = body.split(';').find_map(|declaration| {
+            let (property, value) = declaration.split_once(':')?;
+            (property.trim() == "initial-value").then(|| value.trim())
+        });
+        if let Some(initial_value) = initial_value {
+            declarations.push(format!("{}: {initial_value};\n", name.trim()));
+        }
+    }
+
+    if declarations.is_empty() {
+        String::new()
+    } else {
+        format!(
+            ":host, *, ::before, ::after, ::backdrop {{\n{}}}\n",
+            declarations.concat()
+        )
+    }
+}
+
+/// rewrites a stylesheet for a shadow root: the document root selectors become `:host`,
+/// lone attribute selectors like the ones of named themes also match the host, and the initial
+/// values of `@property` rules get fallbacks
+pub fn shadow_stylesheet(css: &str) -> String {
+    let mut res = map_rule_selectors(css, |selectors| {
+        map_selector_list(selectors, |selector| {
+            if ROOT_SELECTORS.contains(&selector) {
+                vec![":host".to_string()]
+            } else if selector.starts_with('[')
+                && selector.ends_with(']')
+                && selector.matches('[').count() == 1
+            {
+                vec![format!(":host({selector})"), selector.to_string()]
+            } else {
+                vec![selector.to_string()]
+            }
+        })
+    });
+    res.push_str(&property_fallbacks(css));
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::scoping::{scope_at_rule, scope_stylesheet, shadow_stylesheet};
+
+    #[test]
+    fn test_scope_stylesheet() {
+        let css = r#"/* 1. reset */
+*,
+::after {
+  margin: 0; /* 2 */
+}
+html, :host {
+  line-height: 1.5;
+}
+@supports (not (-webkit-appearance: -apple-pay-button)) /* Safari */ {
+  ::placeholder { color: red; }
+}
+@keyframes spin {
+  to { transform: rotate(360deg); }
+}
+.a:where([title="{x}"]) { content: "}"; }
+"#;
+        assert_eq!(
+            scope_stylesheet(css, "#app"),
+            r#"/* 1. reset */
+#app *,
+#app ::after {
+  margin: 0; /* 2 */
+}
+#app {
+  line-height: 1.5;
+}
+@supports (not (-webkit-appearance: -apple-pay-button)) /* Safari */ {
+  #app ::placeholder { color: red; }
+}
+@keyframes spin {
+  to { transform: rotate(360deg); }
+}
+#app .a:where([title="{x}"]) { content: "}"; }
+"#
+        );
+    }
+
+    #[test]
+    fn test_shadow_stylesheet() {
+        let css = r#"html,
+:host {
+  line-height: 1.5;
+}
+:root {
+--spacing: 0.25rem;
+}
+[data-theme="dark"] {
+--color-bg: black;
+}
+@property --tw-border-style {
+  syntax: "*";
+  inherits: false;
+  initial-value: solid;
+}
+"#;
+        assert_eq!(
+            shadow_stylesheet(css),
+            r#":host {
+  line-height: 1.5;
+}
+:host {
+--spacing: 0.25rem;
+}
+:host([data-theme="dark"]),
+[data-theme="dark"] {
+--color-bg: black;
+}
+@property --tw-border-style {
+  syntax: "*";
+  inherits: false;
+  initial-value: solid;
+}
+:host, *, ::before, ::after, ::backdrop {
+--tw-border-style: solid;
+}
+"#
+        );
+    }
+
+    #[test]
+    fn test_scope_at_rule() {
+        let css = "@layer base, utilities;\n/* reset */\nhtml, .a { margin: 0; }\n@keyframes spin {\n  to { rotate: 1turn; }\n}\n@media (x) {\n  :root { top: 0; }\n}\n";
+        assert_eq!(
+            scope_at_rule(css, "#widget"),
+            "@layer base, utilities;\n@scope (#widget) {\n/* reset */\n:scope,\n.a { margin: 0; }\n}\n@keyframes spin {\n  to { rotate: 1turn; }\n}\n@scope (#widget) {\n@media (x) {\n  :scope { top: 0; }\n}\n}\n"
+        );
+    }
+}
@@ -0,0 +1,292 @@
+use serde::Serialize;
+
+use crate::{EmitEnv, minify_css, report::LineIndex};
+
+const BASE64_DIGITS: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// appends `value` as a base64 vlq like the `mappings` of a source map have them
+fn push_vlq(res: &mut String, value: i64) {
+    // the sign is the lowest bit
+    let mut rest = if value < 0 {
+        ((-value as u64) << 1) | 1
+    } else {
+        (value as u64) << 1
+    };
+    loop {
+        let mut digit = rest & 0b11111;
+        rest >>= 5;
+        if rest > 0 {
+            digit |= 0b100000;
+        }
+        res.push(BASE64_DIGITS[digit as usize] as char);
+        if rest == 0 {
+            break;
+        }
+    }
+}
+
+/// a rule of the stylesheet and where its class was found, lines and columns start at 0
+#[derive(Debug, Clone, PartialEq)]
+struct Mapping {
+    generated_line: usize,
+    generated_column: usize,
+    source: usize,
+    line: usize,
+    column: usize,
+}
+
+/// a version 3 source map from the rules of a stylesheet to the classes they were generated
+/// for, see `EmitEnv::to_css_stylesheet_with_source_map`
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct SourceMap {
+    /// the stylesheet the map is for
+    pub file: String,
+    /// the file ids passed to `EmitEnv::parse_source`
+    pub sources: Vec<String>,
+    mappings: Vec<Mapping>,
+}
+
+impl SourceMap {
+    fn encode_mappings(&self) -> String {
+        let mut res = String::new();
+        let mut line = 0;
+        let mut previous_column = 0;
+        let mut previous_source = 0;
+        let mut previous_line = 0;
+        let mut previous_source_column = 0;
+        for (i, mapping) in self.mappings.iter().enumerate() {
+            if i > 0 && mapping.generated_line == line {
+                res.push(',');
+            }
+            while line < mapping.generated_line {
+                res.push(';');
+                line += 1;
+                previous_column = 0;
+            }
+            for (value, previous) in [
+                (mapping.generated_column, &mut previous_column),
+                (mapping.source, &mut previous_source),
+                (mapping.line, &mut previous_line),
+                (mapping.column, &mut previous_source_column),
+            ] {
+                push_vlq(&mut res, value as i64 - *previous as i64);
+                *previous = value;
+            }
+        }
+        res
+    }
+
+    pub fn to_json(&self) -> String {
+        #[derive(Serialize)]
+        struct Json<'a> {
+            version: u8,
+            file: &'a str,
+            sources: &'a [String],
+            names: [&'a str; 0],
+            mappings: String,
+        }
+        let json = Json {
+            version: 3,
+            file: &self.file,
+            sources: &self.sources,
+            names: [],
+            mappings: self.encode_mappings(),
+        };
+        serde_json::to_string(&json).expect("source maps are serializable") + "\n"
+    }
+}
+
+/// the line of a rule that holds its own selector, comments and at-rules wrapped around it by
+/// flattening are skipped. without the `{` or `,` after it, a merged rule lists more selectors
+fn selector_line(rule: &str) -> Option<&str> {
+    rule.lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty() && !line.starts_with("/*") && !line.starts_with('@'))
+        .map(|line| line.trim_end_matches(['{', ',']).trim_end())
+}
+
+/// the offset of `selector` in `css` where it's a whole selector, at `from` or after it
+fn find_selector(css: &str, from: usize, selector: &str) -> Option<usize> {
+    css[from..]
+        .match_indices(selector)
+        .map(|(offset, _)| from + offset)
+        .find(|offset| {
+            let before = css[..*offset].chars().next_back();
+            let after = css[offset + selector.len()..].chars().next();
+            before.is_none_or(|c| matches!(c, '\n' | ' ' | ',' | '{' | '}' | ';'))
+                && after.is_none_or(|c| matches!(c, '\n' | ' ' | ',' | '{'))
+        })
+}
+
+impl EmitEnv {
+    /// `to_css_stylesheet` and a source map from its rules to where their classes were found.
+    /// only rules generated by `parse_source` know where they were found. `file` is the name of
+    /// the stylesheet.
+    ///
+    /// every rule is emitted on its own, then found in the stylesheet by its selector, so
+    /// nothing has to be written into the css to find it again. the rules are in the same order there, so the search goes on from the
+    /// previous one, and only starts over for the rules that merging moved
+    pub fn to_css_stylesheet_with_source_map(
+        &self,
+        with_preflight: bool,
+        file: &str,
+    ) -> (String, SourceMap) {
+        let css = self.to_css_stylesheet(with_preflight);
+
+        let mut res = SourceMap {
+            file: file.to_string(),
+            ..Default::default()
+        };
+        let line_index = LineIndex::new(&css);
+        let mut from = 0;
+        for css_def in self.sorted_defs(self.defs.iter()) {
+            let Some(origin) = &css_def.origin else {
+                continue;
+            };
+            let rule = self.def_to_css(css_def);
+            let Some(selector) = selector_line(&rule) else {
+                continue;
+            };
+            let selector = if self.options.minify {
+                minify_css(selector)
+            } else {
+                selector.to_string()
+            };
+            let Some(offset) =
+                find_selector(&css, from, &selector).or_else(|| find_selector(&css, 0, &selector))
+            else {
+                continue;
+            };
+            from = offset + selector.len();
+
+            let source = match res.sources.iter().position(|s| *s == origin.file) {
+                Some(source) => source,
+                None => {
+                    res.sources.push(origin.file.clone());
+                    res.sources.len() - 1
+                }
+            };
+            let generated = line_index.location(file, offset);
+            res.mappings.push(Mapping {
+                generated_line: generated.line - 1,
+                generated_column: generated.column - 1,
+                source,
+                line: origin.line - 1,
+                column: origin.column - 1,
+            });
+        }
+        res.mappings
+            .sort_by_key(|m| (m.generated_line, m.generated_column));
+        (css, res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CompileOptions, EmitEnv,
+        source_map::{Mapping, push_vlq},
+    };
+
+    #[test]
+    fn test_vlq() {
+        let mut res = String::new();
+        for value in [0, 1, -1, 15, 16, -17, 1000] {
+            push_vlq(&mut res, value);
+            res.push(' ');
+        }
+        assert_eq!(res, "A C D e gB jB w+B ");
+    }
+
+    #[test]
+    fn test_source_map() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        let options = CompileOptions::default();
+        env.parse_source(
+            &options,
+            "index.html",
+            "<div class=\"p-4\">\n  <b class=\"flex\">",
+        );
+        env.parse_source(&options, "a.html", "<i class=\"p-40 flex\">");
+        // no origin, but still has to be skipped over
+        env.compile_classes(&options, ["grid"]);
+
+        let (css, map) = env.to_css_stylesheet_with_source_map(false, "out.css");
+        assert_eq!(css, env.to_css_stylesheet(false));
+        assert_eq!(map.sources, ["index.html", "a.html"]);
+        let position = |selector: &str| {
+            let offset = css.find(selector).unwrap();
+            let line = css[..offset].matches('\n').count();
+            (
+                line,
+                offset - css[..offset].rfind('\n').map_or(0, |i| i + 1),
+            )
+        };
+        let mapped = |selector: &str| {
+            let (generated_line, generated_column) = position(selector);
+            map.mappings
+                .iter()
+                .find(|m| {
+                    (m.generated_line, m.generated_column) == (generated_line, generated_column)
+                })
+                .cloned()
+        };
+        assert_eq!(
+            mapped(".p-4{"),
+            Some(Mapping {
+                generated_line: position(".p-4{").0,
+                generated_column: 0,
+                source: 0,
+                line: 0,
+                column: 12,
+            })
+        );
+        assert_eq!(mapped(".flex{").map(|m| (m.source, m.line)), Some((0, 1)));
+        assert_eq!(mapped(".p-40{").map(|m| m.source), Some(1));
+        assert_eq!(mapped(".grid{"), None);
+        assert_eq!(map.mappings.len(), 3);
+
+        let json = map.to_json();
+        assert!(json.starts_with(
+            "{\"version\":3,\"file\":\"out.css\",\"sources\":[\"index.html\",\"a.html\"]"
+        ));
+
+        // minified it's all on one line
+        env.options.minify = true;
+        let (css, map) = env.to_css_stylesheet_with_source_map(false, "out.css");
+        assert_eq!(css, env.to_css_stylesheet(false));
+        assert_eq!(map.mappings.len(), 3);
+        assert!(!map.to_json().contains(';'));
+
+        // flattened, a rule nested in a media query is found where it ends up
+        env.options.minify = false;
+        env.options.flatten_nesting = true;
+        env.parse_source(&options, "b.html", "<i class=\"hover:flex\">");
+        let (css, map) = env.to_css_stylesheet_with_source_map(false, "out.css");
+        assert_eq!(css, env.to_css_stylesheet(false));
+        let offset = css.find(".hover\\:flex:where(:hover)").unwrap();
+        let line = css[..offset].matches('\n').count();
+        assert!(
+            map.mappings
+                .iter()
+                .any(|m| m.generated_line == line && m.source == 2)
+        );
+    }
+
+    #[test]
+    fn test_source_map_private_use_content() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        let options = CompileOptions::default();
+        env.parse_source(
+            &options,
+            "icons.html",
+            "<i class=\"before:content-['\u{e000}'] after:content-['\u{e001}\u{e000}'] p-4\">",
+        );
+        let (css, map) = env.to_css_stylesheet_with_source_map(false, "out.css");
+        assert_eq!(css, env.to_css_stylesheet(false));
+        assert!(css.contains("content: '\u{e000}';"), "{css}");
+        assert_eq!(map.mappings.len(), 3);
+    }
+}
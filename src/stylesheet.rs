@@ -0,0 +1,531 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+use chumsky::{
+    IterParser, Parser,
+    error::Rich,
+    extra,
+    prelude::{choice, just, none_of, recursive},
+};
+
+#[derive(Debug, Clone, PartialEq)]
+enum CssNode {
+    Declaration(String, String),
+    Block(String, Vec<CssNode>),
+    Statement(String),
+}
+
+fn quoted_string_parser<'a>()
+-> impl Parser<'a, &'a str, &'a str, extra::Err<Rich<'a, char>>> + Clone {
+    choice((
+        just('"')
+            .then(none_of("\"").repeated())
+            .then(just('"'))
+            .to_slice(),
+        just('\'')
+            .then(none_of("'").repeated())
+            .then(just('\''))
+            .to_slice(),
+    ))
+}
+
+/// everything up to the next `{`, `;` or `}` that isn't inside of parens or quotes
+fn prelude_parser<'a>() -> impl Parser<'a, &'a str, &'a str, extra::Err<Rich<'a, char>>> + Clone {
+    let parens = recursive(|parens| {
+        just('(')
+            .then(
+                choice((
+                    quoted_string_parser().ignored(),
+                    parens,
+                    none_of("()\"'").ignored(),
+                ))
+                .repeated(),
+            )
+            .then(just(')'))
+            .ignored()
+    });
+
+    choice((
+        quoted_string_parser().ignored(),
+        parens,
+        none_of("{};()\"'").ignored(),
+    ))
+    .repeated()
+    .at_least(1)
+    .to_slice()
+}
+
+fn css_nodes_parser<'a>()
+-> impl Parser<'a, &'a str, Vec<CssNode>, extra::Err<Rich<'a, char>>> + Clone {
+    recursive(|nodes| {
+        choice((
+            prelude_parser()
+                .then_ignore(just('{'))
+                .then(nodes)
+                .then_ignore(just('}'))
+                .map(|(prelude, children): (&str, Vec<CssNode>)| {
+                    CssNode::Block(prelude.trim().to_string(), children)
+                }),
+            prelude_parser()
+                .or_not()
+                .then_ignore(just(';'))
+                .map(|prelude| prelude_to_node(prelude.unwrap_or_default())),
+            prelude_parser()
+                .then_ignore(just('}').rewind())
+                .map(prelude_to_node),
+        ))
+        .padded()
+        .repeated()
+        .collect::<Vec<_>>()
+        .map(|nodes| {
+            nodes
+                .into_iter()
+                .filter(|node| !matches!(node, CssNode::Statement(s) if s.is_empty()))
+                .collect()
+        })
+    })
+}
+
+fn prelude_to_node(prelude: &str) -> CssNode {
+    let prelude = prelude.trim();
+    if prelude.starts_with('@') {
+        return CssNode::Statement(normalize_whitespace(prelude));
+    }
+
+    match prelude.split_once(':') {
+        Some((name, value)) => CssNode::Declaration(
+            name.trim().to_string(),
+            normalize_value(value.trim()).to_string(),
+        ),
+        None => CssNode::Statement(normalize_whitespace(prelude)),
+    }
+}
+
+fn strip_comments(src: &str) -> String {
+    let mut res = String::with_capacity(src.len());
+    let mut rest = src;
+    while let Some(start) = rest.find("/*") {
+        res.push_str(&rest[..start]);
+        match rest[start + 2..].find("*/") {
+            Some(end) => rest = &rest[start + 2 + end + 2..],
+            None => rest = "",
+        }
+    }
+    res.push_str(rest);
+    res
+}
+
+fn normalize_whitespace(s: &str) -> String {
+    s.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// canonical spacing for values: single spaces, `, ` between arguments and no padding inside of
+/// parens
+fn normalize_value(s: &str) -> String {
+    let s = normalize_whitespace(s);
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ',' => {
+                res.truncate(res.trim_end().len());
+                res.push_str(", ");
+                while chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+            }
+            '(' => {
+                res.push('(');
+                while chars.peek() == Some(&' ') {
+                    chars.next();
+                }
+            }
+            ')' => {
+                res.truncate(res.trim_end().len());
+                res.push(')');
+            }
+            _ => res.push(c),
+        }
+    }
+    res
+}
+
+/// splits on commas that aren't nested inside of parens, brackets or quotes
+fn split_top_level_commas(s: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut depth = 0;
+    let mut quote = None;
+    let mut current = String::new();
+    for c in s.chars() {
+        match (c, quote) {
+            ('"' | '\'', None) => quote = Some(c),
+            (c, Some(q)) if c == q => quote = None,
+            ('(' | '[', None) => depth += 1,
+            (')' | ']', None) => depth -= 1,
+            (',', None) if depth == 0 => {
+                res.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    res.push(current);
+    res.into_iter()
+        .map(|s| normalize_selector(&s))
+        .filter(|s| !s.is_empty())
+        .collect()
+}
+
+fn normalize_selector(s: &str) -> String {
+    let s = normalize_whitespace(s);
+    let mut res = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '>' | '+' | '~' => {
+                res.truncate(res.trim_end().len());
+                res.push_str(&format!(" {c} "));
+            }
+            ' ' if res.ends_with(' ') => {}
+            _ => res.push(c),
+        }
+    }
+    res.trim().to_string()
+}
+
+/// at-rules that only add a condition to the surrounding rule instead of containing rules of
+/// their own
+fn is_conditional_at_rule(prelude: &str) -> bool {
+    [
+        "@media",
+        "@supports",
+        "@container",
+        "@layer",
+        "@starting-style",
+        "@scope",
+        "@document",
+    ]
+    .iter()
+    .any(|at_rule| prelude.starts_with(at_rule))
+}
+
+/// identifies one flattened rule: the stack of at-rules it's nested in and its selector
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RuleKey {
+    pub at_rules: Vec<String>,
+    pub selector: String,
+}
+
+impl Display for RuleKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for at_rule in &self.at_rules {
+            write!(f, "{at_rule} > ")?;
+        }
+        if self.selector.is_empty() {
+            write!(f, "(no selector)")
+        } else {
+            write!(f, "{}", self.selector)
+        }
+    }
+}
+
+/// a stylesheet reduced to selector -> declarations, with nesting resolved and all formatting and
+/// ordering information dropped
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct NormalizedStylesheet {
+    pub rules: BTreeMap<RuleKey, BTreeMap<String, String>>,
+    pub statements: BTreeSet<String>,
+}
+
+impl NormalizedStylesheet {
+    pub fn parse(src: &str) -> Option<Self> {
+        let without_comments = strip_comments(src);
+        let nodes = css_nodes_parser()
+            .parse(without_comments.as_str())
+            .into_result()
+            .ok()?;
+
+        let mut res = NormalizedStylesheet::default();
+        res.add_nodes(&nodes, &[], &[]);
+        Some(res)
+    }
+
+    fn add_nodes(&mut self, nodes: &[CssNode], at_rules: &[String], selectors: &[String]) {
+        for node in nodes {
+            match node {
+                CssNode::Declaration(name, value) => {
+                    let keys = if selectors.is_empty() {
+                        vec![String::new()]
+                    } else {
+                        selectors.to_vec()
+                    };
+                    for selector in keys {
+                        self.rules
+                            .entry(RuleKey {
+                                at_rules: at_rules.to_vec(),
+                                selector,
+                            })
+                            .or_default()
+                            .insert(name.clone(), value.clone());
+                    }
+                }
+                CssNode::Statement(statement) => {
+                    let mut full = at_rules.to_vec();
+                    full.push(statement.clone());
+                    self.statements.insert(full.join(" > "));
+                }
+                CssNode::Block(prelude, children) => {
+                    let mut nested_at_rules = at_rules.to_vec();
+                    if prelude.starts_with('@') {
+                        nested_at_rules.push(normalize_value(prelude));
+                        if is_conditional_at_rule(prelude) {
+                            self.add_nodes(children, &nested_at_rules, selectors);
+                        } else {
+                            self.add_nodes(children, &nested_at_rules, &[]);
+                        }
+                    } else {
+                        let resolved = resolve_nested_selectors(selectors, prelude);
+                        self.add_nodes(children, at_rules, &resolved);
+                    }
+                }
+            }
+        }
+    }
+
+    /// renders the stylesheet with sorted statements, at-rules, selectors and declarations and
+    /// two space indentation, the output is stable as long as the semantics don't change
+    pub fn to_css(&self) -> String {
+        let mut res = String::new();
+        for statement in self.statements.iter() {
+            res.push_str(statement);
+            res.push_str(";\n");
+        }
+
+        let mut current_at_rules: Option<&Vec<String>> = None;
+        for (key, declarations) in self.rules.iter() {
+            if current_at_rules != Some(&key.at_rules) {
+                if let Some(at_rules) = current_at_rules {
+                    for depth in (0..at_rules.len()).rev() {
+                        res.push_str(&format!("{}}}\n", "  ".repeat(depth)));
+                    }
+                }
+                for (depth, at_rule) in key.at_rules.iter().enumerate() {
+                    res.push_str(&format!("{}{at_rule} {{\n", "  ".repeat(depth)));
+                }
+                current_at_rules = Some(&key.at_rules);
+            }
+
+            let mut indent = "  ".repeat(key.at_rules.len());
+            if !key.selector.is_empty() {
+                res.push_str(&format!("{indent}{} {{\n", key.selector));
+                indent.push_str("  ");
+            }
+            for (property, value) in declarations {
+                res.push_str(&format!("{indent}{property}: {value};\n"));
+            }
+            if !key.selector.is_empty() {
+                res.push_str(&format!("{}}}\n", "  ".repeat(key.at_rules.len())));
+            }
+        }
+        if let Some(at_rules) = current_at_rules {
+            for depth in (0..at_rules.len()).rev() {
+                res.push_str(&format!("{}}}\n", "  ".repeat(depth)));
+            }
+        }
+        res
+    }
+
+    pub fn diff(&self, actual: &NormalizedStylesheet) -> Vec<StylesheetDifference> {
+        let mut res = Vec::new();
+        for (key, expected_decls) in self.rules.iter() {
+            let Some(actual_decls) = actual.rules.get(key) else {
+                res.push(StylesheetDifference::MissingRule(key.clone()));
+                continue;
+            };
+            for (property, expected_value) in expected_decls {
+                match actual_decls.get(property) {
+                    None => res.push(StylesheetDifference::MissingDeclaration {
+                        rule: key.clone(),
+                        property: property.clone(),
+                        expected: expected_value.clone(),
+                    }),
+                    Some(actual_value) if actual_value != expected_value => {
+                        res.push(StylesheetDifference::ChangedDeclaration {
+                            rule: key.clone(),
+                            property: property.clone(),
+                            expected: expected_value.clone(),
+                            actual: actual_value.clone(),
+                        })
+                    }
+                    _ => {}
+                }
+            }
+            for (property, actual_value) in actual_decls {
+                if !expected_decls.contains_key(property) {
+                    res.push(StylesheetDifference::ExtraDeclaration {
+                        rule: key.clone(),
+                        property: property.clone(),
+                        actual: actual_value.clone(),
+                    });
+                }
+            }
+        }
+        for key in actual.rules.keys() {
+            if !self.rules.contains_key(key) {
+                res.push(StylesheetDifference::ExtraRule(key.clone()));
+            }
+        }
+        for statement in self.statements.difference(&actual.statements) {
+            res.push(StylesheetDifference::MissingStatement(statement.clone()));
+        }
+        for statement in actual.statements.difference(&self.statements) {
+            res.push(StylesheetDifference::ExtraStatement(statement.clone()));
+        }
+        res
+    }
+}
+
+fn resolve_nested_selectors(parents: &[String], prelude: &str) -> Vec<String> {
+    let children = split_top_level_commas(prelude);
+    if parents.is_empty() {
+        return children;
+    }
+
+    let mut res = Vec::new();
+    for parent in parents {
+        for child in children.iter() {
+            if child.contains('&') {
+                res.push(child.replace('&', parent));
+            } else {
+                res.push(format!("{parent} {child}"));
+            }
+        }
+    }
+    res
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum StylesheetDifference {
+    MissingRule(RuleKey),
+    ExtraRule(RuleKey),
+    MissingDeclaration {
+        rule: RuleKey,
+        property: String,
+        expected: String,
+    },
+    ExtraDeclaration {
+        rule: RuleKey,
+        property: String,
+        actual: String,
+    },
+    ChangedDeclaration {
+        rule: RuleKey,
+        property: String,
+        expected: String,
+        actual: String,
+    },
+    MissingStatement(String),
+    ExtraStatement(String),
+}
+
+impl Display for StylesheetDifference {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StylesheetDifference::MissingRule(rule) => write!(f, "- {rule}"),
+            StylesheetDifference::ExtraRule(rule) => write!(f, "+ {rule}"),
+            StylesheetDifference::MissingDeclaration {
+                rule,
+                property,
+                expected,
+            } => write!(f, "- {rule} {{ {property}: {expected}; }}"),
+            StylesheetDifference::ExtraDeclaration {
+                rule,
+                property,
+                actual,
+            } => write!(f, "+ {rule} {{ {property}: {actual}; }}"),
+            StylesheetDifference::ChangedDeclaration {
+                rule,
+                property,
+                expected,
+                actual,
+            } => write!(f, "~ {rule} {{ {property}: {expected}; }} -> {actual}"),
+            StylesheetDifference::MissingStatement(statement) => write!(f, "- {statement};"),
+            StylesheetDifference::ExtraStatement(statement) => write!(f, "+ {statement};"),
+        }
+    }
+}
+
+/// compares two stylesheets semantically, returns `None` if either of them can't be parsed
+pub fn diff_stylesheets(expected: &str, actual: &str) -> Option<Vec<StylesheetDifference>> {
+    Some(NormalizedStylesheet::parse(expected)?.diff(&NormalizedStylesheet::parse(actual)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::stylesheet::{
+        NormalizedStylesheet, RuleKey, StylesheetDifference, diff_stylesheets,
+    };
+
+    #[test]
+    fn test_nesting_is_flattened() {
+        let sheet = NormalizedStylesheet::parse(
+            "/* comment */
+            .a, .b {
+                color: red;
+                &:hover { color: blue }
+                @media (width >= 40rem) {
+                    padding: calc( var(--spacing)*4 );
+                }
+            }",
+        )
+        .expect("should parse");
+
+        let hover = RuleKey {
+            at_rules: vec![],
+            selector: ".b:hover".to_string(),
+        };
+        let media = RuleKey {
+            at_rules: vec!["@media (width >= 40rem)".to_string()],
+            selector: ".a".to_string(),
+        };
+        assert_eq!(sheet.rules.len(), 6);
+        assert_eq!(sheet.rules[&hover]["color"], "blue");
+        assert_eq!(sheet.rules[&media]["padding"], "calc(var(--spacing)*4)");
+    }
+
+    #[test]
+    fn test_diff_ignores_formatting_and_order() {
+        let expected = ".a { color: red; margin: 0 } .b{padding:1px}";
+        let actual = ".b {\n  padding: 1px;\n}\n.a{margin:0;color:red;}";
+        assert_eq!(diff_stylesheets(expected, actual), Some(vec![]));
+
+        let diff = diff_stylesheets(expected, ".a { color: blue; margin: 0 }").unwrap();
+        assert_eq!(diff.len(), 2);
+        assert!(matches!(
+            &diff[0],
+            StylesheetDifference::ChangedDeclaration { actual, .. } if actual == "blue"
+        ));
+        assert!(matches!(&diff[1], StylesheetDifference::MissingRule(..)));
+    }
+
+    #[test]
+    fn test_to_css_is_canonical() {
+        let a = NormalizedStylesheet::parse(
+            "@media print { .b { color: red } } .a{margin:0;color : red} :root{--b:1;--a:2}",
+        )
+        .unwrap();
+        let b = NormalizedStylesheet::parse(
+            ":root { --a: 2; --b: 1 }\n.a { color: red; margin: 0; }\n@media print{.b{color:red;}}",
+        )
+        .unwrap();
+
+        assert_eq!(a.to_css(), b.to_css());
+        assert_eq!(
+            a.to_css(),
+            ".a {\n  color: red;\n  margin: 0;\n}\n:root {\n  --a: 2;\n  --b: 1;\n}\n\
+             @media print {\n  .b {\n    color: red;\n  }\n}\n"
+        );
+    }
+}
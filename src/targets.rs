@@ -0,0 +1,366 @@
+use std::{fmt::Display, str::FromStr};
+
+use crate::flatten::{Item, parse_items};
+
+/// a browser version like `15.4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Version(pub u32, pub u32);
+
+impl FromStr for Version {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (major, minor) = s.split_once('.').unwrap_or((s, "0"));
+        match (major.parse(), minor.parse()) {
+            (Ok(major), Ok(minor)) => Ok(Version(major, minor)),
+            _ => Err(format!("{s} isn't a version like 15.4")),
+        }
+    }
+}
+
+impl Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.0, self.1)
+    }
+}
+
+/// css the emitted stylesheets use that older browsers don't support, see `BrowserTargets`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// `.a { &:hover { ... } }`
+    Nesting,
+    /// `@media (width >= 48rem)`
+    MediaRanges,
+    /// `oklch()`, `oklab()`, `lch()` and `lab()`
+    ModernColors,
+}
+
+impl Feature {
+    /// the first chrome, firefox and safari versions that support it
+    fn supported_since(&self) -> [Version; 3] {
+        match self {
+            Feature::Nesting => [Version(112, 0), Version(117, 0), Version(16, 5)],
+            Feature::MediaRanges => [Version(104, 0), Version(63, 0), Version(16, 4)],
+            Feature::ModernColors => [Version(111, 0), Version(113, 0), Version(15, 4)],
+        }
+    }
+}
+
+/// the oldest browser versions the stylesheets have to work in, browsers that are `None` don't
+/// matter. parsed from a list like `chrome 100, firefox 115, safari 15.4`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BrowserTargets {
+    pub chrome: Option<Version>,
+    pub firefox: Option<Version>,
+    pub safari: Option<Version>,
+}
+
+impl BrowserTargets {
+    /// whether every target supports `feature`
+    pub fn supports(&self, feature: Feature) -> bool {
+        [self.chrome, self.firefox, self.safari]
+            .iter()
+            .zip(feature.supported_since())
+            .all(|(target, since)| target.is_none_or(|target| target >= since))
+    }
+}
+
+impl FromStr for BrowserTargets {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut res = BrowserTargets::default();
+        for target in s.split(',').map(str::trim).filter(|t| !t.is_empty()) {
+            let Some((browser, version)) = target.split_once(' ') else {
+                return Err(format!(
+                    "{target} isn't a browser and version like safari 15.4"
+                ));
+            };
+            let version = Some(version.trim().parse()?);
+            match browser {
+                "chrome" | "edge" => res.chrome = version,
+                "firefox" => res.firefox = version,
+                "safari" | "ios_saf" => res.safari = version,
+                _ => {
+                    return Err(format!(
+                        "unknown browser {browser}, the browsers are chrome, firefox and safari"
+                    ));
+                }
+            }
+        }
+        Ok(res)
+    }
+}
+
+/// `(width >= 48rem)` -> `(min-width: 48rem)` in the `@media` and `@container` preludes of
+/// `css`, for browsers without range syntax. a query that is only `(width < 48rem)` becomes
+/// `not all and (min-width: 48rem)`, or `not (min-width: 48rem)` for a container. in longer
+/// queries `<` and `>` move the value by `0.001` like `(max-width: 47.999rem)`. comparisons with
+/// values that can't be moved like `calc()` are left alone
+pub fn lower_media_ranges(css: &str) -> String {
+    let mut res = String::with_capacity(css.len());
+    let mut previous_end = 0;
+    lower_items(css, &parse_items(css), &mut res, &mut previous_end);
+    res.push_str(&css[previous_end..]);
+    res
+}
+
+/// copies `css` up to the preludes of the `@media` and `@container` blocks in `items` and their
+/// lowered version
+fn lower_items(css: &str, items: &[Item], res: &mut String, previous_end: &mut usize) {
+    for item in items {
+        let Item::Block { prelude, items, .. } = item else {
+            continue;
+        };
+        if let Some((at_rule, query)) = ["@media", "@container"]
+            .iter()
+            .find_map(|at_rule| Some((at_rule, prelude.strip_prefix(at_rule)?)))
+        {
+            let start = prelude.as_ptr() as usize - css.as_ptr() as usize;
+            res.push_str(&css[*previous_end..start]);
+            res.push_str(&format!(
+                "{at_rule} {}",
+                lower_query(query.trim(), *at_rule == "@container")
+            ));
+            *previous_end = start + prelude.len();
+        }
+        lower_items(css, items, res, previous_end);
+    }
+}
+
+/// how a feature compares to a value, `(48rem <= width)` is `width` `>=` `48rem`
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Comparison {
+    Less,
+    LessOrEqual,
+    Equal,
+    GreaterOrEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn parse(op: &str) -> Option<Self> {
+        Some(match op {
+            "<" => Comparison::Less,
+            "<=" => Comparison::LessOrEqual,
+            "=" => Comparison::Equal,
+            ">=" => Comparison::GreaterOrEqual,
+            ">" => Comparison::Greater,
+            _ => return None,
+        })
+    }
+
+    /// the comparison with the sides swapped, `48rem < width` is `width > 48rem`
+    fn flipped(self) -> Self {
+        match self {
+            Comparison::Less => Comparison::Greater,
+            Comparison::LessOrEqual => Comparison::GreaterOrEqual,
+            Comparison::Equal => Comparison::Equal,
+            Comparison::GreaterOrEqual => Comparison::LessOrEqual,
+            Comparison::Greater => Comparison::Less,
+        }
+    }
+}
+
+/// `width >= 48rem`, `40rem <= width < 64rem` -> the feature and what it's compared to
+fn parse_range(condition: &str) -> Option<(&str, Vec<(Comparison, &str)>)> {
+    let mut parts = Vec::new();
+    let mut rest = condition;
+    while let Some(op_start) = rest.find(['<', '>', '=']) {
+        let op_len = if rest[op_start + 1..].starts_with('=') {
+            2
+        } else {
+            1
+        };
+        parts.push(rest[..op_start].trim());
+        parts.push(&rest[op_start..op_start + op_len]);
+        rest = &rest[op_start + op_len..];
+    }
+    parts.push(rest.trim());
+
+    let is_feature = |s: &str| {
+        !s.is_empty()
+            && s.chars().all(|c| c.is_ascii_alphabetic() || c == '-')
+            && !s.starts_with('-')
+    };
+    match parts.as_slice() {
+        [feature, op, value] if is_feature(feature) => {
+            Some((feature, vec![(Comparison::parse(op)?, *value)]))
+        }
+        [value, op, feature] if is_feature(feature) => {
+            Some((feature, vec![(Comparison::parse(op)?.flipped(), *value)]))
+        }
+        [low, low_op, feature, high_op, high] if is_feature(feature) => Some((
+            feature,
+            vec![
+                (Comparison::parse(low_op)?.flipped(), *low),
+                (Comparison::parse(high_op)?, *high),
+            ],
+        )),
+        _ => None,
+    }
+}
+
+/// `48rem` moved by `delta` thousandths, `None` for values that aren't a number with a unit
+fn nudged(value: &str, delta: f64) -> Option<String> {
+    let number_end = value
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '-'))
+        .unwrap_or(value.len());
+    let number = value[..number_end].parse::<f64>().ok()?;
+    let unit = &value[number_end..];
+    if !unit.chars().all(|c| c.is_ascii_alphabetic() || c == '%') {
+        return None;
+    }
+    let nudged = ((number * 1000.0).round() + delta) / 1000.0;
+    Some(format!("{nudged}{unit}"))
+}
+
+/// the legacy conditions for a range condition, `None` if it can't be lowered
+fn lower_range(feature: &str, comparisons: &[(Comparison, &str)]) -> Option<String> {
+    let mut res = Vec::new();
+    for (comparison, value) in comparisons {
+        res.push(match comparison {
+            Comparison::GreaterOrEqual => format!("(min-{feature}: {value})"),
+            Comparison::LessOrEqual => format!("(max-{feature}: {value})"),
+            Comparison::Equal => format!("({feature}: {value})"),
+            Comparison::Greater => format!("(min-{feature}: {})", nudged(value, 1.0)?),
+            Comparison::Less => format!("(max-{feature}: {})", nudged(value, -1.0)?),
+        });
+    }
+    Some(res.join(" and "))
+}
+
+/// `container` queries have no media types, so their negation is `not (...)`
+fn lower_query(query: &str, container: bool) -> String {
+    // a lone exclusive comparison is the negation of an inclusive one, no value has to move
+    if let Some(condition) = query
+        .strip_prefix('(')
+        .and_then(|rest| rest.strip_suffix(')'))
+        .filter(|condition| !condition.contains(['(', ')']))
+        && let Some((feature, comparisons)) = parse_range(condition)
+        && let [(comparison, value)] = comparisons.as_slice()
+    {
+        let not = if container { "not" } else { "not all and" };
+        match comparison {
+            Comparison::Less => return format!("{not} (min-{feature}: {value})"),
+            Comparison::Greater => return format!("{not} (max-{feature}: {value})"),
+            _ => {}
+        }
+    }
+
+    // every condition in parentheses on its own, `screen and (width >= 40rem)`
+    let mut res = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(open) = rest.find('(') {
+        res.push_str(&rest[..open]);
+        let Some(close) = rest[open..].find(')').map(|close| open + close) else {
+            break;
+        };
+        let condition = &rest[open + 1..close];
+        let lowered = if condition.contains('(') {
+            None
+        } else {
+            parse_range(condition)
+                .and_then(|(feature, comparisons)| lower_range(feature, &comparisons))
+        };
+        match lowered {
+            Some(lowered) => res.push_str(&lowered),
+            None => res.push_str(&rest[open..=close]),
+        }
+        rest = &rest[close + 1..];
+    }
+    res.push_str(rest);
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        CompileOptions, EmitEnv,
+        targets::{BrowserTargets, Feature, Version, lower_media_ranges},
+    };
+
+    #[test]
+    fn test_browser_targets() {
+        let targets = "chrome 100, safari 15.4".parse::<BrowserTargets>().unwrap();
+        assert_eq!(targets.safari, Some(Version(15, 4)));
+        assert_eq!(targets.firefox, None);
+        assert!(!targets.supports(Feature::Nesting));
+        assert!(!targets.supports(Feature::MediaRanges));
+        assert!(!targets.supports(Feature::ModernColors));
+        assert!(BrowserTargets::default().supports(Feature::Nesting));
+        assert!(
+            "chrome 120, firefox 117"
+                .parse::<BrowserTargets>()
+                .unwrap()
+                .supports(Feature::Nesting)
+        );
+        assert!("netscape 4".parse::<BrowserTargets>().is_err());
+        assert!("chrome".parse::<BrowserTargets>().is_err());
+
+        assert_eq!(
+            lower_media_ranges(
+                "@media (width >= 48rem) {\n.a{\n@media (hover: hover) {}\n}\n}\n@media (width < 40rem) {}"
+            ),
+            "@media (min-width: 48rem) {\n.a{\n@media (hover: hover) {}\n}\n}\n@media not all and (min-width: 40rem) {}"
+        );
+        for (query, lowered) in [
+            ("(width > 40rem)", "not all and (max-width: 40rem)"),
+            ("(48rem <= width)", "(min-width: 48rem)"),
+            (
+                "(width >= 40rem) and (width < 64rem)",
+                "(min-width: 40rem) and (max-width: 63.999rem)",
+            ),
+            (
+                "screen and (40rem < width <= 64rem)",
+                "screen and (min-width: 40.001rem) and (max-width: 64rem)",
+            ),
+            (
+                "(height > 600px) and (hover: hover)",
+                "(min-height: 600.001px) and (hover: hover)",
+            ),
+            // values that can't be moved stay as they are
+            (
+                "(width > calc(1rem + 2px)) and (x: y)",
+                "(width > calc(1rem + 2px)) and (x: y)",
+            ),
+        ] {
+            assert_eq!(
+                lower_media_ranges(&format!("@media {query} {{}}")),
+                format!("@media {lowered} {{}}")
+            );
+        }
+        assert_eq!(
+            lower_media_ranges("@container sidebar (width >= 24rem) {}"),
+            "@container sidebar (min-width: 24rem) {}"
+        );
+        assert_eq!(
+            lower_media_ranges("@container (width < 48rem) {}"),
+            "@container not (min-width: 48rem) {}"
+        );
+    }
+
+    #[test]
+    fn test_lowered_stylesheet() {
+        let mut env = EmitEnv::new_with_default_config();
+        env.options.no_theme_vars = true;
+        env.options.targets = Some("safari 15".parse().unwrap());
+        env.compile_classes(
+            &CompileOptions::default(),
+            ["md:hover:flex", "max-[40rem]:p-4", "@md:p-2", "@max-md:p-2"],
+        );
+        let css = env.to_css_stylesheet(false);
+        assert!(
+            css.contains(
+                "@media (hover: hover) {\n@media (min-width: 48rem) {\n.md\\:hover\\:flex:where(:hover) {"
+            ),
+            "{css}"
+        );
+        assert!(css.contains("@media not all and (min-width: 40rem) {"));
+        assert!(css.contains("@container (min-width: 28rem) {"), "{css}");
+        assert!(css.contains("@container not (min-width: 28rem) {"), "{css}");
+        assert!(!css.contains("@container not all"), "{css}");
+        assert!(!css.contains('&'));
+        assert!(!css.contains(['<', '>']), "{css}");
+    }
+}